@@ -1,7 +1,7 @@
 #[cfg(feature = "release-health")]
 use sentry_types::protocol::v7::SessionStatus;
 
-use crate::protocol::{Event, Level};
+use crate::protocol::{Event, Feedback, Level, MonitorCheckInStatus};
 use crate::types::Uuid;
 use crate::{Hub, Integration, IntoBreadcrumbs, Scope};
 
@@ -63,6 +63,59 @@ pub fn capture_message(msg: &str, level: Level) -> Uuid {
     Hub::with_active(|hub| hub.capture_message(msg, level))
 }
 
+/// Captures a check-in for the cron monitor identified by `monitor_slug`.
+///
+/// The return value is the `check_in_id`, or the nil UUID if the check-in was
+/// discarded. To report the duration of a job, or to complete a check-in that
+/// was started as [`MonitorCheckInStatus::InProgress`], build a
+/// [`MonitorCheckIn`](crate::protocol::MonitorCheckIn) reusing that id and
+/// pass it to [`Client::capture_check_in`](crate::Client::capture_check_in).
+///
+/// # Examples
+///
+/// ```
+/// use sentry::protocol::{EnvelopeItem, MonitorCheckInStatus};
+///
+/// let envelopes = sentry::test::with_captured_envelopes(|| {
+///     sentry::capture_check_in("nightly-backup", MonitorCheckInStatus::InProgress);
+/// });
+/// let Some(EnvelopeItem::MonitorCheckIn(check_in)) = envelopes[0].items().next() else {
+///     panic!("expected a check-in");
+/// };
+/// assert_eq!(check_in.monitor_slug, "nightly-backup");
+/// ```
+pub fn capture_check_in(monitor_slug: &str, status: MonitorCheckInStatus) -> Uuid {
+    Hub::with_active(|hub| hub.capture_check_in(monitor_slug, status))
+}
+
+/// Captures feedback given by a user, for instance about a crash they ran into.
+///
+/// Setting the `associated_event_id` of the feedback to the id returned when
+/// capturing an event attaches the feedback to that event. The return value
+/// is the `event_id` of the feedback, or the nil UUID if it was discarded.
+///
+/// # Examples
+///
+/// ```
+/// use sentry::protocol::{EnvelopeItem, Feedback};
+///
+/// let envelopes = sentry::test::with_captured_envelopes(|| {
+///     let event_id = sentry::capture_message("Something went wrong", sentry::Level::Error);
+///     sentry::capture_feedback(Feedback {
+///         message: "It crashed when I clicked save".into(),
+///         associated_event_id: Some(event_id),
+///         ..Default::default()
+///     });
+/// });
+/// let Some(EnvelopeItem::Feedback(feedback)) = envelopes[1].items().next() else {
+///     panic!("expected feedback");
+/// };
+/// assert_eq!(feedback.associated_event_id, envelopes[0].uuid().copied());
+/// ```
+pub fn capture_feedback(feedback: Feedback) -> Uuid {
+    Hub::with_active(|hub| hub.capture_feedback(feedback))
+}
+
 /// Records a breadcrumb by calling a function.
 ///
 /// The total number of breadcrumbs that can be recorded are limited by the
@@ -13,13 +13,15 @@ use rand::random;
 use sentry_types::random_uuid;
 
 use crate::constants::SDK_INFO;
-use crate::protocol::{ClientSdkInfo, Event};
+use crate::protocol::{ClientSdkInfo, DataCategory, Event, Feedback, MonitorCheckIn};
 #[cfg(feature = "release-health")]
 use crate::session::SessionFlusher;
 use crate::types::{Dsn, Uuid};
 #[cfg(feature = "release-health")]
 use crate::SessionMode;
-use crate::{ClientOptions, Envelope, Hub, Integration, Scope, Transport};
+use crate::{
+    ClientOptions, DsnError, Envelope, Hub, Integration, Scope, Transport, TransportError,
+};
 #[cfg(feature = "UNSTABLE_logs")]
 use sentry_types::protocol::v7::{Log, LogAttribute};
 
@@ -116,7 +118,44 @@ impl Client {
     ///
     /// If the DSN on the options is set to `None` the client will be entirely
     /// disabled.
-    pub fn with_options(mut options: ClientOptions) -> Client {
+    ///
+    /// # Panics
+    ///
+    /// This panics if [`ClientOptions::require_dsn`] is set and the options are
+    /// not valid, see [`Client::try_with_options`].
+    pub fn with_options(options: ClientOptions) -> Client {
+        match Client::try_with_options(options) {
+            Ok(client) => client,
+            Err(err) => panic!("invalid sentry client options: {err}"),
+        }
+    }
+
+    /// Creates a new sentry client for the given options, failing if
+    /// [`ClientOptions::require_dsn`] is set and [`ClientOptions::validate`]
+    /// fails.
+    ///
+    /// Without `require_dsn`, this never fails and behaves like
+    /// [`Client::with_options`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sentry::{Client, ClientOptions, DsnError};
+    ///
+    /// let options = ClientOptions {
+    ///     require_dsn: true,
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(Client::try_with_options(options).err(), Some(DsnError::Missing));
+    /// ```
+    pub fn try_with_options(options: ClientOptions) -> Result<Client, DsnError> {
+        if options.require_dsn {
+            options.validate()?;
+        }
+        Ok(Client::new(options))
+    }
+
+    fn new(mut options: ClientOptions) -> Client {
         // Create the main hub eagerly to avoid problems with the background thread
         // See https://github.com/getsentry/sentry-rust/issues/237
         Hub::with(|_| {});
@@ -309,6 +348,36 @@ impl Client {
         Default::default()
     }
 
+    /// Captures a cron monitor check-in and sends it to Sentry.
+    ///
+    /// The check-in is sent with the environment configured on the client,
+    /// unless it specifies one itself. The return value is the `check_in_id`,
+    /// or the nil UUID if the check-in was discarded.
+    pub fn capture_check_in(&self, mut check_in: MonitorCheckIn) -> Uuid {
+        if let Some(ref transport) = *self.transport.read().unwrap() {
+            if check_in.environment.is_none() {
+                check_in.environment = self.options.environment.as_deref().map(Into::into);
+            }
+            let check_in_id = check_in.check_in_id;
+            transport.send_envelope(check_in.into());
+            return check_in_id;
+        }
+        Default::default()
+    }
+
+    /// Captures feedback given by a user and sends it to Sentry.
+    ///
+    /// The return value is the `event_id` of the feedback, or the nil UUID if
+    /// it was discarded.
+    pub fn capture_feedback(&self, feedback: Feedback) -> Uuid {
+        if let Some(ref transport) = *self.transport.read().unwrap() {
+            let event_id = feedback.event_id;
+            transport.send_envelope(feedback.into());
+            return event_id;
+        }
+        Default::default()
+    }
+
     /// Sends the specified [`Envelope`] to sentry.
     pub fn send_envelope(&self, envelope: Envelope) {
         if let Some(ref transport) = *self.transport.read().unwrap() {
@@ -324,6 +393,9 @@ impl Client {
     }
 
     /// Drains all pending events without shutting down.
+    ///
+    /// The aggregated sessions are handed to the transport first, so that
+    /// they are drained along with the events.
     pub fn flush(&self, timeout: Option<Duration>) -> bool {
         #[cfg(feature = "release-health")]
         if let Some(ref flusher) = *self.session_flusher.read().unwrap() {
@@ -336,9 +408,53 @@ impl Client {
         }
     }
 
+    /// Drains only the envelopes carrying items of `category` from the
+    /// transport, leaving the other ones queued.
+    ///
+    /// Like with [`Client::flush`], the timeout defaults to the `shutdown_timeout`.
+    /// See [`Transport::flush_category`].
+    pub fn flush_category(&self, category: DataCategory, timeout: Option<Duration>) -> bool {
+        if let Some(ref transport) = *self.transport.read().unwrap() {
+            transport.flush_category(category, timeout.unwrap_or(self.options.shutdown_timeout))
+        } else {
+            true
+        }
+    }
+
+    /// Returns the most recent failure of the transport to send an envelope.
+    ///
+    /// See [`Transport::last_error`].
+    pub fn last_transport_error(&self) -> Option<TransportError> {
+        self.transport.read().unwrap().as_ref()?.last_error()
+    }
+
+    /// Lifts all rate limits of the transport, so that events are sent again
+    /// right away.
+    ///
+    /// See [`Transport::clear_rate_limits`].
+    pub fn clear_rate_limits(&self) {
+        if let Some(ref transport) = *self.transport.read().unwrap() {
+            transport.clear_rate_limits();
+        }
+    }
+
+    /// Returns how long until the transport sends items of `category` again,
+    /// or [`None`] if it sends them now.
+    ///
+    /// See [`Transport::time_until_allowed`].
+    pub fn time_until_allowed(&self, category: DataCategory) -> Option<Duration> {
+        self.transport
+            .read()
+            .unwrap()
+            .as_ref()?
+            .time_until_allowed(category)
+    }
+
     /// Drains all pending events and shuts down the transport behind the
     /// client.  After shutting down the transport is removed.
     ///
+    /// Like with [`Client::flush`], the aggregated sessions are drained as well.
+    ///
     /// This returns `true` if the queue was successfully drained in the
     /// given time or `false` if not (for instance because of a timeout).
     /// If no timeout is provided the client will wait for as long a
@@ -1,5 +1,8 @@
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::fmt;
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -7,9 +10,12 @@ use crate::constants::USER_AGENT;
 use crate::performance::TracesSampler;
 #[cfg(feature = "UNSTABLE_logs")]
 use crate::protocol::Log;
-use crate::protocol::{Breadcrumb, Event};
+use crate::protocol::{Breadcrumb, DataCategory, Envelope, Event, Url};
 use crate::types::Dsn;
-use crate::{Integration, IntoDsn, TransportFactory};
+use crate::{
+    BodyTransform, DynamicSamplingContextFn, EnvelopeTimeoutFn, Integration, IntoDsn,
+    RateLimitCallback, TransportErrorResponseCallback, TransportFactory, TransportResultCallback,
+};
 
 /// Type alias for before event/breadcrumb handlers.
 pub type BeforeCallback<T> = Arc<dyn Fn(T) -> Option<T> + Send + Sync>;
@@ -45,6 +51,115 @@ pub enum SessionMode {
     Request,
 }
 
+/// What the transport does with a new envelope when its queue is full.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum QueueOverflowPolicy {
+    /// Drop the new envelope.
+    #[default]
+    DropNewest,
+    /// Drop the oldest queued envelope to make room for the new one.
+    DropOldest,
+}
+
+/// How the transport compresses envelope request bodies.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Compression {
+    /// Send envelopes uncompressed.
+    #[default]
+    None,
+    /// Compress envelopes with gzip, which requires the `gzip` feature.
+    Gzip,
+    /// Compress envelopes with brotli, which requires the `brotli` feature.
+    Br,
+}
+
+/// The reason why [`ClientOptions`] can't be used to send envelopes, see
+/// [`ClientOptions::validate`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DsnError {
+    /// No DSN is configured.
+    Missing,
+    /// The `endpoint_override` is not a valid HTTP(S) URL.
+    InvalidEndpointOverride(String),
+}
+
+impl fmt::Display for DsnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing => write!(f, "no DSN configured"),
+            Self::InvalidEndpointOverride(base) => write!(f, "invalid endpoint override `{base}`"),
+        }
+    }
+}
+
+impl std::error::Error for DsnError {}
+
+/// The TLS implementation the transport uses when several are compiled in.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum TlsBackend {
+    /// Use `rustls`, enabled by the `rustls` feature.
+    #[default]
+    Rustls,
+    /// Use the platform's TLS implementation, enabled by the `native-tls` feature.
+    NativeTls,
+}
+
+/// The IP address family the transport connects to Sentry with.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum AddressFamily {
+    /// Use any address the host name resolves to.
+    #[default]
+    Auto,
+    /// Only use IPv4 addresses.
+    Ipv4,
+    /// Only use IPv6 addresses.
+    Ipv6,
+}
+
+/// The HTTP version the transport should prefer for sending envelopes.
+///
+/// This is only a preference: a transport that doesn't support the preferred
+/// version falls back to HTTP/1.1. The `ureq` transport currently only speaks
+/// HTTP/1.1, so [`HttpVersion::Http2`] degrades to HTTP/1.1 there.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum HttpVersion {
+    /// HTTP/1.1.
+    #[default]
+    Http1_1,
+    /// HTTP/2, for multiplexing many envelopes over a single connection.
+    Http2,
+}
+
+/// How [`ClientOptions::user_agent`] is reported to Sentry.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum UserAgentMode {
+    /// Report the user agent as is.
+    #[default]
+    Override,
+    /// Append the SDK name and version to the user agent.
+    Append,
+}
+
+/// PEM encoded data, given either directly or as the path of a file containing it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PemSource {
+    /// The PEM encoded data itself.
+    Bytes(Vec<u8>),
+    /// The path of a file containing the PEM encoded data.
+    Path(PathBuf),
+}
+
+impl PemSource {
+    /// Returns the PEM encoded data, reading it from the file if necessary.
+    pub fn read(&self) -> std::io::Result<Cow<'_, [u8]>> {
+        match self {
+            PemSource::Bytes(bytes) => Ok(Cow::Borrowed(bytes)),
+            PemSource::Path(path) => std::fs::read(path).map(Cow::Owned),
+        }
+    }
+}
+
 /// The maximum size of an HTTP request body that the SDK captures.
 ///
 /// Only request bodies that parse as JSON or form data are currently captured.
@@ -96,6 +211,16 @@ pub struct ClientOptions {
     // Common options
     /// The DSN to use.  If not set the client is effectively disabled.
     pub dsn: Option<Dsn>,
+    /// Whether creating a client fails if envelopes can't be sent with these
+    /// options (defaults to `false`).
+    ///
+    /// By default, a client without a DSN is merely disabled. When this is
+    /// set, [`Client::try_with_options`](crate::Client::try_with_options)
+    /// returns the error of [`ClientOptions::validate`] instead, and
+    /// `Client::with_options` and `sentry::init` panic with it. Note that an
+    /// invalid `SENTRY_DSN` is ignored by `sentry::init`, so that it counts as
+    /// a missing DSN.
+    pub require_dsn: bool,
     /// Enables debug mode.
     ///
     /// In debug mode debug information is printed to stderr to help you understand what
@@ -108,9 +233,33 @@ pub struct ClientOptions {
     ///
     /// Defaults to either `"development"` or `"production"` depending on the
     /// `debug_assertions` cfg-attribute.
+    ///
+    /// The transport also sends it in the `X-Sentry-Environment` header of
+    /// every request, which is currently only honored by the `ureq` transport.
     pub environment: Option<Cow<'static, str>>,
+    /// The region the application is deployed in. (defaults to `None`)
+    ///
+    /// This is not added to events, but sent in the `X-Sentry-Region` header
+    /// of every request so that a relay can route on it. This is currently
+    /// only honored by the `ureq` transport.
+    pub region: Option<Cow<'static, str>>,
+    /// The IP address of the client, sent in the `X-Forwarded-For` header of
+    /// every request. (defaults to `None`)
+    ///
+    /// This makes Sentry attribute events to the client rather than to a
+    /// relay or proxy in between. The value `{{auto}}` forwards the exact
+    /// `ip_address` of the user of each event instead, if it has one. This is
+    /// currently only honored by the `ureq` transport.
+    pub client_ip: Option<Cow<'static, str>>,
     /// The sample rate for event submission. (0.0 - 1.0, defaults to 1.0)
     pub sample_rate: f32,
+    /// The sample rate for attachments, independent of `sample_rate`.
+    /// (0.0 - 1.0, defaults to 1.0)
+    ///
+    /// Attachments that are not sampled are removed from their envelope, which
+    /// is still sent with all its other items. This is currently only honored
+    /// by the `ureq` and `curl` transports.
+    pub attachment_sample_rate: f32,
     /// The sample rate for tracing transactions. (0.0 - 1.0, defaults to 0.0)
     pub traces_sample_rate: f32,
     /// If given, called with a SamplingContext for each transaction to determine the sampling rate.
@@ -149,13 +298,43 @@ pub struct ClientOptions {
     /// Callback that is executed for each Log being added.
     #[cfg(feature = "UNSTABLE_logs")]
     pub before_send_log: Option<BeforeCallback<Log>>,
+    /// Callback that is executed for each Envelope right before the transport queues it.
+    ///
+    /// Unlike `before_send`, this sees every envelope item, including
+    /// attachments and envelopes not containing an event. Returning `None`
+    /// drops the whole envelope, while returning a modified envelope sends
+    /// that instead. This is honored by the `reqwest`, `curl` and `ureq`
+    /// transports.
+    pub before_send_envelope: Option<BeforeCallback<Envelope>>,
     // Transport options
     /// The transport to use.
     ///
     /// This is typically either a boxed function taking the client options by
     /// reference and returning a `Transport`, a boxed `Arc<Transport>` or
-    /// alternatively the `DefaultTransportFactory`.
+    /// alternatively one of the factories of `sentry::transports`, like the
+    /// `DefaultTransportFactory`. The [`NoopTransport`](crate::NoopTransport)
+    /// is a factory of itself.
+    ///
+    /// A custom transport is plugged in with a function creating it:
+    ///
+    /// ```
+    /// # use std::sync::Arc;
+    /// # use sentry_core::{ClientOptions, NoopTransport, Transport};
+    /// let options = ClientOptions {
+    ///     transport: Some(Arc::new(|options: &ClientOptions| {
+    ///         let _options = options;
+    ///         Arc::new(NoopTransport) as Arc<dyn Transport>
+    ///     })),
+    ///     ..Default::default()
+    /// };
+    /// ```
     pub transport: Option<Arc<dyn TransportFactory>>,
+    /// A base URL replacing the scheme, host and path of the DSN when sending
+    /// envelopes, for instance to route them through a relay.
+    ///
+    /// The project and the authentication are still taken from the DSN. An
+    /// invalid URL is ignored. This is honored by all built-in transports.
+    pub endpoint_override: Option<String>,
     /// An optional HTTP proxy to use.
     ///
     /// This will default to the `http_proxy` environment variable.
@@ -165,8 +344,370 @@ pub struct ClientOptions {
     /// This will default to the `HTTPS_PROXY` environment variable
     /// or `http_proxy` if that one exists.
     pub https_proxy: Option<Cow<'static, str>>,
-    /// The timeout on client drop for draining events on shutdown.
+    /// Hosts which are connected to directly, bypassing the proxy.
+    ///
+    /// An entry matches a host exactly, while an entry starting with a dot like
+    /// `.internal` matches all hosts ending in it. `*` matches any host. This
+    /// will default to the `NO_PROXY` environment variable. It is currently only
+    /// honored by the `ureq`, `reqwest` and `curl` transports.
+    pub no_proxy: Vec<String>,
+    /// The username used to authenticate with the proxy.
+    ///
+    /// This is only used if the proxy URL does not contain credentials itself.
+    /// It is currently only honored by the `ureq`, `reqwest` and `curl` transports.
+    pub proxy_username: Option<Cow<'static, str>>,
+    /// The password used to authenticate with the proxy, see `proxy_username`.
+    pub proxy_password: Option<Cow<'static, str>>,
+    /// Tunnels all requests through the proxy with `CONNECT`. (defaults to `false`)
+    ///
+    /// Requests to HTTPS endpoints are always tunneled, while requests to plain
+    /// HTTP endpoints are otherwise forwarded by the proxy, which some proxies
+    /// mishandle. The `ureq` transport tunnels all requests anyway, so this is
+    /// only honored by the `curl` transport.
+    pub proxy_tunnel: bool,
+    /// Additional CA certificates to trust when connecting to Sentry.
+    ///
+    /// The certificates are trusted in addition to the default root
+    /// certificates. This allows connecting to a relay using a private CA
+    /// without resorting to `accept_invalid_certs`. It is currently only
+    /// honored by the `ureq` and `reqwest` transports, and only if no custom
+    /// agent or client was provided.
+    pub extra_ca_certs: Option<PemSource>,
+    /// The certificate chain used to authenticate with Sentry via mutual TLS.
+    ///
+    /// This needs to be set together with `client_key_pem`. It is currently
+    /// only honored by the `ureq` and `reqwest` transports, and only if no
+    /// custom agent or client was provided.
+    pub client_cert_pem: Option<PemSource>,
+    /// The private key belonging to `client_cert_pem`.
+    pub client_key_pem: Option<PemSource>,
+    /// The TLS implementation to use. (defaults to rustls)
+    ///
+    /// This only has an effect if both the `rustls` and `native-tls` features
+    /// of the `sentry` crate are enabled, otherwise the one compiled in is
+    /// used. It is currently only honored by the `ureq` transport, and only
+    /// if no custom agent was provided.
+    pub tls_backend: TlsBackend,
+    /// The timeout on client drop for draining events on shutdown. (defaults to 2s)
+    ///
+    /// This is also used by [`Client::flush`](crate::Client::flush) and
+    /// [`Client::close`](crate::Client::close) when no explicit timeout is given.
+    /// Once it elapses, the `ureq` and `curl` transports cancel a send still
+    /// in flight, closing its connection, unless a custom agent was provided.
     pub shutdown_timeout: Duration,
+    /// The timeout for establishing a connection to Sentry. (defaults to no timeout)
+    ///
+    /// This is currently only honored by the `ureq` and `reqwest` transports,
+    /// and only if no custom agent or client was provided.
+    pub transport_connect_timeout: Option<Duration>,
+    /// The timeout for receiving a response from Sentry. (defaults to no timeout)
+    ///
+    /// This applies to both the response headers and the response body. It is
+    /// currently only honored by the `ureq` and `reqwest` transports, and only
+    /// if no custom agent or client was provided.
+    pub transport_read_timeout: Option<Duration>,
+    /// Callback that determines how long sending an envelope may take in total.
+    /// (defaults to the timeouts of the transport)
+    ///
+    /// The timeout returned for an envelope replaces the other timeouts for
+    /// sending it, so that for instance small events can fail fast while large
+    /// attachments get more time. Every retry gets the full timeout again.
+    /// This is currently only honored by the `ureq` transport.
+    pub envelope_timeout_fn: Option<Arc<EnvelopeTimeoutFn>>,
+    /// The maximum number of idle connections kept open to Sentry. (defaults to 3)
+    ///
+    /// Reusing pooled connections avoids redoing the DNS lookup as well as the
+    /// TCP and TLS handshakes for every request. Setting this to `0` disables
+    /// connection reuse. This is currently only honored by the `ureq` and
+    /// `reqwest` transports, and only if no custom agent or client was provided.
+    pub transport_max_idle_connections: usize,
+    /// Opens a connection to Sentry in the background when the transport is
+    /// created. (defaults to false)
+    ///
+    /// The DNS lookup as well as the TCP and TLS handshakes then no longer
+    /// delay the first envelope. Failing to connect is ignored. This is
+    /// currently only honored by the `ureq` transport, and requires
+    /// connections to be reused.
+    pub warm_up_transport: bool,
+    /// How long an idle connection is kept open for reuse. (defaults to 15 seconds)
+    ///
+    /// This is currently only honored by the `ureq` and `reqwest` transports,
+    /// and only if no custom agent or client was provided.
+    pub transport_idle_timeout: Duration,
+    /// The IP address family to connect to Sentry with. (defaults to any)
+    ///
+    /// Restricting this avoids waiting for connections to time out on hosts
+    /// where one of the families is advertised but broken. This is currently
+    /// only honored by the `curl` and `ureq` transports, and by `ureq` only if
+    /// no custom agent was provided.
+    pub address_family: AddressFamily,
+    /// Addresses to connect to for the given host names, instead of looking
+    /// them up in DNS. (defaults to none)
+    ///
+    /// Hosts not in the map are resolved as usual. The port of the address is
+    /// connected to whatever port the URL names. This is currently only
+    /// honored by the `curl` and `ureq` transports, and by `ureq` only if no
+    /// custom agent was provided.
+    pub resolve_override: HashMap<String, SocketAddr>,
+    /// The path of a Unix domain socket to send envelopes over, instead of
+    /// connecting to the host of the DSN. (defaults to `None`)
+    ///
+    /// This suits a relay running next to the application. The requests are
+    /// still made to the URL of the DSN, but always as plain HTTP, and no
+    /// proxy is used. This is currently only honored by the `ureq` transport
+    /// on Unix, and only if no custom agent was provided.
+    pub uds_path: Option<PathBuf>,
+    /// The HTTP version to prefer for sending envelopes.
+    /// (defaults to [`HttpVersion::Http1_1`])
+    ///
+    /// This is currently only honored by the `ureq` transport, and only if no
+    /// custom agent was provided.
+    pub http_version_preference: HttpVersion,
+    /// Send envelopes on the thread capturing them instead of a background
+    /// thread. (defaults to false)
+    ///
+    /// Capturing an event then blocks until it was sent, but nothing is lost
+    /// when the process exits without flushing, which suits CLI tools and tests.
+    /// This is currently only honored by the `ureq` and `curl` transports.
+    pub blocking_transport: bool,
+    /// The name of the thread sending envelopes in the background. (defaults
+    /// to `"sentry-transport"`)
+    ///
+    /// This makes the thread easy to find in profiles and crash dumps. It is
+    /// not used by the `reqwest` transport when it runs on an existing runtime.
+    pub transport_thread_name: Cow<'static, str>,
+    /// How often the transport sends a check-in to show that it is alive.
+    /// (defaults to `None`, never)
+    ///
+    /// The check-ins go to the `sentry-transport-heartbeat` monitor, whether
+    /// or not anything else is sent, so that an alert can be set up for when
+    /// they stop arriving. They are subject to rate limits like any other
+    /// envelope. This is currently only honored by the `ureq` and `curl`
+    /// transports, and not with a `blocking_transport`.
+    pub heartbeat_interval: Option<Duration>,
+    /// The maximum number of envelopes waiting to be sent. (defaults to 30)
+    ///
+    /// Envelopes submitted while the queue is full are dropped according to
+    /// `queue_overflow_policy`.
+    pub transport_queue_size: usize,
+    /// Merge queued envelopes into requests of up to this many bytes. (defaults to `None`)
+    ///
+    /// When enabled, envelopes waiting in the transport queue are combined
+    /// into a single request, reducing the number of requests to Sentry.
+    /// Envelopes belonging to different events are never combined. This is
+    /// currently only honored by the `ureq` and `curl` transports.
+    pub transport_batch_max_bytes: Option<usize>,
+    /// The maximum number of envelopes sent at the same time. (defaults to 1)
+    ///
+    /// Further envelopes wait in the transport queue until a send finishes.
+    /// This is currently only honored by the `reqwest` transport, the `ureq`
+    /// and `curl` transports always send one envelope at a time.
+    pub max_concurrent_sends: usize,
+    /// The maximum size of a serialized envelope in bytes. (defaults to 200 MiB)
+    ///
+    /// Sentry rejects larger envelopes, so they are dropped instead of being
+    /// sent. Attachments are dropped first, largest first, keeping the rest of
+    /// the envelope if it fits without them. This is currently only honored by
+    /// the `ureq` and `curl` transports.
+    pub max_envelope_size: usize,
+    /// The maximum number of attachments sent with an envelope. (defaults to `None`)
+    ///
+    /// The attachments past the first ones are dropped, while the event and
+    /// the other items are still sent. This is currently only honored by the
+    /// `ureq` and `curl` transports.
+    pub max_attachments_per_envelope: Option<usize>,
+    /// The maximum number of items sent with an envelope. (defaults to `None`)
+    ///
+    /// This is a safety net against envelopes with a pathological number of
+    /// items. The items past the limit are dropped by priority, attachments
+    /// first, then profiles, logs, sessions and other items, with events and
+    /// transactions last. Items of the same priority are kept in order. This
+    /// is currently only honored by the `ureq` and `curl` transports.
+    pub max_items_per_envelope: Option<usize>,
+    /// Which envelope to drop when the transport queue is full. (defaults to dropping the newest)
+    ///
+    /// This is currently only honored by the `ureq` and `curl` transports.
+    pub queue_overflow_policy: QueueOverflowPolicy,
+    /// The categories of envelope items to drop first when the transport is
+    /// under pressure, the least valuable first. (defaults to none)
+    ///
+    /// Once the queue is filled up to the
+    /// [`load_shedding_high_water_mark`](Self::load_shedding_high_water_mark),
+    /// new items of the first category are dropped, and of more categories the
+    /// fuller the queue gets, until all of them are dropped when it is full.
+    /// While a category is rate limited, the categories listed before it are
+    /// dropped as well, and all of them if it is not listed. Categories not
+    /// listed are never dropped this way. This is currently only honored by
+    /// the `ureq` and `curl` transports.
+    pub load_shedding_order: Vec<DataCategory>,
+    /// The fraction of the `transport_queue_size` from which on the transport
+    /// drops the items of the `load_shedding_order`. (defaults to 0.75)
+    pub load_shedding_high_water_mark: f32,
+    /// How to compress envelope request bodies. (defaults to no compression)
+    ///
+    /// Each encoding requires the feature of the `sentry` crate of the same
+    /// name, and this is currently only honored by the `ureq` transport.
+    /// Without the `brotli` feature brotli falls back to gzip, and without the
+    /// `gzip` feature gzip falls back to sending envelopes uncompressed.
+    ///
+    /// Compressed envelopes are buffered in full before they are sent, while
+    /// uncompressed ones are streamed into the request.
+    pub transport_compression: Compression,
+    /// The level to compress envelopes at with the `transport_compression`.
+    /// (defaults to the level of the encoding, 6 for gzip and 5 for brotli)
+    ///
+    /// Lower levels trade compression ratio for speed. Gzip levels range from
+    /// 0, which only stores the envelope, to 9, and brotli levels from 0 to 11.
+    /// Levels out of that range are clamped to it. Like the compression, this
+    /// is currently only honored by the `ureq` transport.
+    pub compression_level: Option<u32>,
+    /// The number of times a failed envelope send is retried. (defaults to 0)
+    ///
+    /// Sends are only retried on connection errors and `5xx` responses, using
+    /// an exponential backoff. Responses that carry rate limits are never retried.
+    /// Every attempt to send an envelope with an event ID carries that ID in
+    /// an `Idempotency-Key` header, so that duplicates can be dropped if an
+    /// earlier attempt did arrive. This is currently only honored by the
+    /// `ureq` transport.
+    pub transport_max_retries: usize,
+    /// The number of consecutive failures to reach Sentry after which the
+    /// transport stops sending envelopes for a while. (defaults to 0, never)
+    ///
+    /// Once the [`circuit_breaker_cooldown`](Self::circuit_breaker_cooldown)
+    /// is over, a single envelope is sent to probe whether Sentry can be
+    /// reached again. Envelopes held back in the meantime are spooled to the
+    /// `offline_spool_dir` if there is one, and dropped otherwise. This is
+    /// honored by the `ureq` and `curl` transports, where `curl` drops them.
+    pub circuit_breaker_threshold: usize,
+    /// How long the transport stops sending envelopes once the
+    /// [`circuit_breaker_threshold`](Self::circuit_breaker_threshold) is
+    /// reached. (defaults to 30s)
+    pub circuit_breaker_cooldown: Duration,
+    /// A file used to persist rate limits across process restarts.
+    ///
+    /// When set, the rate limits that are still active are written to this file
+    /// when the transport shuts down and are loaded again when a new transport
+    /// is created. A missing or corrupt file results in no active rate limits.
+    /// This is honored by the `reqwest`, `curl` and `ureq` transports.
+    pub rate_limit_store_path: Option<PathBuf>,
+    /// The fraction of a rate limit added to it as a random delay. (defaults to
+    /// `0.0`, honoring the limits exactly)
+    ///
+    /// When many clients are rate limited at the same time, this keeps them
+    /// from all resuming at once. The jitter only ever extends the delay the
+    /// server asked for, so `0.1` makes limits last up to 10% longer. The
+    /// fraction is clamped to between `0.0` and `1.0`. This is honored by the
+    /// `reqwest`, `curl` and `ureq` transports.
+    pub rate_limit_jitter: f32,
+    /// How long it takes to resume sending everything after a rate limit
+    /// expired. (defaults to zero, resuming right away)
+    ///
+    /// During that time, the fraction of envelopes sent grows steadily, so
+    /// that a burst of queued envelopes does not run into the limit right
+    /// away again. Limits lifted explicitly by the server are not ramped up.
+    /// This is honored by the `reqwest`, `curl` and `ureq` transports.
+    pub rate_limit_ramp_up: Duration,
+    /// Callback that is executed whenever a response changes a rate limit.
+    ///
+    /// It is not called for responses that leave the rate limits as they are.
+    /// It runs on the thread of the transport, so it should return quickly.
+    /// This is honored by the `reqwest`, `curl` and `ureq` transports.
+    pub on_rate_limit: Option<Arc<RateLimitCallback>>,
+    /// Callback that is executed after each attempt of the transport to send an envelope.
+    ///
+    /// This can be used to monitor the health of the transport. It is currently
+    /// only invoked by the `ureq` transport.
+    pub on_transport_result: Option<Arc<TransportResultCallback>>,
+    /// Callback that is executed with the status and body of each unsuccessful
+    /// response to an envelope.
+    ///
+    /// This can be used to log why the server rejected an envelope. It is
+    /// currently only invoked by the `ureq` transport.
+    pub on_transport_error_response: Option<Arc<TransportErrorResponseCallback>>,
+    /// The maximum number of bytes of an error response body passed to
+    /// `on_transport_error_response`. (defaults to 1024)
+    pub transport_error_body_limit: usize,
+    /// The maximum number of bytes of a response body read to be logged in
+    /// `debug` mode. (defaults to 4096)
+    ///
+    /// Longer bodies are logged truncated, so that a proxy responding with a
+    /// huge error page does not make the transport read all of it. This is
+    /// currently only honored by the `ureq` and `reqwest` transports.
+    pub transport_log_body_limit: usize,
+    /// Whether the body of responses is read to be logged in `debug` mode.
+    /// (defaults to `true`)
+    ///
+    /// Turning this off saves reading the body of every response, though a
+    /// connection whose response body was not read can't be reused. The rate
+    /// limits are applied either way, and `on_transport_error_response` still
+    /// gets to see the body of unsuccessful responses. This is currently only
+    /// honored by the `ureq` and `reqwest` transports.
+    pub read_response_body: bool,
+    /// Additional headers sent with every request made by the transport.
+    ///
+    /// These are added to the headers set by the SDK and can not replace the
+    /// `X-Sentry-Auth` header. This is currently only honored by the `ureq`
+    /// transport.
+    pub extra_transport_headers: Vec<(String, String)>,
+    /// Transforms the serialized envelope before it is sent, for instance to
+    /// encrypt it.
+    ///
+    /// The transform runs before compression, and may add headers describing
+    /// the transformed body. Envelopes spooled to disk are stored untransformed,
+    /// and envelopes are buffered in full to be transformed.
+    /// (defaults to sending the envelope unchanged)
+    ///
+    /// This is currently only honored by the `ureq` transport.
+    pub body_transform: Option<Arc<BodyTransform>>,
+    /// Callback returning the Dynamic Sampling Context to send an envelope with.
+    ///
+    /// It is called right before an envelope without a `trace` header is sent,
+    /// and the context it returns becomes that header, so that Sentry samples
+    /// all of a trace consistently. This is distinct from the trace context
+    /// of the items. (defaults to sending no `trace` header)
+    ///
+    /// This is currently only honored by the `ureq`, `curl` and `reqwest`
+    /// transports.
+    pub dynamic_sampling_context_fn: Option<Arc<DynamicSamplingContextFn>>,
+    /// Callback that is executed while an envelope is uploaded, with the bytes
+    /// sent so far and the size of the envelope.
+    ///
+    /// It is called with `0` bytes when an upload starts, and then whenever
+    /// more of the envelope was handed to the connection. Every retry starts
+    /// over. It runs on the thread of the transport, so it should return quickly.
+    ///
+    /// This requires the `upload-progress` feature and is currently only
+    /// honored by the `ureq` transport.
+    #[cfg(feature = "upload-progress")]
+    pub on_upload_progress: Option<Arc<crate::UploadProgressCallback>>,
+    /// A directory in which envelopes are stored while Sentry can't be reached.
+    ///
+    /// Envelopes that fail to send because of network errors, `429` or `5xx`
+    /// responses are written to this directory. They are sent again, oldest
+    /// first, the next time a transport is created. This is currently only
+    /// honored by the `ureq` transport.
+    pub offline_spool_dir: Option<PathBuf>,
+    /// The maximum number of envelopes kept in `offline_spool_dir`. (defaults to 100)
+    ///
+    /// When the limit is reached, the oldest envelopes are removed.
+    pub spool_max_items: usize,
+    /// The maximum total size in bytes of the envelopes kept in
+    /// `offline_spool_dir`. (defaults to no limit)
+    ///
+    /// When the limit is reached, the oldest envelopes are removed. Envelopes
+    /// larger than the limit are not spooled at all.
+    pub spool_max_bytes: Option<usize>,
+    /// Write envelopes to files in this directory instead of sending them.
+    ///
+    /// This is useful to inspect what the SDK would send, or to capture a
+    /// reproduction. It takes effect when the default transport factory is used.
+    pub dry_run_dir: Option<PathBuf>,
+    /// Send client reports about events that were discarded by the SDK. (defaults to true)
+    ///
+    /// The transport counts envelope items it drops because of rate limits, a
+    /// full queue or failed sends, and periodically reports these counts to
+    /// Sentry. This is currently only honored by the `ureq` and `curl` transports.
+    pub send_client_reports: bool,
     /// Controls the maximum size of an HTTP request body that can be captured when using HTTP
     /// server integrations. Needs `send_default_pii` to be enabled to have any effect.
     pub max_request_body_size: MaxRequestBodySize,
@@ -179,6 +720,8 @@ pub struct ClientOptions {
     /// # Warning
     ///
     /// This introduces significant vulnerabilities, and should only be used as a last resort.
+    /// The transports log a warning with [`debug`](Self::debug) whenever they
+    /// are created with verification disabled.
     pub accept_invalid_certs: bool,
     /// Enable Release Health Session tracking.
     ///
@@ -197,6 +740,9 @@ pub struct ClientOptions {
     pub trim_backtraces: bool,
     /// The user agent that should be reported.
     pub user_agent: Cow<'static, str>,
+    /// Whether the `user_agent` replaces the SDK's own or has it appended.
+    /// (defaults to [`UserAgentMode::Override`])
+    pub user_agent_mode: UserAgentMode,
 }
 
 impl ClientOptions {
@@ -222,6 +768,136 @@ impl ClientOptions {
         self.integrations.push(Arc::new(integration));
         self
     }
+
+    /// Returns the user agent the transports report, as composed from
+    /// `user_agent` and `user_agent_mode`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let options = sentry::ClientOptions {
+    ///     user_agent: "my-app/1.0".into(),
+    ///     user_agent_mode: sentry::UserAgentMode::Append,
+    ///     ..Default::default()
+    /// };
+    /// assert!(options
+    ///     .transport_user_agent()
+    ///     .starts_with("my-app/1.0 sentry.rust/"));
+    /// ```
+    pub fn transport_user_agent(&self) -> Cow<'static, str> {
+        match self.user_agent_mode {
+            UserAgentMode::Override => self.user_agent.clone(),
+            UserAgentMode::Append => format!("{} {}", self.user_agent, USER_AGENT).into(),
+        }
+    }
+
+    /// Returns the URL envelopes are sent to and the matching `X-Sentry-Auth`
+    /// header, as used by the built-in transports.
+    ///
+    /// Returns [`None`] if no DSN is configured.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let options = sentry::ClientOptions {
+    ///     dsn: "https://public@example.com/42".parse().ok(),
+    ///     ..Default::default()
+    /// };
+    /// let (url, auth) = options.envelope_endpoint().unwrap();
+    /// assert_eq!(url, "https://example.com/api/42/envelope/");
+    /// assert!(auth.starts_with("Sentry sentry_key=public, sentry_version=7"));
+    /// ```
+    pub fn envelope_endpoint(&self) -> Option<(String, String)> {
+        let dsn = self.dsn.as_ref()?;
+        let user_agent = self.transport_user_agent();
+        let auth = dsn.to_auth(Some(&user_agent)).to_string();
+        Some((self.envelope_url()?.to_string(), auth))
+    }
+
+    /// Checks that envelopes can be sent with these options.
+    ///
+    /// A parsed [`Dsn`] always has a valid scheme, host and project id, so
+    /// this checks that there is one, and that the `endpoint_override`
+    /// envelopes are sent to instead is valid. Without a DSN, the client is
+    /// merely disabled, so this only needs to be checked by applications that
+    /// want to treat that as an error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sentry::DsnError;
+    ///
+    /// let mut options = sentry::ClientOptions::default();
+    /// assert_eq!(options.validate(), Err(DsnError::Missing));
+    ///
+    /// options.dsn = "https://public@example.com/42".parse().ok();
+    /// assert_eq!(options.validate(), Ok(()));
+    ///
+    /// options.endpoint_override = Some("ftp://relay.example.com".into());
+    /// assert!(matches!(
+    ///     options.validate(),
+    ///     Err(DsnError::InvalidEndpointOverride(_))
+    /// ));
+    /// ```
+    pub fn validate(&self) -> Result<(), DsnError> {
+        let dsn = self.dsn.as_ref().ok_or(DsnError::Missing)?;
+        match self.endpoint_override {
+            Some(ref base) if override_envelope_url(base, dsn).is_none() => {
+                Err(DsnError::InvalidEndpointOverride(base.clone()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the URL envelopes are sent to, honoring `endpoint_override`.
+    ///
+    /// Returns [`None`] if no DSN is configured.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let options = sentry::ClientOptions {
+    ///     dsn: "https://public@example.com/42".parse().ok(),
+    ///     endpoint_override: Some("https://relay.example.com/eu/".into()),
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(
+    ///     options.envelope_url().unwrap().as_str(),
+    ///     "https://relay.example.com/eu/api/42/envelope/"
+    /// );
+    /// ```
+    pub fn envelope_url(&self) -> Option<Url> {
+        let dsn = self.dsn.as_ref()?;
+        let Some(ref base) = self.endpoint_override else {
+            return Some(dsn.envelope_api_url());
+        };
+        match override_envelope_url(base, dsn) {
+            Some(url) => Some(url),
+            None => {
+                #[cfg(feature = "client")]
+                sentry_debug!("Ignoring invalid endpoint override `{}`", base);
+                Some(dsn.envelope_api_url())
+            }
+        }
+    }
+}
+
+/// Builds the envelope URL of the `dsn` project under the `base` URL.
+///
+/// Returns [`None`] if `base` is not a valid HTTP(S) URL.
+fn override_envelope_url(base: &str, dsn: &Dsn) -> Option<Url> {
+    let mut base = Url::parse(base).ok()?;
+    if !matches!(base.scheme(), "http" | "https") || !base.has_host() {
+        return None;
+    }
+    // keep the last path segment when joining
+    if !base.path().ends_with('/') {
+        base.set_path(&format!("{}/", base.path()));
+    }
+    base.set_query(None);
+    base.set_fragment(None);
+    base.join(&format!("api/{}/envelope/", dsn.project_id()))
+        .ok()
 }
 
 impl fmt::Debug for ClientOptions {
@@ -232,6 +908,12 @@ impl fmt::Debug for ClientOptions {
         #[derive(Debug)]
         struct BeforeBreadcrumb;
         let before_breadcrumb = self.before_breadcrumb.as_ref().map(|_| BeforeBreadcrumb);
+        #[derive(Debug)]
+        struct BeforeSendEnvelope;
+        let before_send_envelope = self
+            .before_send_envelope
+            .as_ref()
+            .map(|_| BeforeSendEnvelope);
         #[cfg(feature = "UNSTABLE_logs")]
         let before_send_log = {
             #[derive(Debug)]
@@ -240,16 +922,44 @@ impl fmt::Debug for ClientOptions {
         };
         #[derive(Debug)]
         struct TransportFactory;
+        #[derive(Debug)]
+        struct OnRateLimit;
+        let on_rate_limit = self.on_rate_limit.as_ref().map(|_| OnRateLimit);
+        #[derive(Debug)]
+        struct OnTransportResult;
+        let on_transport_result = self.on_transport_result.as_ref().map(|_| OnTransportResult);
+        #[derive(Debug)]
+        struct OnTransportErrorResponse;
+        let on_transport_error_response = self
+            .on_transport_error_response
+            .as_ref()
+            .map(|_| OnTransportErrorResponse);
+        #[derive(Debug)]
+        struct EnvelopeTimeoutFn;
+        let envelope_timeout_fn = self.envelope_timeout_fn.as_ref().map(|_| EnvelopeTimeoutFn);
+        #[derive(Debug)]
+        struct BodyTransform;
+        let body_transform = self.body_transform.as_ref().map(|_| BodyTransform);
+        #[derive(Debug)]
+        struct DynamicSamplingContextFn;
+        let dynamic_sampling_context_fn = self
+            .dynamic_sampling_context_fn
+            .as_ref()
+            .map(|_| DynamicSamplingContextFn);
 
         let integrations: Vec<_> = self.integrations.iter().map(|i| i.name()).collect();
 
         let mut debug_struct = f.debug_struct("ClientOptions");
         debug_struct
             .field("dsn", &self.dsn)
+            .field("require_dsn", &self.require_dsn)
             .field("debug", &self.debug)
             .field("release", &self.release)
             .field("environment", &self.environment)
+            .field("region", &self.region)
+            .field("client_ip", &self.client_ip)
             .field("sample_rate", &self.sample_rate)
+            .field("attachment_sample_rate", &self.attachment_sample_rate)
             .field("traces_sample_rate", &self.traces_sample_rate)
             .field(
                 "traces_sampler",
@@ -268,10 +978,82 @@ impl fmt::Debug for ClientOptions {
             .field("default_integrations", &self.default_integrations)
             .field("before_send", &before_send)
             .field("before_breadcrumb", &before_breadcrumb)
+            .field("before_send_envelope", &before_send_envelope)
             .field("transport", &TransportFactory)
+            .field("endpoint_override", &self.endpoint_override)
             .field("http_proxy", &self.http_proxy)
             .field("https_proxy", &self.https_proxy)
+            .field("no_proxy", &self.no_proxy)
+            .field("proxy_username", &self.proxy_username)
+            .field(
+                "proxy_password",
+                &self.proxy_password.as_ref().map(|_| "[redacted]"),
+            )
+            .field("proxy_tunnel", &self.proxy_tunnel)
+            .field("extra_ca_certs", &self.extra_ca_certs)
+            .field("client_cert_pem", &self.client_cert_pem)
+            .field(
+                "client_key_pem",
+                &self.client_key_pem.as_ref().map(|_| "[redacted]"),
+            )
+            .field("tls_backend", &self.tls_backend)
             .field("shutdown_timeout", &self.shutdown_timeout)
+            .field("transport_connect_timeout", &self.transport_connect_timeout)
+            .field("transport_read_timeout", &self.transport_read_timeout)
+            .field("envelope_timeout_fn", &envelope_timeout_fn)
+            .field(
+                "transport_max_idle_connections",
+                &self.transport_max_idle_connections,
+            )
+            .field("warm_up_transport", &self.warm_up_transport)
+            .field("transport_idle_timeout", &self.transport_idle_timeout)
+            .field("address_family", &self.address_family)
+            .field("resolve_override", &self.resolve_override)
+            .field("uds_path", &self.uds_path)
+            .field("http_version_preference", &self.http_version_preference)
+            .field("blocking_transport", &self.blocking_transport)
+            .field("transport_thread_name", &self.transport_thread_name)
+            .field("heartbeat_interval", &self.heartbeat_interval)
+            .field("transport_queue_size", &self.transport_queue_size)
+            .field("transport_batch_max_bytes", &self.transport_batch_max_bytes)
+            .field("max_concurrent_sends", &self.max_concurrent_sends)
+            .field("max_envelope_size", &self.max_envelope_size)
+            .field(
+                "max_attachments_per_envelope",
+                &self.max_attachments_per_envelope,
+            )
+            .field("max_items_per_envelope", &self.max_items_per_envelope)
+            .field("queue_overflow_policy", &self.queue_overflow_policy)
+            .field("load_shedding_order", &self.load_shedding_order)
+            .field(
+                "load_shedding_high_water_mark",
+                &self.load_shedding_high_water_mark,
+            )
+            .field("transport_compression", &self.transport_compression)
+            .field("compression_level", &self.compression_level)
+            .field("transport_max_retries", &self.transport_max_retries)
+            .field("circuit_breaker_threshold", &self.circuit_breaker_threshold)
+            .field("circuit_breaker_cooldown", &self.circuit_breaker_cooldown)
+            .field("rate_limit_store_path", &self.rate_limit_store_path)
+            .field("rate_limit_jitter", &self.rate_limit_jitter)
+            .field("rate_limit_ramp_up", &self.rate_limit_ramp_up)
+            .field("on_rate_limit", &on_rate_limit)
+            .field("on_transport_result", &on_transport_result)
+            .field("on_transport_error_response", &on_transport_error_response)
+            .field(
+                "transport_error_body_limit",
+                &self.transport_error_body_limit,
+            )
+            .field("transport_log_body_limit", &self.transport_log_body_limit)
+            .field("read_response_body", &self.read_response_body)
+            .field("extra_transport_headers", &self.extra_transport_headers)
+            .field("body_transform", &body_transform)
+            .field("dynamic_sampling_context_fn", &dynamic_sampling_context_fn)
+            .field("offline_spool_dir", &self.offline_spool_dir)
+            .field("spool_max_items", &self.spool_max_items)
+            .field("spool_max_bytes", &self.spool_max_bytes)
+            .field("dry_run_dir", &self.dry_run_dir)
+            .field("send_client_reports", &self.send_client_reports)
             .field("accept_invalid_certs", &self.accept_invalid_certs);
 
         #[cfg(feature = "release-health")]
@@ -284,10 +1066,21 @@ impl fmt::Debug for ClientOptions {
             .field("enable_logs", &self.enable_logs)
             .field("before_send_log", &before_send_log);
 
+        #[cfg(feature = "upload-progress")]
+        {
+            #[derive(Debug)]
+            struct OnUploadProgress;
+            debug_struct.field(
+                "on_upload_progress",
+                &self.on_upload_progress.as_ref().map(|_| OnUploadProgress),
+            );
+        }
+
         debug_struct
             .field("extra_border_frames", &self.extra_border_frames)
             .field("trim_backtraces", &self.trim_backtraces)
             .field("user_agent", &self.user_agent)
+            .field("user_agent_mode", &self.user_agent_mode)
             .finish()
     }
 }
@@ -296,10 +1089,14 @@ impl Default for ClientOptions {
     fn default() -> ClientOptions {
         ClientOptions {
             dsn: None,
+            require_dsn: false,
             debug: false,
             release: None,
             environment: None,
+            region: None,
+            client_ip: None,
             sample_rate: 1.0,
+            attachment_sample_rate: 1.0,
             traces_sample_rate: 0.0,
             traces_sampler: None,
             max_breadcrumbs: 100,
@@ -312,10 +1109,64 @@ impl Default for ClientOptions {
             default_integrations: true,
             before_send: None,
             before_breadcrumb: None,
+            before_send_envelope: None,
             transport: None,
+            endpoint_override: None,
             http_proxy: None,
             https_proxy: None,
+            no_proxy: Vec::new(),
+            proxy_username: None,
+            proxy_password: None,
+            proxy_tunnel: false,
+            extra_ca_certs: None,
+            client_cert_pem: None,
+            client_key_pem: None,
+            tls_backend: TlsBackend::Rustls,
             shutdown_timeout: Duration::from_secs(2),
+            transport_connect_timeout: None,
+            transport_read_timeout: None,
+            envelope_timeout_fn: None,
+            transport_max_idle_connections: 3,
+            warm_up_transport: false,
+            transport_idle_timeout: Duration::from_secs(15),
+            address_family: AddressFamily::Auto,
+            resolve_override: HashMap::new(),
+            uds_path: None,
+            http_version_preference: HttpVersion::Http1_1,
+            blocking_transport: false,
+            transport_thread_name: "sentry-transport".into(),
+            heartbeat_interval: None,
+            transport_queue_size: 30,
+            transport_batch_max_bytes: None,
+            max_concurrent_sends: 1,
+            max_envelope_size: 200 * 1024 * 1024,
+            max_attachments_per_envelope: None,
+            max_items_per_envelope: None,
+            queue_overflow_policy: QueueOverflowPolicy::DropNewest,
+            load_shedding_order: Vec::new(),
+            load_shedding_high_water_mark: 0.75,
+            transport_compression: Compression::None,
+            compression_level: None,
+            transport_max_retries: 0,
+            circuit_breaker_threshold: 0,
+            circuit_breaker_cooldown: Duration::from_secs(30),
+            rate_limit_store_path: None,
+            rate_limit_jitter: 0.0,
+            rate_limit_ramp_up: Duration::ZERO,
+            on_rate_limit: None,
+            on_transport_result: None,
+            on_transport_error_response: None,
+            transport_error_body_limit: 1024,
+            transport_log_body_limit: 4096,
+            read_response_body: true,
+            extra_transport_headers: Vec::new(),
+            body_transform: None,
+            dynamic_sampling_context_fn: None,
+            offline_spool_dir: None,
+            spool_max_items: 100,
+            spool_max_bytes: None,
+            dry_run_dir: None,
+            send_client_reports: true,
             accept_invalid_certs: false,
             #[cfg(feature = "release-health")]
             auto_session_tracking: false,
@@ -324,11 +1175,14 @@ impl Default for ClientOptions {
             extra_border_frames: vec![],
             trim_backtraces: true,
             user_agent: Cow::Borrowed(USER_AGENT),
+            user_agent_mode: UserAgentMode::Override,
             max_request_body_size: MaxRequestBodySize::Medium,
             #[cfg(feature = "UNSTABLE_logs")]
             enable_logs: false,
             #[cfg(feature = "UNSTABLE_logs")]
             before_send_log: None,
+            #[cfg(feature = "upload-progress")]
+            on_upload_progress: None,
         }
     }
 }
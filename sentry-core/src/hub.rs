@@ -4,7 +4,10 @@
 
 use std::sync::{Arc, RwLock};
 
-use crate::protocol::{Event, Level, Log, LogAttribute, LogLevel, Map, SessionStatus};
+use crate::protocol::{
+    Event, Feedback, Level, Log, LogAttribute, LogLevel, Map, MonitorCheckIn, MonitorCheckInStatus,
+    SessionStatus,
+};
 use crate::types::Uuid;
 use crate::{Integration, IntoBreadcrumbs, Scope, ScopeGuard};
 
@@ -122,6 +125,35 @@ impl Hub {
         }}
     }
 
+    /// Captures a cron monitor check-in.
+    ///
+    /// See the global [`capture_check_in`](fn.capture_check_in.html)
+    /// for more documentation.
+    pub fn capture_check_in(&self, monitor_slug: &str, status: MonitorCheckInStatus) -> Uuid {
+        with_client_impl! {{
+            let Some(client) = self.client() else { return Default::default() };
+            client.capture_check_in(MonitorCheckIn {
+                check_in_id: crate::types::random_uuid(),
+                monitor_slug: monitor_slug.into(),
+                status,
+                environment: None,
+                duration: None,
+                monitor_config: None,
+            })
+        }}
+    }
+
+    /// Captures feedback given by a user.
+    ///
+    /// See the global [`capture_feedback`](fn.capture_feedback.html)
+    /// for more documentation.
+    pub fn capture_feedback(&self, feedback: Feedback) -> Uuid {
+        with_client_impl! {{
+            let Some(client) = self.client() else { return Default::default() };
+            client.capture_feedback(feedback)
+        }}
+    }
+
     /// Start a new session for Release Health.
     ///
     /// See the global [`start_session`](fn.start_session.html)
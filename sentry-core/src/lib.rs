@@ -93,6 +93,8 @@
 //! - `feature = "test"`: Activates the [`test`] module, which can be used to
 //!   write integration tests. It comes with a test transport which can capture
 //!   all sent events for inspection.
+//! - `feature = "test-util"`: An alias of `test`, for crates testing their
+//!   Sentry integration with the test transport of the [`test`] module.
 //!
 //! [Sentry]: https://sentry.io/
 //! [`sentry`]: https://crates.io/crates/sentry
@@ -117,21 +119,32 @@ mod hub;
 mod integration;
 mod intodsn;
 mod performance;
+mod ratelimits;
 mod scope;
 mod transport;
 
 // public api or exports from this crate
 pub use crate::api::*;
 pub use crate::breadcrumbs::IntoBreadcrumbs;
-pub use crate::clientoptions::{BeforeCallback, ClientOptions, SessionMode};
+pub use crate::clientoptions::{
+    AddressFamily, BeforeCallback, ClientOptions, Compression, DsnError, HttpVersion, PemSource,
+    QueueOverflowPolicy, SessionMode, TlsBackend, UserAgentMode,
+};
 pub use crate::error::{capture_error, event_from_error, parse_type_from_debug};
 pub use crate::futures::{SentryFuture, SentryFutureExt};
 pub use crate::hub::Hub;
 pub use crate::integration::Integration;
 pub use crate::intodsn::IntoDsn;
 pub use crate::performance::*;
+pub use crate::ratelimits::{RateLimit, RateLimits};
 pub use crate::scope::{Scope, ScopeGuard};
-pub use crate::transport::{Transport, TransportFactory};
+#[cfg(feature = "upload-progress")]
+pub use crate::transport::UploadProgressCallback;
+pub use crate::transport::{
+    BodyTransform, DynamicSamplingContextFn, EnvelopeTimeoutFn, NoopTransport, RateLimitCallback,
+    Transport, TransportError, TransportErrorKind, TransportErrorResponse,
+    TransportErrorResponseCallback, TransportFactory, TransportOutcome, TransportResultCallback,
+};
 #[cfg(feature = "UNSTABLE_logs")]
 mod logger; // structured logging macros exported with `#[macro_export]`
 
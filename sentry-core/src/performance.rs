@@ -313,7 +313,7 @@ impl TransactionContext {
 
         // And set our key
         let existing_value = custom.insert(key, value);
-        std::mem::swap(&mut self.custom, &mut Some(custom));
+        self.custom = Some(custom);
         existing_value
     }
 
@@ -741,7 +741,7 @@ impl Transaction {
     /// for as long as it lives. Therefore you must take care not to keep the returned
     /// `TransactionData` around too long or it will never relinquish the lock and you may run into
     /// a deadlock.
-    pub fn data(&self) -> TransactionData {
+    pub fn data(&self) -> TransactionData<'_> {
         TransactionData(self.inner.lock().unwrap())
     }
 
@@ -962,7 +962,7 @@ impl Span {
     /// for as long as it lives. Therefore you must take care not to keep the returned
     /// `Data` around too long or it will never relinquish the lock and you may run into
     /// a deadlock.
-    pub fn data(&self) -> Data {
+    pub fn data(&self) -> Data<'_> {
         Data(self.span.lock().unwrap())
     }
 
@@ -0,0 +1,283 @@
+use std::time::Duration;
+
+use crate::protocol::DataCategory;
+
+/// A single limit of an `X-Sentry-Rate-Limits` header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RateLimit {
+    /// How long the limit lasts.
+    ///
+    /// Negative or non-finite numbers of seconds yield a limit of a minute,
+    /// and limits longer than a day are shortened to that. Groups of the
+    /// header whose seconds are not a number at all are skipped.
+    pub duration: Duration,
+    /// The categories limited, or all of them if this is empty.
+    pub categories: Vec<DataCategory>,
+    /// What the limit applies to, like the `project` or `key`.
+    pub scope: Option<String>,
+    /// Why the limit was applied, like `quota_exceeded`.
+    pub reason: Option<String>,
+}
+
+impl RateLimit {
+    /// The duration of a limit with a negative or non-finite number of
+    /// seconds, and of a `429` response that does not specify one.
+    pub const DEFAULT_DURATION: Duration = Duration::from_secs(60);
+    /// The longest limit that is honored, to guard against absurd values.
+    pub const MAX_DURATION: Duration = Duration::from_secs(24 * 60 * 60);
+
+    /// Converts a number of seconds sent by the server, like in a
+    /// `Retry-After` header, into the duration of a limit.
+    ///
+    /// This rounds up to whole seconds, and clamps to the same bounds as
+    /// [`RateLimit::duration`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use sentry_core::RateLimit;
+    ///
+    /// assert_eq!(RateLimit::duration_from_secs(1.5), Duration::from_secs(2));
+    /// assert_eq!(RateLimit::duration_from_secs(-1.0), RateLimit::DEFAULT_DURATION);
+    /// assert_eq!(RateLimit::duration_from_secs(1e9), RateLimit::MAX_DURATION);
+    /// ```
+    pub fn duration_from_secs(seconds: f64) -> Duration {
+        if seconds.is_finite() && seconds >= 0.0 {
+            Duration::from_secs_f64(seconds.ceil().min(Self::MAX_DURATION.as_secs_f64()))
+        } else {
+            Self::DEFAULT_DURATION
+        }
+    }
+
+    /// Returns the category of the given name, as named by the Sentry server.
+    ///
+    /// Returns [`None`] for categories the SDK doesn't send.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sentry_core::protocol::DataCategory;
+    /// use sentry_core::RateLimit;
+    ///
+    /// assert_eq!(RateLimit::parse_category("log_item"), Some(DataCategory::LogItem));
+    /// assert_eq!(RateLimit::parse_category("metric_bucket"), None);
+    /// ```
+    pub fn parse_category(name: &str) -> Option<DataCategory> {
+        Some(match name {
+            "error" => DataCategory::Error,
+            "session" => DataCategory::Session,
+            "transaction" => DataCategory::Transaction,
+            "attachment" => DataCategory::Attachment,
+            "monitor" => DataCategory::Monitor,
+            "log_item" => DataCategory::LogItem,
+            "profile" => DataCategory::Profile,
+            "feedback" => DataCategory::Feedback,
+            _ => return None,
+        })
+    }
+
+    /// Parses a single group of the header.
+    fn parse(group: &str) -> Option<Self> {
+        // <group> = <time>:(<category>;)*(:<scope>(:<reason>(:(<namespace>;)*)?)?)?
+        let mut splits = group.split(':');
+        let seconds = splits.next()?.trim().parse::<f64>().ok()?;
+        let duration = Self::duration_from_secs(seconds);
+
+        let names = splits.next().unwrap_or_default().trim();
+        let categories: Vec<_> = names
+            .split(';')
+            .filter_map(|name| Self::parse_category(name.trim()))
+            .collect();
+        // a limit of only unknown categories limits nothing the SDK sends
+        if !names.is_empty() && categories.is_empty() {
+            return None;
+        }
+
+        let mut field = || {
+            splits
+                .next()
+                .map(str::trim)
+                .filter(|field| !field.is_empty())
+                .map(str::to_owned)
+        };
+        // namespaces only restrict limits on metrics, which the SDK doesn't send
+        Some(Self {
+            duration,
+            categories,
+            scope: field(),
+            reason: field(),
+        })
+    }
+
+    /// Returns whether the limit applies to items of `category`.
+    pub fn applies_to(&self, category: DataCategory) -> bool {
+        self.categories.is_empty() || self.categories.contains(&category)
+    }
+}
+
+/// The limits of an `X-Sentry-Rate-Limits` header, as parsed by [`RateLimits::parse`].
+///
+/// This only describes the limits. The transports of the `sentry` crate apply
+/// them, adding some jitter, but custom transports can use this to honor them
+/// in the same way.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RateLimits {
+    limits: Vec<RateLimit>,
+}
+
+impl RateLimits {
+    /// Parses the value of an `X-Sentry-Rate-Limits` header.
+    ///
+    /// Limits which are malformed, or which only name categories the SDK
+    /// doesn't send, are skipped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use sentry_core::protocol::DataCategory;
+    /// use sentry_core::RateLimits;
+    ///
+    /// let limits = RateLimits::parse("60:transaction;profile:key, 5::organization");
+    /// assert_eq!(limits.limits().len(), 2);
+    /// let transactions = limits.duration_for(Some(DataCategory::Transaction));
+    /// assert_eq!(transactions, Some(Duration::from_secs(60)));
+    /// // the second limit applies to all categories
+    /// let errors = limits.duration_for(Some(DataCategory::Error));
+    /// assert_eq!(errors, Some(Duration::from_secs(5)));
+    /// ```
+    pub fn parse(header: &str) -> Self {
+        // <rate-limit> = (<group>,)+
+        Self {
+            limits: header.split(',').filter_map(RateLimit::parse).collect(),
+        }
+    }
+
+    /// Returns the limits in the order of the header.
+    pub fn limits(&self) -> &[RateLimit] {
+        &self.limits
+    }
+
+    /// Returns whether there are no limits.
+    pub fn is_empty(&self) -> bool {
+        self.limits.is_empty()
+    }
+
+    /// Returns how long items of `category` are limited, or the limits of
+    /// all categories given [`None`].
+    ///
+    /// When several limits apply, the longest one wins.
+    pub fn duration_for(&self, category: Option<DataCategory>) -> Option<Duration> {
+        self.limits
+            .iter()
+            .filter(|limit| match category {
+                Some(category) => limit.applies_to(category),
+                None => limit.categories.is_empty(),
+            })
+            .map(|limit| limit.duration)
+            .max()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(seconds: u64) -> Duration {
+        Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn test_multiple_limits() {
+        let limits = RateLimits::parse(
+            "60:transaction:key:quota_exceeded, 120:attachment;monitor:organization:quota_exceeded:custom;spans, 30:log_item",
+        );
+        assert_eq!(
+            limits.limits(),
+            [
+                RateLimit {
+                    duration: secs(60),
+                    categories: vec![DataCategory::Transaction],
+                    scope: Some("key".into()),
+                    reason: Some("quota_exceeded".into()),
+                },
+                RateLimit {
+                    duration: secs(120),
+                    categories: vec![DataCategory::Attachment, DataCategory::Monitor],
+                    scope: Some("organization".into()),
+                    reason: Some("quota_exceeded".into()),
+                },
+                RateLimit {
+                    duration: secs(30),
+                    categories: vec![DataCategory::LogItem],
+                    scope: None,
+                    reason: None,
+                },
+            ]
+        );
+        assert_eq!(
+            limits.duration_for(Some(DataCategory::Monitor)),
+            Some(secs(120))
+        );
+        assert_eq!(limits.duration_for(Some(DataCategory::Error)), None);
+        assert_eq!(limits.duration_for(None), None);
+    }
+
+    #[test]
+    fn test_empty_categories() {
+        let limits = RateLimits::parse("30::bar, 10:error");
+        assert_eq!(limits.limits()[0].categories, []);
+        assert_eq!(limits.limits()[0].scope.as_deref(), Some("bar"));
+        assert!(limits.limits()[0].applies_to(DataCategory::Session));
+        assert_eq!(limits.duration_for(None), Some(secs(30)));
+        // the longest limit applying to a category wins
+        assert_eq!(
+            limits.duration_for(Some(DataCategory::Error)),
+            Some(secs(30))
+        );
+
+        let limits = RateLimits::parse("10");
+        assert_eq!(limits.duration_for(None), Some(secs(10)));
+    }
+
+    #[test]
+    fn test_unknown_categories() {
+        // unknown categories are ignored, and limits of only those skipped
+        let limits =
+            RateLimits::parse("120:invalid:invalid, 4711:foo;bar;baz;security;error:project");
+        assert_eq!(limits.limits().len(), 1);
+        assert_eq!(limits.limits()[0].categories, [DataCategory::Error]);
+        assert_eq!(limits.limits()[0].duration, secs(4711));
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert!(RateLimits::parse("").is_empty());
+        assert!(RateLimits::parse(" , ,").is_empty());
+        assert!(RateLimits::parse("soon:error").is_empty());
+        assert!(RateLimits::parse(":error").is_empty());
+
+        // the valid limits are still parsed
+        let limits = RateLimits::parse("garbage, 20:session");
+        assert_eq!(limits.limits().len(), 1);
+        assert_eq!(
+            limits.duration_for(Some(DataCategory::Session)),
+            Some(secs(20))
+        );
+    }
+
+    #[test]
+    fn test_durations() {
+        let duration = |header| RateLimits::parse(header).duration_for(None);
+        assert_eq!(duration("1.2"), Some(secs(2)));
+        assert_eq!(duration("-5"), Some(RateLimit::DEFAULT_DURATION));
+        assert_eq!(duration("NaN"), Some(RateLimit::DEFAULT_DURATION));
+        assert_eq!(duration("inf"), Some(RateLimit::DEFAULT_DURATION));
+        assert_eq!(duration("99999999"), Some(RateLimit::MAX_DURATION));
+        assert_eq!(duration(" 7 ::key"), Some(secs(7)));
+    }
+}
@@ -182,6 +182,14 @@ mod session_impl {
         crashed: u32,
     }
 
+    /// Returns the start of the minute-long aggregation bucket containing `started`.
+    fn bucket_start(started: SystemTime) -> SystemTime {
+        let since_epoch = started
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        SystemTime::UNIX_EPOCH + Duration::from_secs(since_epoch.as_secs() / 60 * 60)
+    }
+
     /// Background Session Flusher
     ///
     /// The background flusher queues session updates for delayed batched sending.
@@ -263,17 +271,8 @@ mod session_impl {
                 attributes: session_update.attributes.clone(),
             });
 
-            let duration = session_update
-                .started
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap();
-            let duration = (duration.as_secs() / 60) * 60;
-            let started = SystemTime::UNIX_EPOCH
-                .checked_add(Duration::from_secs(duration))
-                .unwrap();
-
             let key = AggregationKey {
-                started,
+                started: bucket_start(session_update.started),
                 distinct_id: session_update.distinct_id,
             };
 
@@ -491,6 +490,58 @@ mod session_impl {
             assert_eq!(items.next(), None);
         }
 
+        #[test]
+        fn test_session_aggregation_buckets() {
+            let at = |secs: f64| SystemTime::UNIX_EPOCH + Duration::from_secs_f64(secs);
+            assert_eq!(bucket_start(at(0.0)), at(0.0));
+            assert_eq!(bucket_start(at(59.999)), at(0.0));
+            assert_eq!(bucket_start(at(60.0)), at(60.0));
+            assert_eq!(bucket_start(at(119.5)), at(60.0));
+
+            let transport = crate::test::TestTransport::new();
+            let flusher = SessionFlusher::new(
+                Arc::new(std::sync::RwLock::new(Some(transport.clone()))),
+                SessionMode::Request,
+            );
+            let update = |started: f64, status: SessionStatus, errors: u64| SessionUpdate {
+                session_id: random_uuid(),
+                distinct_id: None,
+                sequence: None,
+                timestamp: None,
+                started: at(started),
+                init: true,
+                duration: None,
+                status,
+                errors,
+                attributes: SessionAttributes {
+                    release: "some-release".into(),
+                    environment: None,
+                    ip_address: None,
+                    user_agent: None,
+                },
+            };
+            flusher.enqueue(update(0.0, SessionStatus::Exited, 0));
+            flusher.enqueue(update(59.9, SessionStatus::Exited, 2));
+            flusher.enqueue(update(60.0, SessionStatus::Crashed, 1));
+            flusher.enqueue(update(61.0, SessionStatus::Abnormal, 0));
+            flusher.enqueue(update(90.0, SessionStatus::Exited, 0));
+            flusher.flush();
+
+            let envelopes = transport.fetch_and_clear_envelopes();
+            assert_eq!(envelopes.len(), 1);
+            let Some(EnvelopeItem::SessionAggregates(aggregates)) = envelopes[0].items().next()
+            else {
+                panic!("expected session aggregates");
+            };
+            let mut buckets: Vec<_> = aggregates
+                .aggregates
+                .iter()
+                .map(|b| (b.started, b.exited, b.errored, b.crashed, b.abnormal))
+                .collect();
+            buckets.sort_by_key(|bucket| bucket.0);
+            assert_eq!(buckets, [(at(0.0), 1, 1, 0, 0), (at(60.0), 1, 0, 1, 1)]);
+        }
+
         #[test]
         fn test_session_error() {
             let envelopes = capture_envelopes(|| {
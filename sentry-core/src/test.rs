@@ -21,7 +21,7 @@
 
 use std::sync::{Arc, LazyLock, Mutex};
 
-use crate::protocol::Event;
+use crate::protocol::{EnvelopeItem, Event, Transaction};
 use crate::types::Dsn;
 use crate::{ClientOptions, Envelope, Hub, Transport};
 
@@ -58,6 +58,56 @@ impl TestTransport {
         })
     }
 
+    /// Returns the envelopes collected so far, keeping them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sentry::test::TestTransport;
+    /// use sentry::{ClientOptions, Hub, Level};
+    /// use std::sync::Arc;
+    ///
+    /// let transport = TestTransport::new();
+    /// let options = ClientOptions {
+    ///     dsn: Some("https://public@example.com/1".parse().unwrap()),
+    ///     transport: Some(Arc::new(transport.clone())),
+    ///     ..ClientOptions::default()
+    /// };
+    /// let hub = Hub::new(Some(Arc::new(options.into())), Default::default());
+    /// hub.capture_message("Hello World!", Level::Warning);
+    ///
+    /// assert_eq!(transport.envelopes().len(), 1);
+    /// assert_eq!(transport.events()[0].message.as_deref(), Some("Hello World!"));
+    /// assert!(transport.transactions().is_empty());
+    /// ```
+    pub fn envelopes(&self) -> Vec<Envelope> {
+        self.collected.lock().unwrap().clone()
+    }
+
+    /// Returns the events collected so far, keeping them.
+    pub fn events(&self) -> Vec<Event<'static>> {
+        self.collected
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|envelope| envelope.event().cloned())
+            .collect()
+    }
+
+    /// Returns the transactions collected so far, keeping them.
+    pub fn transactions(&self) -> Vec<Transaction<'static>> {
+        self.collected
+            .lock()
+            .unwrap()
+            .iter()
+            .flat_map(|envelope| envelope.items())
+            .filter_map(|item| match item {
+                EnvelopeItem::Transaction(transaction) => Some(transaction.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
     /// Fetches and clears the contained events.
     pub fn fetch_and_clear_events(&self) -> Vec<Event<'static>> {
         self.fetch_and_clear_envelopes()
@@ -1,8 +1,195 @@
+use std::fmt;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
+use crate::protocol::{DataCategory, DynamicSamplingContext};
 use crate::{ClientOptions, Envelope};
 
+/// The outcome of a single attempt of a transport to send an [`Envelope`].
+///
+/// This is passed to the [`ClientOptions::on_transport_result`] callback.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TransportOutcome {
+    /// The envelope was accepted by the server.
+    Sent {
+        /// The size of the serialized envelope.
+        raw_bytes: usize,
+        /// The size of the request body, after transforming and compressing
+        /// the serialized envelope.
+        wire_bytes: usize,
+        /// The time from starting the request until the response headers
+        /// were received.
+        duration: Duration,
+        /// The time from starting the request until the response body was
+        /// read as well, or the same as `duration` if the body was not read.
+        total_duration: Duration,
+    },
+    /// The server responded with rate limits.
+    RateLimited,
+    /// The server responded with an unsuccessful HTTP status code.
+    HttpError(u16),
+    /// The request failed before a response was received, for the given
+    /// reason.
+    NetworkError(TransportErrorKind),
+}
+
+/// The category of a [`TransportError`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TransportErrorKind {
+    /// The request failed before a response was received, for a reason not
+    /// covered by a more specific kind.
+    Network,
+    /// The host could not be resolved.
+    Dns,
+    /// The TLS handshake failed.
+    Tls,
+    /// Connecting to the host timed out.
+    ConnectTimeout,
+    /// Sending the request or receiving the response timed out.
+    ReadTimeout,
+    /// The server responded with an unsuccessful HTTP status code.
+    Http(u16),
+    /// The envelope could not be serialized.
+    Serialization,
+    /// The envelope was not sent because of a rate limit.
+    RateLimited,
+    /// The envelope was dropped without trying to send it, for example
+    /// because it was too large or the transport was shut down.
+    Discarded,
+    /// Sending the envelope panicked, so it was dropped.
+    Panicked,
+}
+
+impl TransportErrorKind {
+    /// Whether the request failed before a response was received.
+    pub fn is_network(self) -> bool {
+        matches!(
+            self,
+            Self::Network | Self::Dns | Self::Tls | Self::ConnectTimeout | Self::ReadTimeout
+        )
+    }
+
+    /// Whether sending the envelope again later may succeed.
+    ///
+    /// Network failures, rate limits and server errors are transient, while
+    /// client errors and envelopes that can't be serialized are permanent.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sentry_core::TransportErrorKind;
+    ///
+    /// assert!(TransportErrorKind::Dns.is_transient());
+    /// assert!(TransportErrorKind::Http(503).is_transient());
+    /// assert!(!TransportErrorKind::Http(400).is_transient());
+    /// ```
+    pub fn is_transient(self) -> bool {
+        match self {
+            Self::Http(code) => code == 429 || code >= 500,
+            Self::Serialization | Self::Discarded | Self::Panicked => false,
+            _ => true,
+        }
+    }
+}
+
+impl fmt::Display for TransportErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Network => f.write_str("network error"),
+            Self::Dns => f.write_str("DNS error"),
+            Self::Tls => f.write_str("TLS error"),
+            Self::ConnectTimeout => f.write_str("connect timeout"),
+            Self::ReadTimeout => f.write_str("read timeout"),
+            Self::Http(code) => write!(f, "HTTP status {code}"),
+            Self::Serialization => f.write_str("serialization error"),
+            Self::RateLimited => f.write_str("rate limited"),
+            Self::Discarded => f.write_str("discarded"),
+            Self::Panicked => f.write_str("panicked"),
+        }
+    }
+}
+
+/// A failure of a transport to send an [`Envelope`].
+///
+/// This is returned by [`Transport::last_error`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TransportError {
+    /// When the failure happened.
+    pub timestamp: SystemTime,
+    /// The category of the failure.
+    pub kind: TransportErrorKind,
+    /// A human readable description of the failure.
+    pub message: String,
+}
+
+impl TransportError {
+    /// Creates a new error that happened just now.
+    pub fn new(kind: TransportErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            timestamp: SystemTime::now(),
+            kind,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for TransportError {}
+
+/// The type of the callback invoked with each [`TransportOutcome`].
+pub type TransportResultCallback = dyn Fn(TransportOutcome) + Send + Sync;
+
+/// The type of the callback invoked whenever the transport updates a rate limit.
+///
+/// It is passed the category the limit applies to, or [`None`] if it applies
+/// to all of them, along with the time the limit expires at.
+pub type RateLimitCallback = dyn Fn(Option<DataCategory>, SystemTime) + Send + Sync;
+
+/// An unsuccessful response of the server to an envelope.
+///
+/// This is passed to the [`ClientOptions::on_transport_error_response`] callback.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TransportErrorResponse {
+    /// The HTTP status code of the response.
+    pub status: u16,
+    /// The start of the response body, truncated to
+    /// [`ClientOptions::transport_error_body_limit`] bytes.
+    pub body: String,
+}
+
+/// The type of the callback invoked with each [`TransportErrorResponse`].
+pub type TransportErrorResponseCallback = dyn Fn(&TransportErrorResponse) + Send + Sync;
+
+/// The type of the [`ClientOptions::body_transform`] hook.
+///
+/// It is given the serialized envelope and returns the body to send instead.
+/// Headers pushed onto the given list are sent along with that body.
+pub type BodyTransform = dyn Fn(Vec<u8>, &mut Vec<(String, String)>) -> Vec<u8> + Send + Sync;
+
+/// The type of the [`ClientOptions::envelope_timeout_fn`] callback.
+///
+/// It returns how long sending the given envelope may take at most.
+pub type EnvelopeTimeoutFn = dyn Fn(&Envelope) -> Duration + Send + Sync;
+
+/// The type of the [`ClientOptions::dynamic_sampling_context_fn`] callback.
+///
+/// It returns the `trace` header to send the given envelope with, if any.
+pub type DynamicSamplingContextFn =
+    dyn Fn(&Envelope) -> Option<DynamicSamplingContext> + Send + Sync;
+
+/// The type of the [`ClientOptions::on_upload_progress`] callback.
+///
+/// It is given the number of bytes of the envelope sent so far, and its total size.
+#[cfg(feature = "upload-progress")]
+pub type UploadProgressCallback = dyn Fn(u64, u64) + Send + Sync;
+
 /// The trait for transports.
 ///
 /// A transport is responsible for sending events to Sentry.  Custom implementations
@@ -16,6 +203,10 @@ pub trait Transport: Send + Sync + 'static {
 
     /// Flushes the transport queue if there is one.
     ///
+    /// Transports accumulating data to send later, such as client reports,
+    /// should send it along with the queue, so that it is not lost when the
+    /// application exits.
+    ///
     /// If the queue was successfully drained, the return value should be
     /// `true` or `false` if events were left in it.
     fn flush(&self, timeout: Duration) -> bool {
@@ -23,10 +214,77 @@ pub trait Transport: Send + Sync + 'static {
         true
     }
 
+    /// Flushes the transport queue, returning how many envelopes were still
+    /// queued when the `timeout` elapsed.
+    ///
+    /// Transports that can't tell how many envelopes are left return `1` if
+    /// [`Transport::flush`] timed out and `0` otherwise, so that a timeout is
+    /// never mistaken for a drained queue.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use sentry_core::{Envelope, Transport};
+    ///
+    /// struct StuckTransport;
+    ///
+    /// impl Transport for StuckTransport {
+    ///     fn send_envelope(&self, _envelope: Envelope) {}
+    ///     fn flush(&self, _timeout: Duration) -> bool {
+    ///         false
+    ///     }
+    /// }
+    ///
+    /// assert_eq!(StuckTransport.flush_remaining(Duration::ZERO), 1);
+    /// ```
+    fn flush_remaining(&self, timeout: Duration) -> usize {
+        usize::from(!self.flush(timeout))
+    }
+
+    /// Flushes only the queued envelopes carrying items of `category`,
+    /// leaving the other ones queued.
+    ///
+    /// This returns `true` if those envelopes were sent before the `timeout`
+    /// elapsed. Transports that can't drain their queue selectively flush all
+    /// of it instead.
+    fn flush_category(&self, category: DataCategory, timeout: Duration) -> bool {
+        let _category = category;
+        self.flush(timeout)
+    }
+
     /// Instructs the Transport to shut down.
     fn shutdown(&self, timeout: Duration) -> bool {
         self.flush(timeout)
     }
+
+    /// Lifts all rate limits the transport is currently honoring.
+    ///
+    /// This is meant for operators, for instance to resume sending right away
+    /// once the cause of a rate limit was fixed. Transports that don't apply
+    /// rate limits do nothing.
+    fn clear_rate_limits(&self) {}
+
+    /// Returns how long until items of `category` may be sent again, or
+    /// [`None`] if they may be sent now.
+    ///
+    /// Producers can use this to pace themselves rather than have their
+    /// envelopes dropped. Transports that don't apply rate limits always
+    /// return [`None`].
+    fn time_until_allowed(&self, category: DataCategory) -> Option<Duration> {
+        let _category = category;
+        None
+    }
+
+    /// Returns the most recent failure to send an envelope, if any.
+    ///
+    /// This can be used for health checks. The error is not cleared by later
+    /// successful sends, so its `timestamp` should be used to decide whether
+    /// it is still relevant.
+    fn last_error(&self) -> Option<TransportError> {
+        None
+    }
 }
 
 /// A factory creating transport instances.
@@ -56,14 +314,75 @@ where
     }
 }
 
+/// A [`Transport`] that silently discards all envelopes.
+///
+/// This is useful to exercise the SDK, for instance in integration tests or
+/// during local development, without sending anything to Sentry. A DSN still
+/// needs to be configured for the client to be enabled.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Arc;
+///
+/// let options = sentry::ClientOptions {
+///     dsn: Some("https://public@example.com/1".parse().unwrap()),
+///     transport: Some(Arc::new(sentry::NoopTransport)),
+///     ..Default::default()
+/// };
+/// let _guard = sentry::init(options);
+///
+/// let event_id = sentry::capture_message("Hello World!", sentry::Level::Info);
+/// assert!(!event_id.is_nil());
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoopTransport;
+
+impl Transport for NoopTransport {
+    fn send_envelope(&self, envelope: Envelope) {
+        let _envelope = envelope;
+    }
+}
+
+impl TransportFactory for NoopTransport {
+    fn create_transport(&self, options: &ClientOptions) -> Arc<dyn Transport> {
+        let _options = options;
+        Arc::new(NoopTransport)
+    }
+}
+
 impl<T: Transport> Transport for Arc<T> {
     fn send_envelope(&self, envelope: Envelope) {
         (**self).send_envelope(envelope)
     }
 
+    fn flush(&self, timeout: Duration) -> bool {
+        (**self).flush(timeout)
+    }
+
+    fn flush_remaining(&self, timeout: Duration) -> usize {
+        (**self).flush_remaining(timeout)
+    }
+
+    fn flush_category(&self, category: DataCategory, timeout: Duration) -> bool {
+        (**self).flush_category(category, timeout)
+    }
+
     fn shutdown(&self, timeout: Duration) -> bool {
         (**self).shutdown(timeout)
     }
+
+    fn clear_rate_limits(&self) {
+        (**self).clear_rate_limits()
+    }
+
+    fn time_until_allowed(&self, category: DataCategory) -> Option<Duration> {
+        (**self).time_until_allowed(category)
+    }
+
+    fn last_error(&self) -> Option<TransportError> {
+        (**self).last_error()
+    }
 }
 
 impl<T: Transport> TransportFactory for Arc<T> {
@@ -36,6 +36,9 @@ pub enum EventMapping {
     Event(sentry_core::protocol::Event<'static>),
 }
 
+/// The target of the events the `sentry` transports record with `tracing`.
+const SDK_TRANSPORT_TARGET: &str = "sentry::transport";
+
 /// The default event filter.
 ///
 /// By default, an exception event is captured for `error`, a breadcrumb for
@@ -205,6 +208,11 @@ where
     S: Subscriber + for<'a> LookupSpan<'a>,
 {
     fn on_event(&self, event: &Event, ctx: Context<'_, S>) {
+        // Events about sending to Sentry must not be sent to Sentry themselves.
+        if event.metadata().target().starts_with(SDK_TRANSPORT_TARGET) {
+            return;
+        }
+
         let item = match &self.event_mapper {
             Some(mapper) => mapper(event, ctx),
             None => {
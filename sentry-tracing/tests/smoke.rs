@@ -51,3 +51,12 @@ fn should_instrument_function_with_event() {
         .expect("to have data attribute with name 'value'");
     assert_eq!(value, 1);
 }
+
+#[test]
+fn should_ignore_sdk_transport_events() {
+    let transport = shared::init_sentry(0.0);
+
+    tracing::error!(target: "sentry::transport", status = 500, "envelope rejected");
+
+    assert!(transport.fetch_and_clear_envelopes().is_empty());
+}
@@ -1,12 +1,13 @@
 use std::fmt;
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 /// The different types an attachment can have.
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Deserialize)]
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Deserialize)]
 pub enum AttachmentType {
     #[serde(rename = "event.attachment")]
     /// (default) A standard attachment without special meaning.
+    #[default]
     Attachment,
     /// A minidump file that creates an error event and is symbolicated. The
     /// file should start with the `MDMP` magic bytes.
@@ -23,12 +24,11 @@ pub enum AttachmentType {
     /// the last logs are extracted into event breadcrumbs.
     #[serde(rename = "unreal.logs")]
     UnrealLogs,
-}
-
-impl Default for AttachmentType {
-    fn default() -> Self {
-        Self::Attachment
-    }
+    /// A JSON file describing the view hierarchy of the application at the
+    /// time of the event, like the ones sent by mobile SDKs. Its content type
+    /// should be `application/json`.
+    #[serde(rename = "event.view_hierarchy")]
+    ViewHierarchy,
 }
 
 impl AttachmentType {
@@ -40,6 +40,7 @@ impl AttachmentType {
             Self::AppleCrashReport => "event.applecrashreport",
             Self::UnrealContext => "unreal.context",
             Self::UnrealLogs => "unreal.logs",
+            Self::ViewHierarchy => "event.view_hierarchy",
         }
     }
 }
@@ -63,21 +64,43 @@ impl Attachment {
     where
         W: std::io::Write,
     {
-        writeln!(
-            writer,
-            r#"{{"type":"attachment","length":{length},"filename":"{filename}","attachment_type":"{at}","content_type":"{ct}"}}"#,
-            filename = self.filename,
-            length = self.buffer.len(),
-            at = self.ty.unwrap_or_default().as_str(),
-            ct = self
-                .content_type
-                .as_ref()
-                .unwrap_or(&"application/octet-stream".to_string())
-        )?;
-
+        self.header_to_writer(writer)?;
         writer.write_all(&self.buffer)?;
         Ok(())
     }
+
+    /// Writes the item header of the attachment to the provided `Writer`.
+    ///
+    /// Attachments without a `ty` or `content_type` are sent as generic
+    /// attachments of binary data.
+    pub(crate) fn header_to_writer<W>(&self, writer: &mut W) -> std::io::Result<()>
+    where
+        W: std::io::Write,
+    {
+        let header = AttachmentHeader {
+            ty: "attachment",
+            length: self.buffer.len(),
+            filename: &self.filename,
+            attachment_type: self.ty.unwrap_or_default().as_str(),
+            content_type: self
+                .content_type
+                .as_deref()
+                .unwrap_or("application/octet-stream"),
+        };
+        serde_json::to_writer(&mut *writer, &header)?;
+        writeln!(writer)
+    }
+}
+
+/// The item header of an [`Attachment`], as written by [`Attachment::header_to_writer`].
+#[derive(Serialize)]
+struct AttachmentHeader<'a> {
+    #[serde(rename = "type")]
+    ty: &'static str,
+    length: usize,
+    filename: &'a str,
+    attachment_type: &'static str,
+    content_type: &'a str,
 }
 
 // Implement Debug manually, otherwise users will be sad when they get a dump
@@ -0,0 +1,84 @@
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::utils::ts_rfc3339;
+
+/// The reason why the SDK discarded an event.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum DiscardReason {
+    /// The transport queue was full.
+    QueueOverflow,
+    /// The event was dropped because of an active rate limit.
+    #[serde(rename = "ratelimit_backoff")]
+    RateLimitBackoff,
+    /// The event could not be sent because of a network error.
+    NetworkError,
+    /// The server rejected the event.
+    SendError,
+    /// The event was dropped because of an internal error, like failing to serialize it.
+    InternalSdkError,
+    /// The event was dropped by sampling.
+    SampleRate,
+    /// The event was dropped by the `before_send` callback.
+    BeforeSend,
+    /// The event was dropped by an event processor.
+    EventProcessor,
+    /// The event exceeded the size accepted by Sentry.
+    TooLarge,
+    /// The item was dropped because its envelope carried more items of its
+    /// kind than the SDK was configured to send.
+    TooManyItems,
+}
+
+/// The category of data a discarded event belongs to.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum DataCategory {
+    /// Data of an unknown type.
+    Default,
+    /// An error event.
+    Error,
+    /// A transaction.
+    Transaction,
+    /// A release health session.
+    Session,
+    /// An attachment, counted in bytes.
+    Attachment,
+    /// A monitor check-in.
+    Monitor,
+    /// A single log item.
+    LogItem,
+    /// A profile.
+    Profile,
+    /// User feedback.
+    Feedback,
+}
+
+/// The number of events discarded for a given reason and category.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DiscardedEvent {
+    /// Why the events were discarded.
+    pub reason: DiscardReason,
+    /// What kind of events were discarded.
+    pub category: DataCategory,
+    /// The number of discarded events.
+    pub quantity: u64,
+}
+
+/// A Client Report, informing Sentry about events that were discarded by the SDK.
+///
+/// Refer to the [Client Reports](https://develop.sentry.dev/sdk/client-reports/)
+/// documentation for more details.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ClientReport {
+    /// The timestamp of when the report was created.
+    #[serde(default = "SystemTime::now", with = "ts_rfc3339")]
+    pub timestamp: SystemTime,
+    /// The discarded events, grouped by reason and category.
+    #[serde(default)]
+    pub discarded_events: Vec<DiscardedEvent>,
+}
@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+
+use super::v7::TraceId;
+
+/// The Dynamic Sampling Context of a trace.
+///
+/// It is sent as the `trace` header of an [`Envelope`](super::v7::Envelope),
+/// so that Sentry can make the same sampling decision for all of the trace.
+/// Refer to the [Dynamic Sampling Context](https://develop.sentry.dev/sdk/telemetry/traces/dynamic-sampling-context/)
+/// documentation for more details.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct DynamicSamplingContext {
+    /// The id of the trace.
+    pub trace_id: TraceId,
+    /// The public key of the DSN the trace is sent with.
+    pub public_key: String,
+    /// The sample rate the head of the trace was sampled with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample_rate: Option<f32>,
+    /// Whether the head of the trace was sampled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sampled: Option<bool>,
+    /// The release of the application at the head of the trace.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub release: Option<String>,
+    /// The environment of the application at the head of the trace.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment: Option<String>,
+    /// The name of the transaction at the head of the trace.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transaction: Option<String>,
+}
@@ -1,4 +1,7 @@
-use std::{io::Write, path::Path};
+use std::borrow::Cow;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::time::SystemTime;
 
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
@@ -7,8 +10,8 @@ use uuid::Uuid;
 use super::v7 as protocol;
 
 use protocol::{
-    Attachment, AttachmentType, Event, Log, MonitorCheckIn, SessionAggregates, SessionUpdate,
-    Transaction,
+    Attachment, AttachmentType, ClientReport, DataCategory, DynamicSamplingContext, Event,
+    Feedback, Log, MonitorCheckIn, Profile, SessionAggregates, SessionUpdate, Transaction,
 };
 
 /// Raised if a envelope cannot be parsed from a given input.
@@ -37,9 +40,18 @@ pub enum EnvelopeError {
     InvalidItemPayload(#[source] serde_json::Error),
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 struct EnvelopeHeader {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     event_id: Option<Uuid>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "crate::utils::ts_rfc3339_opt"
+    )]
+    sent_at: Option<SystemTime>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    trace: Option<DynamicSamplingContext>,
 }
 
 /// An Envelope Item Type.
@@ -67,6 +79,15 @@ enum EnvelopeItemType {
     /// A container of Log items.
     #[serde(rename = "log")]
     LogsContainer,
+    /// A Client Report Item Type.
+    #[serde(rename = "client_report")]
+    ClientReport,
+    /// A Profile Item Type.
+    #[serde(rename = "profile")]
+    Profile,
+    /// A User Feedback Item Type.
+    #[serde(rename = "feedback")]
+    Feedback,
 }
 
 /// An Envelope Item Header.
@@ -118,12 +139,60 @@ pub enum EnvelopeItem {
     MonitorCheckIn(MonitorCheckIn),
     /// A container for a list of multiple items.
     ItemContainer(ItemContainer),
+    /// A Client Report Item.
+    ///
+    /// See the [Client Reports documentation](https://develop.sentry.dev/sdk/client-reports/)
+    /// for more details.
+    ClientReport(ClientReport),
+    /// A Profile Item.
+    ///
+    /// See the [Profiling documentation](https://develop.sentry.dev/sdk/telemetry/profiles/)
+    /// for more details.
+    Profile(Profile),
+    /// A User Feedback Item.
+    ///
+    /// See the [User Feedback documentation](https://develop.sentry.dev/sdk/data-model/envelope-items/#user-feedback)
+    /// for more details.
+    Feedback(Feedback),
     /// This is a sentinel item used to `filter` raw envelopes.
     Raw,
     // TODO:
     // etc…
 }
 
+impl EnvelopeItem {
+    /// The [`DataCategory`] this item is counted as, for instance by rate
+    /// limits and client reports.
+    ///
+    /// Returns [`None`] for client reports, which are not counted as data.
+    pub fn data_category(&self) -> Option<DataCategory> {
+        Some(match self {
+            Self::Event(_) => DataCategory::Error,
+            Self::Transaction(_) => DataCategory::Transaction,
+            Self::SessionUpdate(_) | Self::SessionAggregates(_) => DataCategory::Session,
+            Self::Attachment(_) => DataCategory::Attachment,
+            Self::MonitorCheckIn(_) => DataCategory::Monitor,
+            Self::ItemContainer(ItemContainer::Logs(_)) => DataCategory::LogItem,
+            Self::Profile(_) => DataCategory::Profile,
+            Self::Feedback(_) => DataCategory::Feedback,
+            Self::ClientReport(_) => return None,
+            Self::Raw => DataCategory::Default,
+        })
+    }
+
+    /// The quantity this item is counted as in its [`data_category`](Self::data_category).
+    ///
+    /// This is the size in bytes for attachments, the number of contained items
+    /// for item containers, and 1 for everything else.
+    pub fn quantity(&self) -> u64 {
+        match self {
+            Self::Attachment(attachment) => attachment.buffer.len() as u64,
+            Self::ItemContainer(container) => container.len() as u64,
+            _ => 1,
+        }
+    }
+}
+
 /// A container for a list of multiple items.
 /// It's considered a single envelope item, with its `type` corresponding to the contained items'
 /// `type`.
@@ -210,6 +279,24 @@ impl From<MonitorCheckIn> for EnvelopeItem {
     }
 }
 
+impl From<ClientReport> for EnvelopeItem {
+    fn from(report: ClientReport) -> Self {
+        EnvelopeItem::ClientReport(report)
+    }
+}
+
+impl From<Profile> for EnvelopeItem {
+    fn from(profile: Profile) -> Self {
+        EnvelopeItem::Profile(profile)
+    }
+}
+
+impl From<Feedback> for EnvelopeItem {
+    fn from(feedback: Feedback) -> Self {
+        EnvelopeItem::Feedback(feedback)
+    }
+}
+
 impl From<ItemContainer> for EnvelopeItem {
     fn from(container: ItemContainer) -> Self {
         EnvelopeItem::ItemContainer(container)
@@ -261,6 +348,94 @@ impl Items {
     }
 }
 
+/// The parts of a serialized [`Envelope`], borrowing the payloads of attachments.
+#[derive(Default)]
+struct Chunks<'e> {
+    chunks: Vec<Cow<'e, [u8]>>,
+    current: Vec<u8>,
+}
+
+impl<'e> Chunks<'e> {
+    /// Appends `bytes` without copying them.
+    fn borrow(&mut self, bytes: &'e [u8]) {
+        if !self.current.is_empty() {
+            self.chunks
+                .push(Cow::Owned(std::mem::take(&mut self.current)));
+        }
+        self.chunks.push(Cow::Borrowed(bytes));
+    }
+
+    fn finish(mut self) -> Vec<Cow<'e, [u8]>> {
+        if !self.current.is_empty() {
+            self.chunks.push(Cow::Owned(self.current));
+        }
+        self.chunks
+    }
+}
+
+impl Write for Chunks<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.current.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A [`Read`] yielding a serialized [`Envelope`], see [`Envelope::reader`].
+///
+/// Cloning it yields a reader starting at the same position, without copying
+/// the borrowed attachments.
+#[derive(Clone, Debug)]
+pub struct EnvelopeReader<'e> {
+    chunks: Vec<Cow<'e, [u8]>>,
+    /// The index of the chunk being read, and the offset into it.
+    position: (usize, usize),
+    len: usize,
+}
+
+impl<'e> EnvelopeReader<'e> {
+    fn new(chunks: Vec<Cow<'e, [u8]>>) -> Self {
+        let len = chunks.iter().map(|chunk| chunk.len()).sum();
+        Self {
+            chunks,
+            position: (0, 0),
+            len,
+        }
+    }
+
+    /// Returns the total size of the serialized Envelope in bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the serialized Envelope is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Read for EnvelopeReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let (ref mut index, ref mut offset) = self.position;
+        while let Some(chunk) = self.chunks.get(*index) {
+            let remaining = &chunk[*offset..];
+            if remaining.is_empty() {
+                *index += 1;
+                *offset = 0;
+                continue;
+            }
+            let read = remaining.len().min(buf.len());
+            buf[..read].copy_from_slice(&remaining[..read]);
+            *offset += read;
+            return Ok(read);
+        }
+        Ok(0)
+    }
+}
+
 /// A Sentry Envelope.
 ///
 /// An Envelope is the data format that Sentry uses for Ingestion. It can contain
@@ -272,6 +447,9 @@ impl Items {
 #[derive(Clone, Default, Debug, PartialEq)]
 pub struct Envelope {
     event_id: Option<Uuid>,
+    sent_at: Option<SystemTime>,
+    // boxed, as most envelopes have no trace header
+    trace: Option<Box<DynamicSamplingContext>>,
     items: Items,
 }
 
@@ -302,13 +480,66 @@ impl Envelope {
                 self.event_id = Some(event.event_id);
             } else if let EnvelopeItem::Transaction(ref transaction) = item {
                 self.event_id = Some(transaction.event_id);
+            } else if let EnvelopeItem::Feedback(ref feedback) = item {
+                self.event_id = Some(feedback.event_id);
             }
         }
         items.push(item);
     }
 
+    /// Moves all the items of `other` into this Envelope.
+    ///
+    /// The items of an Envelope can only belong to a single event, so this
+    /// fails if the Envelopes belong to different events or both contain an
+    /// event, and if either of them contains attachments or profiles without
+    /// both belonging to the same event. It also fails if either of them
+    /// contains raw items. In that case, `other` is returned unchanged.
+    pub fn merge(&mut self, other: Envelope) -> Result<(), Envelope> {
+        let same_event = self.event_id.is_some() && self.event_id == other.event_id;
+        let conflicting = if same_event {
+            self.has_event() && other.has_event()
+        } else {
+            (self.event_id.is_some() && other.event_id.is_some())
+                || self.has_event_items()
+                || other.has_event_items()
+        };
+        if conflicting {
+            return Err(other);
+        }
+        match (&mut self.items, other.items) {
+            (Items::EnvelopeItems(items), Items::EnvelopeItems(other_items)) => {
+                items.extend(other_items);
+                self.event_id = self.event_id.or(other.event_id);
+                self.trace = self.trace.take().or(other.trace);
+                Ok(())
+            }
+            (_, items) => Err(Envelope {
+                event_id: other.event_id,
+                sent_at: other.sent_at,
+                trace: other.trace,
+                items,
+            }),
+        }
+    }
+
+    /// Whether this Envelope contains an event, transaction or feedback.
+    fn has_event(&self) -> bool {
+        self.items().any(|item| {
+            matches!(
+                item,
+                EnvelopeItem::Event(_) | EnvelopeItem::Transaction(_) | EnvelopeItem::Feedback(_)
+            )
+        })
+    }
+
+    /// Whether this Envelope contains items which belong to its event.
+    fn has_event_items(&self) -> bool {
+        self.items()
+            .any(|item| matches!(item, EnvelopeItem::Attachment(_) | EnvelopeItem::Profile(_)))
+    }
+
     /// Create an [`Iterator`] over all the [`EnvelopeItem`]s.
-    pub fn items(&self) -> EnvelopeItemIter {
+    pub fn items(&self) -> EnvelopeItemIter<'_> {
         let inner = match &self.items {
             Items::EnvelopeItems(items) => items.iter(),
             Items::Raw(_) => [].iter(),
@@ -322,6 +553,34 @@ impl Envelope {
         self.event_id.as_ref()
     }
 
+    /// Returns the time the Envelope was sent at, if set.
+    pub fn sent_at(&self) -> Option<SystemTime> {
+        self.sent_at
+    }
+
+    /// Sets the `sent_at` header, which Sentry uses to correct for clock drift
+    /// of the client.
+    ///
+    /// This should be set right before the Envelope is sent. For raw Envelopes
+    /// any `sent_at` of the original header is replaced when serializing.
+    pub fn set_sent_at(&mut self, sent_at: SystemTime) {
+        self.sent_at = Some(sent_at);
+    }
+
+    /// Returns the [`DynamicSamplingContext`] of the `trace` header, if set.
+    pub fn trace(&self) -> Option<&DynamicSamplingContext> {
+        self.trace.as_deref()
+    }
+
+    /// Sets the `trace` header, which Sentry uses to sample all the envelopes
+    /// of a trace consistently.
+    ///
+    /// For raw Envelopes this is only added when serializing if the original
+    /// header has no `trace`.
+    pub fn set_trace(&mut self, trace: DynamicSamplingContext) {
+        self.trace = Some(Box::new(trace));
+    }
+
     /// Returns the [`Event`] contained in this Envelope, if any.
     ///
     /// [`Event`]: struct.Event.html
@@ -356,7 +615,10 @@ impl Envelope {
             };
         };
 
-        let mut filtered = Envelope::new();
+        let mut filtered = Envelope {
+            trace: self.trace,
+            ..Default::default()
+        };
         for item in items {
             if predicate(&item) {
                 filtered.add_item(item);
@@ -385,17 +647,43 @@ impl Envelope {
     where
         W: Write,
     {
+        io::copy(&mut self.reader()?, &mut writer)?;
+        Ok(())
+    }
+
+    /// Returns the length in bytes of the serialized Envelope.
+    ///
+    /// This serializes everything but the payloads of attachments, which are
+    /// only measured.
+    pub fn serialized_len(&self) -> io::Result<usize> {
+        Ok(self.reader()?.len())
+    }
+
+    /// Returns a [`Read`] yielding the serialized Envelope.
+    ///
+    /// Only the headers and the non-attachment items are serialized up front,
+    /// the payloads of attachments are read straight from the Envelope. This
+    /// makes it possible to stream large attachments without copying them.
+    pub fn reader(&self) -> io::Result<EnvelopeReader<'_>> {
+        let mut chunks = Chunks::default();
+        self.write_chunks(&mut chunks)?;
+        Ok(EnvelopeReader::new(chunks.finish()))
+    }
+
+    fn write_chunks<'e>(&'e self, mut writer: &mut Chunks<'e>) -> io::Result<()> {
         let items = match &self.items {
-            Items::Raw(bytes) => return writer.write_all(bytes).map(|_| ()),
+            Items::Raw(bytes) => return self.write_raw(bytes, writer),
             Items::EnvelopeItems(items) => items,
         };
 
         // write the headers:
-        let event_id = self.uuid();
-        match event_id {
-            Some(uuid) => writeln!(writer, r#"{{"event_id":"{uuid}"}}"#)?,
-            _ => writeln!(writer, "{{}}")?,
-        }
+        let header = EnvelopeHeader {
+            event_id: self.event_id,
+            sent_at: self.sent_at,
+            trace: self.trace.as_deref().cloned(),
+        };
+        serde_json::to_writer(&mut writer, &header)?;
+        writeln!(writer)?;
 
         let mut item_buf = Vec::new();
         // write each item:
@@ -413,7 +701,8 @@ impl Envelope {
                     serde_json::to_writer(&mut item_buf, transaction)?
                 }
                 EnvelopeItem::Attachment(attachment) => {
-                    attachment.to_writer(&mut writer)?;
+                    attachment.header_to_writer(&mut writer)?;
+                    writer.borrow(&attachment.buffer);
                     writeln!(writer)?;
                     continue;
                 }
@@ -426,6 +715,9 @@ impl Envelope {
                         serde_json::to_writer(&mut item_buf, &wrapper)?
                     }
                 },
+                EnvelopeItem::ClientReport(report) => serde_json::to_writer(&mut item_buf, report)?,
+                EnvelopeItem::Profile(profile) => serde_json::to_writer(&mut item_buf, profile)?,
+                EnvelopeItem::Feedback(feedback) => serde_json::to_writer(&mut item_buf, feedback)?,
                 EnvelopeItem::Raw => {
                     continue;
                 }
@@ -437,6 +729,9 @@ impl Envelope {
                 EnvelopeItem::Transaction(_) => "transaction",
                 EnvelopeItem::MonitorCheckIn(_) => "check_in",
                 EnvelopeItem::ItemContainer(container) => container.ty(),
+                EnvelopeItem::ClientReport(_) => "client_report",
+                EnvelopeItem::Profile(_) => "profile",
+                EnvelopeItem::Feedback(_) => "feedback",
                 EnvelopeItem::Attachment(_) | EnvelopeItem::Raw => unreachable!(),
             };
 
@@ -464,6 +759,40 @@ impl Envelope {
         Ok(())
     }
 
+    /// Writes the raw `bytes` of the Envelope, replacing the `sent_at` of
+    /// their header if it was set, and adding the `trace` if it was set and
+    /// their header has none.
+    ///
+    /// The bytes are written unchanged if their header can't be parsed.
+    fn write_raw<'e>(&self, bytes: &'e [u8], writer: &mut Chunks<'e>) -> io::Result<()> {
+        let header_end = bytes.iter().position(|b| *b == b'\n');
+        let header = header_end.and_then(|end| {
+            serde_json::from_slice::<serde_json::Map<String, serde_json::Value>>(&bytes[..end]).ok()
+        });
+        let (Some(end), Some(mut header)) = (header_end, header) else {
+            writer.borrow(bytes);
+            return Ok(());
+        };
+        let trace = self
+            .trace
+            .as_ref()
+            .filter(|_| !header.contains_key("trace"));
+        if self.sent_at.is_none() && trace.is_none() {
+            writer.borrow(bytes);
+            return Ok(());
+        }
+
+        if let Some(sent_at) = self.sent_at {
+            header.insert("sent_at".into(), crate::utils::to_rfc3339(&sent_at).into());
+        }
+        if let Some(trace) = trace {
+            header.insert("trace".into(), serde_json::to_value(trace)?);
+        }
+        serde_json::to_writer(&mut *writer, &header)?;
+        writer.borrow(&bytes[end..]);
+        Ok(())
+    }
+
     /// Creates a new Envelope from slice.
     pub fn from_slice(slice: &[u8]) -> Result<Envelope, EnvelopeError> {
         let (header, offset) = Self::parse_header(slice)?;
@@ -471,6 +800,8 @@ impl Envelope {
 
         let mut envelope = Envelope {
             event_id: header.event_id,
+            sent_at: header.sent_at,
+            trace: header.trace.map(Box::new),
             ..Default::default()
         };
 
@@ -485,6 +816,8 @@ impl Envelope {
     pub fn from_bytes_raw(bytes: Vec<u8>) -> Result<Self, EnvelopeError> {
         Ok(Self {
             event_id: None,
+            sent_at: None,
+            trace: None,
             items: Items::Raw(bytes),
         })
     }
@@ -596,6 +929,13 @@ impl Envelope {
                 serde_json::from_slice::<LogsDeserializationWrapper>(payload)
                     .map(|x| EnvelopeItem::ItemContainer(ItemContainer::Logs(x.items)))
             }
+            EnvelopeItemType::ClientReport => {
+                serde_json::from_slice(payload).map(EnvelopeItem::ClientReport)
+            }
+            EnvelopeItemType::Profile => serde_json::from_slice(payload).map(EnvelopeItem::Profile),
+            EnvelopeItemType::Feedback => {
+                serde_json::from_slice(payload).map(EnvelopeItem::Feedback)
+            }
         }
         .map_err(EnvelopeError::InvalidItemPayload)?;
 
@@ -632,8 +972,8 @@ mod test {
 
     use super::*;
     use crate::protocol::v7::{
-        Level, MonitorCheckInStatus, MonitorConfig, MonitorSchedule, SessionAttributes,
-        SessionStatus, Span,
+        DataCategory, DiscardReason, DiscardedEvent, Level, MonitorCheckInStatus, MonitorConfig,
+        MonitorSchedule, SessionAttributes, SessionStatus, Span,
     };
 
     fn to_str(envelope: Envelope) -> String {
@@ -672,6 +1012,143 @@ mod test {
         assert_eq!(&serialized, random_invalid_bytes);
     }
 
+    #[test]
+    fn test_sent_at() {
+        let sent_at = timestamp("2020-07-20T14:51:14.296Z");
+        let mut envelope = Envelope::new();
+        envelope.set_sent_at(sent_at);
+        let serialized = to_str(envelope);
+        assert_eq!(serialized, "{\"sent_at\":\"2020-07-20T14:51:14.296Z\"}\n");
+        let envelope = Envelope::from_slice(serialized.as_bytes()).unwrap();
+        assert_eq!(envelope.sent_at(), Some(sent_at));
+
+        // the header of raw envelopes is updated, keeping its other fields
+        let buf = r#"{"event_id":"22d00b3f-d1b1-4b5d-8d20-49d138cd8a9c","sent_at":"2000-01-01T00:00:00Z"}
+{"type":"event","length":74}
+{"event_id":"22d00b3fd1b14b5d8d2049d138cd8a9c","timestamp":1595256674.296}
+"#;
+        let mut envelope = Envelope::from_bytes_raw(buf.to_string().into_bytes()).unwrap();
+        envelope.set_sent_at(sent_at);
+        assert_eq!(
+            to_str(envelope),
+            buf.replace("2000-01-01T00:00:00Z", "2020-07-20T14:51:14.296Z")
+        );
+
+        let random_invalid_bytes = b"oh stahp!\n\0\x01\x02";
+        let mut envelope = Envelope::from_bytes_raw(random_invalid_bytes.to_vec()).unwrap();
+        envelope.set_sent_at(sent_at);
+        let mut serialized = Vec::new();
+        envelope.to_writer(&mut serialized).unwrap();
+        assert_eq!(&serialized, random_invalid_bytes);
+    }
+
+    #[test]
+    fn test_trace_header() {
+        let trace = DynamicSamplingContext {
+            trace_id: "4c79f60c11214eb38604f4ae0781bfb2".parse().unwrap(),
+            public_key: "public".into(),
+            sample_rate: Some(0.5),
+            sampled: Some(true),
+            ..Default::default()
+        };
+        let mut envelope = Envelope::new();
+        envelope.set_trace(trace.clone());
+        let serialized = to_str(envelope);
+        assert_eq!(
+            serialized,
+            r#"{"trace":{"trace_id":"4c79f60c11214eb38604f4ae0781bfb2","public_key":"public","sample_rate":0.5,"sampled":true}}
+"#
+        );
+        let envelope = Envelope::from_slice(serialized.as_bytes()).unwrap();
+        assert_eq!(envelope.trace(), Some(&trace));
+
+        // raw envelopes get the header unless they have one already
+        let buf = r#"{"event_id":"22d00b3f-d1b1-4b5d-8d20-49d138cd8a9c"}
+{"type":"event","length":74}
+{"event_id":"22d00b3fd1b14b5d8d2049d138cd8a9c","timestamp":1595256674.296}
+"#;
+        let mut envelope = Envelope::from_bytes_raw(buf.to_string().into_bytes()).unwrap();
+        envelope.set_trace(trace);
+        let serialized = to_str(envelope);
+        assert!(serialized.starts_with(
+            r#"{"event_id":"22d00b3f-d1b1-4b5d-8d20-49d138cd8a9c","trace":{"public_key":"public","#
+        ));
+        let mut envelope = Envelope::from_bytes_raw(serialized.clone().into_bytes()).unwrap();
+        envelope.set_trace(DynamicSamplingContext::default());
+        assert_eq!(to_str(envelope), serialized);
+    }
+
+    #[test]
+    fn test_reader() {
+        let mut envelope: Envelope = Event {
+            event_id: Uuid::parse_str("22d00b3f-d1b1-4b5d-8d20-49d138cd8a9c").unwrap(),
+            timestamp: timestamp("2020-07-20T14:51:14.296Z"),
+            ..Default::default()
+        }
+        .into();
+        envelope.add_item(Attachment {
+            buffer: b"some content".to_vec(),
+            filename: "file.txt".to_string(),
+            ..Default::default()
+        });
+
+        let mut reader = envelope.reader().unwrap();
+        let Some(EnvelopeItem::Attachment(attachment)) = envelope.items().nth(1) else {
+            panic!("expected an attachment");
+        };
+        assert!(reader.chunks.iter().any(|chunk| matches!(
+            chunk,
+            Cow::Borrowed(bytes) if bytes.as_ptr() == attachment.buffer.as_ptr()
+        )));
+
+        let mut buf = [0; 7];
+        let mut read = Vec::new();
+        loop {
+            match reader.read(&mut buf).unwrap() {
+                0 => break,
+                n => read.extend_from_slice(&buf[..n]),
+            }
+        }
+        assert_eq!(read.len(), reader.len());
+        assert_eq!(String::from_utf8(read).unwrap(), to_str(envelope));
+    }
+
+    #[test]
+    fn test_inspection() {
+        let mut envelope: Envelope = Event {
+            event_id: Uuid::parse_str("22d00b3f-d1b1-4b5d-8d20-49d138cd8a9c").unwrap(),
+            timestamp: timestamp("2020-07-20T14:51:14.296Z"),
+            ..Default::default()
+        }
+        .into();
+        envelope.add_item(Attachment {
+            buffer: b"some content".to_vec(),
+            filename: "file.txt".to_string(),
+            ..Default::default()
+        });
+        envelope.add_item(ClientReport {
+            timestamp: timestamp("2020-07-20T14:51:14.296Z"),
+            discarded_events: Vec::new(),
+        });
+
+        let items: Vec<_> = envelope
+            .items()
+            .map(|item| (item.data_category(), item.quantity()))
+            .collect();
+        assert_eq!(
+            items,
+            [
+                (Some(DataCategory::Error), 1),
+                (Some(DataCategory::Attachment), 12),
+                (None, 1),
+            ]
+        );
+        assert_eq!(
+            envelope.serialized_len().unwrap(),
+            to_str(envelope.clone()).len()
+        );
+    }
+
     #[test]
     fn test_event() {
         let event_id = Uuid::parse_str("22d00b3f-d1b1-4b5d-8d20-49d138cd8a9c").unwrap();
@@ -813,6 +1290,144 @@ mod test {
         )
     }
 
+    #[test]
+    fn test_merge() {
+        let event_id = Uuid::parse_str("22d00b3f-d1b1-4b5d-8d20-49d138cd8a9c").unwrap();
+        let event = Event {
+            event_id,
+            ..Default::default()
+        };
+        let session = SessionUpdate {
+            session_id: event_id,
+            distinct_id: None,
+            sequence: None,
+            timestamp: None,
+            started: SystemTime::now(),
+            init: true,
+            duration: None,
+            status: SessionStatus::Ok,
+            errors: 0,
+            attributes: SessionAttributes {
+                release: "foo-bar@1.2.3".into(),
+                environment: None,
+                ip_address: None,
+                user_agent: None,
+            },
+        };
+
+        let mut envelope: Envelope = session.clone().into();
+        envelope.merge(event.clone().into()).unwrap();
+        envelope.merge(session.into()).unwrap();
+        assert_eq!(envelope.uuid(), Some(&event_id));
+        assert_eq!(envelope.items().count(), 3);
+
+        // only a single event per envelope
+        let other: Envelope = event.clone().into();
+        assert_eq!(envelope.merge(other.clone()), Err(other));
+
+        // attachments only join the envelope of their event
+        let mut attachment = Envelope::new();
+        attachment.add_item(Attachment::default());
+        assert_eq!(envelope.merge(attachment.clone()), Err(attachment.clone()));
+        let mut envelope: Envelope = event.into();
+        envelope.add_item(Attachment::default());
+        assert_eq!(
+            Envelope::new().merge(envelope.clone()),
+            Err(envelope.clone())
+        );
+        attachment.event_id = Some(event_id);
+        envelope.merge(attachment).unwrap();
+        assert_eq!(envelope.items().count(), 3);
+
+        let raw = Envelope::from_bytes_raw(b"{}\n".to_vec()).unwrap();
+        assert_eq!(Envelope::new().merge(raw.clone()), Err(raw.clone()));
+        let mut raw_target = raw.clone();
+        assert!(raw_target.merge(Envelope::new()).is_err());
+        assert_eq!(raw_target, raw);
+    }
+
+    #[test]
+    fn test_client_report() {
+        let report = ClientReport {
+            timestamp: timestamp("2020-07-20T14:51:14.296Z"),
+            discarded_events: vec![DiscardedEvent {
+                reason: DiscardReason::QueueOverflow,
+                category: DataCategory::Error,
+                quantity: 3,
+            }],
+        };
+        let mut envelope = Envelope::new();
+        envelope.add_item(report);
+        assert_eq!(
+            to_str(envelope),
+            r#"{}
+{"type":"client_report","length":121}
+{"timestamp":"2020-07-20T14:51:14.296Z","discarded_events":[{"reason":"queue_overflow","category":"error","quantity":3}]}
+"#
+        )
+    }
+
+    #[test]
+    fn test_profile() {
+        let profile: Profile = serde_json::from_str(
+            r#"{"event_id":"22d00b3f-d1b1-4b5d-8d20-49d138cd8a9c","platform":"rust","version":"1","profile":{"samples":[{"stack_id":0,"thread_id":"1","elapsed_since_start_ns":"0"}],"stacks":[[0]],"frames":[{"function":"main"}]}}"#,
+        )
+        .unwrap();
+
+        let mut envelope: Envelope = Transaction::default().into();
+        envelope.add_item(profile.clone());
+        let serialized = to_str(envelope);
+        let payload = serde_json::to_string(&profile).unwrap();
+        assert!(serialized.ends_with(&format!(
+            "{{\"type\":\"profile\",\"length\":{}}}\n{payload}\n",
+            payload.len()
+        )));
+
+        let deserialized = Envelope::from_slice(serialized.as_bytes()).unwrap();
+        let items: Vec<_> = deserialized.items().collect();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[1], &EnvelopeItem::Profile(profile));
+    }
+
+    #[test]
+    fn test_feedback() {
+        let feedback = Feedback {
+            event_id: Uuid::parse_str("9ec79c33-ec99-42ab-8353-589fcb2e04dc").unwrap(),
+            timestamp: timestamp("2020-07-20T14:51:14.296Z"),
+            message: "It crashed when I clicked save".into(),
+            name: Some("Jane".into()),
+            contact_email: None,
+            associated_event_id: Some(
+                Uuid::parse_str("22d00b3f-d1b1-4b5d-8d20-49d138cd8a9c").unwrap(),
+            ),
+            url: None,
+        };
+        let envelope: Envelope = feedback.clone().into();
+        let serialized = to_str(envelope);
+        assert_eq!(
+            serialized,
+            r#"{"event_id":"9ec79c33-ec99-42ab-8353-589fcb2e04dc"}
+{"type":"feedback","length":222}
+{"event_id":"9ec79c33-ec99-42ab-8353-589fcb2e04dc","timestamp":1595256674.296,"contexts":{"feedback":{"message":"It crashed when I clicked save","name":"Jane","associated_event_id":"22d00b3f-d1b1-4b5d-8d20-49d138cd8a9c"}}}
+"#
+        );
+
+        let deserialized = Envelope::from_slice(serialized.as_bytes()).unwrap();
+        assert_eq!(deserialized.uuid(), Some(&feedback.event_id));
+        let items: Vec<_> = deserialized.items().collect();
+        let [EnvelopeItem::Feedback(deserialized)] = items[..] else {
+            panic!("expected feedback");
+        };
+        // the timestamp loses some precision as a float
+        assert_eq!(
+            Feedback {
+                timestamp: feedback.timestamp,
+                ..deserialized.clone()
+            },
+            feedback
+        );
+    }
+
     #[test]
     fn test_event_with_attachment() {
         let event_id = Uuid::parse_str("22d00b3f-d1b1-4b5d-8d20-49d138cd8a9c").unwrap();
@@ -841,6 +1456,51 @@ some content
         )
     }
 
+    #[test]
+    fn test_typed_attachment_roundtrip() {
+        let mut envelope = Envelope::new();
+        envelope.add_item(Attachment {
+            buffer: br#"{"windows":[]}"#.to_vec(),
+            filename: "view-hierarchy.json".to_string(),
+            content_type: Some("application/json".to_string()),
+            ty: Some(AttachmentType::ViewHierarchy),
+        });
+        // the header values are escaped
+        envelope.add_item(Attachment {
+            buffer: b"log".to_vec(),
+            filename: "\"quoted\".log".to_string(),
+            content_type: Some("text/plain".to_string()),
+            ty: Some(AttachmentType::UnrealLogs),
+        });
+
+        let serialized = to_str(envelope);
+        let headers: Vec<_> = serialized.lines().skip(1).step_by(2).collect();
+        assert_eq!(
+            headers,
+            [
+                r#"{"type":"attachment","length":14,"filename":"view-hierarchy.json","attachment_type":"event.view_hierarchy","content_type":"application/json"}"#,
+                r#"{"type":"attachment","length":3,"filename":"\"quoted\".log","attachment_type":"unreal.logs","content_type":"text/plain"}"#,
+            ]
+        );
+
+        let envelope = Envelope::from_slice(serialized.as_bytes()).unwrap();
+        let attachments: Vec<_> = envelope
+            .items()
+            .map(|item| match item {
+                EnvelopeItem::Attachment(attachment) => attachment,
+                item => panic!("unexpected item {item:?}"),
+            })
+            .collect();
+        assert_eq!(attachments[0].ty, Some(AttachmentType::ViewHierarchy));
+        assert_eq!(
+            attachments[0].content_type.as_deref(),
+            Some("application/json")
+        );
+        assert_eq!(attachments[0].buffer, br#"{"windows":[]}"#);
+        assert_eq!(attachments[1].filename, "\"quoted\".log");
+        assert_eq!(attachments[1].ty, Some(AttachmentType::UnrealLogs));
+    }
+
     #[test]
     fn test_deserialize_envelope_empty() {
         // Without terminating newline after header
@@ -1094,6 +1754,14 @@ some content
         envelope.add_item(session);
         envelope.add_item(attachment);
         envelope.add_item(logs);
+        envelope.add_item(ClientReport {
+            timestamp: timestamp("2020-07-20T14:51:14.296Z"),
+            discarded_events: vec![DiscardedEvent {
+                reason: DiscardReason::RateLimitBackoff,
+                category: DataCategory::Transaction,
+                quantity: 1,
+            }],
+        });
 
         let serialized = to_str(envelope);
         let deserialized = Envelope::from_slice(serialized.as_bytes()).unwrap();
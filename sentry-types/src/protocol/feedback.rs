@@ -0,0 +1,110 @@
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::utils::ts_seconds_float;
+
+/// User Feedback, like the comments of a user on a crash they ran into.
+///
+/// Refer to the [User Feedback](https://develop.sentry.dev/sdk/data-model/envelope-items/#user-feedback)
+/// documentation for more details.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(from = "FeedbackPayload", into = "FeedbackPayload")]
+pub struct Feedback {
+    /// The unique identifier of this feedback.
+    pub event_id: Uuid,
+    /// The time the feedback was given.
+    pub timestamp: SystemTime,
+    /// The comments of the user.
+    pub message: String,
+    /// The name of the user.
+    pub name: Option<String>,
+    /// The email address to contact the user at.
+    pub contact_email: Option<String>,
+    /// The id of the event the feedback is about, like the crash of the user.
+    pub associated_event_id: Option<Uuid>,
+    /// The URL the feedback was given on.
+    pub url: Option<String>,
+}
+
+impl Default for Feedback {
+    fn default() -> Self {
+        Self {
+            event_id: crate::random_uuid(),
+            timestamp: SystemTime::now(),
+            message: Default::default(),
+            name: Default::default(),
+            contact_email: Default::default(),
+            associated_event_id: Default::default(),
+            url: Default::default(),
+        }
+    }
+}
+
+/// The item payload of [`Feedback`], which is shaped like an event carrying
+/// the feedback in its contexts.
+#[derive(Deserialize, Serialize)]
+struct FeedbackPayload {
+    event_id: Uuid,
+    #[serde(with = "ts_seconds_float")]
+    timestamp: SystemTime,
+    contexts: FeedbackContexts,
+}
+
+#[derive(Deserialize, Serialize)]
+struct FeedbackContexts {
+    feedback: FeedbackContext,
+}
+
+#[derive(Deserialize, Serialize)]
+struct FeedbackContext {
+    message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    contact_email: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    associated_event_id: Option<Uuid>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+}
+
+impl From<FeedbackPayload> for Feedback {
+    fn from(payload: FeedbackPayload) -> Self {
+        let FeedbackContext {
+            message,
+            name,
+            contact_email,
+            associated_event_id,
+            url,
+        } = payload.contexts.feedback;
+        Self {
+            event_id: payload.event_id,
+            timestamp: payload.timestamp,
+            message,
+            name,
+            contact_email,
+            associated_event_id,
+            url,
+        }
+    }
+}
+
+impl From<Feedback> for FeedbackPayload {
+    fn from(feedback: Feedback) -> Self {
+        Self {
+            event_id: feedback.event_id,
+            timestamp: feedback.timestamp,
+            contexts: FeedbackContexts {
+                feedback: FeedbackContext {
+                    message: feedback.message,
+                    name: feedback.name,
+                    contact_email: feedback.contact_email,
+                    associated_event_id: feedback.associated_event_id,
+                    url: feedback.url,
+                },
+            },
+        }
+    }
+}
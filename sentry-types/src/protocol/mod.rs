@@ -14,6 +14,10 @@ pub const LATEST: u16 = 7;
 pub use v7 as latest;
 
 mod attachment;
+mod client_report;
+mod dynamic_sampling;
 mod envelope;
+mod feedback;
 mod monitor;
+mod profile;
 mod session;
@@ -0,0 +1,11 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// A Profile, recorded while a transaction was running.
+///
+/// The SDK does not record profiles itself, so the payload is kept as an
+/// opaque JSON object. Refer to the [Sample Format](https://develop.sentry.dev/sdk/data-model/sample-format/)
+/// documentation for its contents.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Profile(pub Map<String, Value>);
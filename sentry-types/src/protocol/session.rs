@@ -11,12 +11,15 @@ use uuid::Uuid;
 use crate::utils::{ts_rfc3339, ts_rfc3339_opt};
 
 /// The Status of a Release Health Session.
-#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Deserialize, Serialize)]
+#[derive(
+    Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd, Deserialize, Serialize,
+)]
 #[serde(rename_all = "snake_case")]
 pub enum SessionStatus {
     /// The session is healthy.
     ///
     /// This does not necessarily indicate that the session is still active.
+    #[default]
     Ok,
     /// The session terminated normally.
     Exited,
@@ -26,12 +29,6 @@ pub enum SessionStatus {
     Abnormal,
 }
 
-impl Default for SessionStatus {
-    fn default() -> Self {
-        Self::Ok
-    }
-}
-
 /// An error used when parsing `SessionStatus`.
 #[derive(Debug, Error)]
 #[error("invalid session status")]
@@ -25,8 +25,12 @@ pub use uuid::Uuid;
 use crate::utils::{ts_rfc3339_opt, ts_seconds_float};
 
 pub use super::attachment::*;
+pub use super::client_report::*;
+pub use super::dynamic_sampling::*;
 pub use super::envelope::*;
+pub use super::feedback::*;
 pub use super::monitor::*;
+pub use super::profile::*;
 pub use super::session::*;
 
 /// An arbitrary (JSON) value.
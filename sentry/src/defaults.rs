@@ -109,6 +109,16 @@ pub fn apply_defaults(mut opts: ClientOptions) -> ClientOptions {
             .or_else(|| std::env::var("https_proxy").ok().map(Cow::Owned))
             .or_else(|| opts.http_proxy.clone());
     }
+    if opts.no_proxy.is_empty() {
+        if let Ok(no_proxy) = std::env::var("NO_PROXY").or_else(|_| std::env::var("no_proxy")) {
+            opts.no_proxy = no_proxy
+                .split(',')
+                .map(str::trim)
+                .filter(|host| !host.is_empty())
+                .map(String::from)
+                .collect();
+        }
+    }
     if let Ok(accept_invalid_certs) = std::env::var("SSL_VERIFY") {
         opts.accept_invalid_certs = !accept_invalid_certs.parse().unwrap_or(true);
     }
@@ -1,8 +1,8 @@
 use std::sync::Arc;
 
-use sentry_core::sentry_debug;
 #[cfg(feature = "release-health")]
 use sentry_core::SessionMode;
+use sentry_core::{sentry_debug, DsnError};
 
 use crate::defaults::apply_defaults;
 use crate::{Client, ClientOptions, Hub};
@@ -88,10 +88,42 @@ impl Drop for ClientInitGuard {
 ///
 /// # Panics
 ///
-/// This will panic when the provided DSN is invalid.
+/// This will panic when the provided DSN is invalid, or when
+/// [`ClientOptions::require_dsn`] is set and no DSN is configured, see
+/// [`try_init`].
 /// If you want to handle invalid DSNs you need to parse them manually by
 /// calling `parse` on each of them and handle the error.
 pub fn init<C>(opts: C) -> ClientInitGuard
+where
+    C: Into<ClientOptions>,
+{
+    match try_init(opts) {
+        Ok(guard) => guard,
+        Err(err) => panic!("failed to initialize sentry: {err}"),
+    }
+}
+
+/// Creates the Sentry client for a given client config and binds it, like
+/// [`init`], failing if [`ClientOptions::require_dsn`] is set and the options
+/// are not valid.
+///
+/// The error is that of [`ClientOptions::validate`], applied after the
+/// defaults were, and no client is bound then.
+///
+/// # Examples
+///
+/// ```
+/// let result = sentry::try_init(sentry::ClientOptions {
+///     dsn: None,
+///     require_dsn: true,
+///     ..Default::default()
+/// });
+/// # // the DSN may be taken from the environment
+/// # if std::env::var("SENTRY_DSN").is_err() {
+/// assert_eq!(result.err(), Some(sentry::DsnError::Missing));
+/// # }
+/// ```
+pub fn try_init<C>(opts: C) -> Result<ClientInitGuard, DsnError>
 where
     C: Into<ClientOptions>,
 {
@@ -102,7 +134,7 @@ where
     #[cfg(feature = "release-health")]
     let session_mode = opts.session_mode;
 
-    let client = Arc::new(Client::from(opts));
+    let client = Arc::new(Client::try_with_options(opts)?);
 
     Hub::with(|hub| hub.bind_client(Some(client.clone())));
     if let Some(dsn) = client.dsn() {
@@ -114,5 +146,5 @@ where
     if auto_session_tracking && session_mode == SessionMode::Application {
         crate::start_session()
     }
-    ClientInitGuard(client)
+    Ok(ClientInitGuard(client))
 }
@@ -66,6 +66,7 @@
 //! | `transport`       | ✅      |                |            |                                                                                          |
 //! | `anyhow`          |         | 🔌             |            |                                                                                          |
 //! | `test`            |         |                |            |                                                                                          |
+//! | `test-util`       |         |                |            |                                                                                          |
 //! | `debug-images`    | ✅      | 🔌             |            |                                                                                          |
 //! | `log`             |         | 🔌             |            | Requires extra setup; See [`sentry-log`]'s documentation.                                |
 //! | `slog`            |         | 🔌             |            | Requires extra setup; See [`sentry-slog`]'s documentation.                               |
@@ -97,6 +98,7 @@
 //! ## Debugging/Testing
 //! - `anyhow`: Enables support for the `anyhow` crate.
 //! - `test`: Enables testing support.
+//! - `test-util`: An alias of `test`.
 //!
 //! ## Logging
 //! - `log`: Enables support for the `log` crate.
@@ -109,13 +111,21 @@
 //!   feature, and `default-features = false` must be set to completely disable building `native-tls`
 //!   dependencies.
 //! - `curl`: Enables the `curl` transport.
-//! - `ureq`: Enables the `ureq` transport using `rustls`.
+//! - `ureq`: Enables the `ureq` transport using `rustls`, which is then preferred over the others.
 //! - `ureq-native-tls`: Enables the `ureq` transport using `native-tls`.
+//! - `gzip`: Enables gzip compression of envelope requests, see
+//!   `ClientOptions::transport_compression`. Currently only used by the `ureq` transport.
+//! - `brotli`: Enables brotli compression of envelope requests, see
+//!   `ClientOptions::transport_compression`. Currently only used by the `ureq` transport.
+//! - `upload-progress`: Enables reporting the progress of envelope uploads, see
+//!   `ClientOptions::on_upload_progress`. Currently only used by the `ureq` transport.
 //!
 //! ## Integrations
 //! - `actix`: Enables support for the `actix-web` crate.
 //! - `tower`: Enables support for the `tower` crate and those using it.
 //! - `tracing`: Enables support for the `tracing` crate and those using it.
+//!   The transports then also record what they send as `tracing` debug events
+//!   targeting `sentry::transport`.
 //! - `opentelemetry`: Enables support for the `opentelemetry` and `opentelemetry-sdk` crates.
 
 #![doc(html_favicon_url = "https://sentry-brand.storage.googleapis.com/favicon.ico")]
@@ -136,7 +146,7 @@ pub use sentry_core::*;
 
 // added public API
 pub use crate::defaults::apply_defaults;
-pub use crate::init::{init, ClientInitGuard};
+pub use crate::init::{init, try_init, ClientInitGuard};
 
 /// Available Sentry Integrations.
 ///
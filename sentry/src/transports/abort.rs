@@ -0,0 +1,95 @@
+use std::io;
+use std::time::Duration;
+
+use ureq::unversioned::transport::time::Duration as UreqDuration;
+use ureq::unversioned::transport::{Buffers, ConnectionDetails, Connector, NextTimeout, Transport};
+use ureq::Error;
+
+use super::thread::Interrupt;
+
+/// How long waiting for the response goes on before checking for an abort.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Makes the connections of the connector it is chained to fail their
+/// requests once the `interrupt` aborts, closing them.
+#[derive(Debug)]
+pub struct AbortConnector {
+    pub interrupt: Interrupt,
+}
+
+impl<In: Transport> Connector<In> for AbortConnector {
+    type Out = AbortTransport<In>;
+
+    fn connect(
+        &self,
+        _details: &ConnectionDetails,
+        chained: Option<In>,
+    ) -> Result<Option<Self::Out>, Error> {
+        Ok(chained.map(|inner| AbortTransport {
+            inner,
+            interrupt: self.interrupt.clone(),
+        }))
+    }
+}
+
+#[derive(Debug)]
+pub struct AbortTransport<T> {
+    inner: T,
+    interrupt: Interrupt,
+}
+
+impl<T> AbortTransport<T> {
+    fn check(&self) -> Result<(), Error> {
+        if self.interrupt.is_aborted() {
+            return Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "the transport shut down before the request completed",
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+impl<T: Transport> Transport for AbortTransport<T> {
+    fn buffers(&mut self) -> &mut dyn Buffers {
+        self.inner.buffers()
+    }
+
+    fn transmit_output(&mut self, amount: usize, timeout: NextTimeout) -> Result<(), Error> {
+        self.check()?;
+        self.inner.transmit_output(amount, timeout)
+    }
+
+    fn await_input(&mut self, timeout: NextTimeout) -> Result<bool, Error> {
+        // Wait in short slices, to notice an abort while the server stalls.
+        let mut remaining = timeout.after;
+        loop {
+            self.check()?;
+            let slice = match remaining {
+                UreqDuration::Exact(left) if left <= POLL_INTERVAL => remaining,
+                _ => UreqDuration::Exact(POLL_INTERVAL),
+            };
+            let next = NextTimeout {
+                after: slice,
+                reason: timeout.reason,
+            };
+            match self.inner.await_input(next) {
+                Err(Error::Timeout(_)) if slice != remaining => {
+                    if let UreqDuration::Exact(left) = remaining {
+                        remaining = UreqDuration::Exact(left - POLL_INTERVAL);
+                    }
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn is_open(&mut self) -> bool {
+        !self.interrupt.is_aborted() && self.inner.is_open()
+    }
+
+    fn is_tls(&self) -> bool {
+        self.inner.is_tls()
+    }
+}
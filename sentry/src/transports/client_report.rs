@@ -0,0 +1,133 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use crate::protocol::{ClientReport, DataCategory, DiscardReason, DiscardedEvent, EnvelopeItem};
+use crate::Envelope;
+
+/// How often the accumulated client reports are sent.
+pub const CLIENT_REPORT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The number of discarded events, keyed by reason and category.
+type DiscardedCounts = BTreeMap<(DiscardReason, DataCategory), u64>;
+
+/// Accumulates the events discarded by a transport, to be sent as a [`ClientReport`].
+///
+/// Cloning it yields a handle to the same counts.
+#[derive(Clone, Debug, Default)]
+pub struct ClientReports {
+    discarded: Option<Arc<Mutex<DiscardedCounts>>>,
+}
+
+impl ClientReports {
+    /// Creates a new accumulator, which does nothing unless `enabled` is set.
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            discarded: enabled.then(Default::default),
+        }
+    }
+
+    /// Records that a single envelope `item` was discarded.
+    pub fn record_item(&self, reason: DiscardReason, item: &EnvelopeItem) {
+        // losing a client report is not worth reporting, and they have no category
+        let (Some(discarded), Some(category)) = (&self.discarded, item.data_category()) else {
+            return;
+        };
+        *discarded
+            .lock()
+            .unwrap()
+            .entry((reason, category))
+            .or_default() += item.quantity();
+    }
+
+    /// Records that all the items of `envelope` were discarded.
+    pub fn record_envelope(&self, reason: DiscardReason, envelope: &Envelope) {
+        let mut items = envelope.items().peekable();
+        if items.peek().is_none() {
+            // raw envelopes don't expose their items
+            self.record_item(reason, &EnvelopeItem::Raw);
+        }
+        for item in items {
+            self.record_item(reason, item);
+        }
+    }
+
+    /// Takes the counts accumulated so far as an envelope containing a [`ClientReport`].
+    ///
+    /// Returns [`None`] if nothing was discarded.
+    pub fn take_envelope(&self) -> Option<Envelope> {
+        let discarded = std::mem::take(&mut *self.discarded.as_ref()?.lock().unwrap());
+        if discarded.is_empty() {
+            return None;
+        }
+        let discarded_events = discarded
+            .into_iter()
+            .map(|((reason, category), quantity)| DiscardedEvent {
+                reason,
+                category,
+                quantity,
+            })
+            .collect();
+
+        let mut envelope = Envelope::new();
+        envelope.add_item(ClientReport {
+            timestamp: SystemTime::now(),
+            discarded_events,
+        });
+        Some(envelope)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{Attachment, Event};
+
+    #[test]
+    fn test_client_reports() {
+        let reports = ClientReports::new(true);
+        assert!(reports.take_envelope().is_none());
+
+        let mut envelope: Envelope = Event::default().into();
+        envelope.add_item(Attachment {
+            buffer: vec![0; 10],
+            ..Default::default()
+        });
+        reports.record_envelope(DiscardReason::QueueOverflow, &envelope);
+        reports.record_envelope(DiscardReason::QueueOverflow, &Event::default().into());
+        reports.record_item(DiscardReason::RateLimitBackoff, &Event::default().into());
+
+        let envelope = reports.take_envelope().unwrap();
+        let Some(EnvelopeItem::ClientReport(report)) = envelope.items().next() else {
+            panic!("expected a client report");
+        };
+        assert_eq!(
+            report.discarded_events,
+            [
+                DiscardedEvent {
+                    reason: DiscardReason::QueueOverflow,
+                    category: DataCategory::Error,
+                    quantity: 2,
+                },
+                DiscardedEvent {
+                    reason: DiscardReason::QueueOverflow,
+                    category: DataCategory::Attachment,
+                    quantity: 10,
+                },
+                DiscardedEvent {
+                    reason: DiscardReason::RateLimitBackoff,
+                    category: DataCategory::Error,
+                    quantity: 1,
+                },
+            ]
+        );
+        assert!(reports.take_envelope().is_none());
+    }
+
+    #[test]
+    fn test_disabled() {
+        let reports = ClientReports::new(false);
+        reports.record_envelope(DiscardReason::QueueOverflow, &Event::default().into());
+        assert!(reports.take_envelope().is_none());
+    }
+}
@@ -0,0 +1,239 @@
+use std::borrow::Cow;
+#[cfg(any(feature = "gzip", feature = "brotli"))]
+use std::io::Write;
+
+#[cfg(feature = "gzip")]
+use flate2::write::GzEncoder;
+
+use crate::{sentry_debug, Compression};
+
+/// The default gzip level, which is the one of zlib.
+const GZIP_LEVEL: u32 = 6;
+
+/// The default brotli quality level, trading some ratio for a lot of speed
+/// over the maximum of 11.
+const BROTLI_QUALITY: u32 = 5;
+
+/// The base 2 logarithm of the brotli window size.
+#[cfg(feature = "brotli")]
+const BROTLI_WINDOW: u32 = 22;
+
+/// Resolves the `compression` to the one that is actually available.
+///
+/// Brotli falls back to gzip and gzip to no compression when the feature
+/// implementing them is not enabled.
+pub fn resolve(compression: Compression) -> Compression {
+    match compression {
+        Compression::Br if !cfg!(feature = "brotli") => {
+            sentry_debug!("brotli compression requires the `brotli` feature, trying gzip");
+            resolve(Compression::Gzip)
+        }
+        Compression::Gzip if !cfg!(feature = "gzip") => {
+            sentry_debug!("gzip compression requires the `gzip` feature, sending uncompressed");
+            Compression::None
+        }
+        compression => compression,
+    }
+}
+
+/// Returns the level to compress with the resolved `compression` at, given
+/// the configured `level`.
+///
+/// Levels out of the range of the encoding are clamped to it.
+pub fn level(compression: Compression, level: Option<u32>) -> u32 {
+    let (default, max) = match compression {
+        Compression::Br => (BROTLI_QUALITY, 11),
+        _ => (GZIP_LEVEL, 9),
+    };
+    match level {
+        Some(level) if level > max => {
+            sentry_debug!(
+                "compression level {} is out of range, using {} instead",
+                level,
+                max
+            );
+            max
+        }
+        Some(level) => level,
+        None => default,
+    }
+}
+
+/// Compresses a serialized envelope `body` with the given `compression` at
+/// the given `level`, both of which should have been [resolved](resolve) first.
+///
+/// Returns the body that should be sent, along with the value of the
+/// `Content-Encoding` header to send with it, if any. When compressing fails,
+/// the body is returned unchanged.
+#[cfg_attr(
+    not(any(feature = "gzip", feature = "brotli")),
+    allow(unused_variables)
+)]
+pub fn compress(
+    body: &[u8],
+    compression: Compression,
+    level: u32,
+) -> (Cow<'_, [u8]>, Option<&'static str>) {
+    match compression {
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => encoded(body, gzip(body, level), "gzip"),
+        #[cfg(feature = "brotli")]
+        Compression::Br => encoded(body, brotli(body, level), "br"),
+        _ => (Cow::Borrowed(body), None),
+    }
+}
+
+/// Returns the `compressed` body along with its `encoding`, or the original
+/// `body` if compressing it failed.
+#[cfg(any(feature = "gzip", feature = "brotli"))]
+fn encoded<'b>(
+    body: &'b [u8],
+    compressed: std::io::Result<Vec<u8>>,
+    encoding: &'static str,
+) -> (Cow<'b, [u8]>, Option<&'static str>) {
+    match compressed {
+        Ok(compressed) => (Cow::Owned(compressed), Some(encoding)),
+        Err(err) => {
+            sentry_debug!("Failed to compress envelope: {}", err);
+            (Cow::Borrowed(body), None)
+        }
+    }
+}
+
+#[cfg(feature = "gzip")]
+fn gzip(body: &[u8], level: u32) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(
+        Vec::with_capacity(body.len() / 2),
+        flate2::Compression::new(level),
+    );
+    encoder.write_all(body)?;
+    encoder.finish()
+}
+
+#[cfg(feature = "brotli")]
+fn brotli(body: &[u8], level: u32) -> std::io::Result<Vec<u8>> {
+    let mut encoder = brotli::CompressorWriter::new(
+        Vec::with_capacity(body.len() / 2),
+        4096,
+        level,
+        BROTLI_WINDOW,
+    );
+    encoder.write_all(body)?;
+    encoder.flush()?;
+    Ok(encoder.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(any(feature = "gzip", feature = "brotli"))]
+    fn envelope_body() -> Vec<u8> {
+        use crate::protocol::{Event, Level};
+        use crate::Envelope;
+
+        let envelope: Envelope = Event {
+            message: Some("Hello World!".repeat(100)),
+            level: Level::Warning,
+            ..Default::default()
+        }
+        .into();
+        let mut body = Vec::new();
+        envelope.to_writer(&mut body).unwrap();
+        body
+    }
+
+    #[test]
+    fn test_disabled() {
+        let (body, encoding) = compress(b"hello", Compression::None, GZIP_LEVEL);
+        assert_eq!(&*body, b"hello");
+        assert_eq!(encoding, None);
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(resolve(Compression::None), Compression::None);
+        let gzip = if cfg!(feature = "gzip") {
+            Compression::Gzip
+        } else {
+            Compression::None
+        };
+        assert_eq!(resolve(Compression::Gzip), gzip);
+        let br = if cfg!(feature = "brotli") {
+            Compression::Br
+        } else {
+            gzip
+        };
+        assert_eq!(resolve(Compression::Br), br);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_gzip_roundtrip() {
+        use std::io::Read;
+
+        let original = envelope_body();
+        let (body, encoding) = compress(&original, Compression::Gzip, GZIP_LEVEL);
+        assert_eq!(encoding, Some("gzip"));
+        assert!(body.len() < original.len());
+
+        let mut decoded = Vec::new();
+        flate2::read::GzDecoder::new(&*body)
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, original);
+    }
+
+    #[cfg(feature = "brotli")]
+    #[test]
+    fn test_brotli_roundtrip() {
+        use std::io::Read;
+
+        let original = envelope_body();
+        let (body, encoding) = compress(&original, Compression::Br, BROTLI_QUALITY);
+        assert_eq!(encoding, Some("br"));
+        assert!(body.len() < original.len());
+
+        let mut decoded = Vec::new();
+        brotli::Decompressor::new(&*body, 4096)
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, original);
+    }
+
+    #[test]
+    fn test_level() {
+        assert_eq!(level(Compression::Gzip, None), 6);
+        assert_eq!(level(Compression::Br, None), 5);
+        assert_eq!(level(Compression::Gzip, Some(1)), 1);
+        assert_eq!(level(Compression::Gzip, Some(42)), 9);
+        assert_eq!(level(Compression::Br, Some(42)), 11);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_gzip_levels() {
+        use std::io::Read;
+
+        let original = envelope_body();
+        let decode = |body: &[u8]| {
+            let mut decoded = Vec::new();
+            flate2::read::GzDecoder::new(body)
+                .read_to_end(&mut decoded)
+                .unwrap();
+            decoded
+        };
+        let sizes: Vec<_> = [0, 1, 9]
+            .into_iter()
+            .map(|level| {
+                let (body, _) = compress(&original, Compression::Gzip, level);
+                assert_eq!(decode(&body), original);
+                body.len()
+            })
+            .collect();
+        // level 0 only stores the envelope, adding some framing
+        assert!(sizes[0] > original.len());
+        assert!(sizes[1] < original.len());
+        assert!(sizes[2] <= sizes[1]);
+    }
+}
@@ -1,18 +1,58 @@
 use std::io::{Cursor, Read};
-use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
 
-use curl::easy::Easy as CurlClient;
+use curl::easy::{Easy as CurlClient, IpResolve};
 
-use super::thread::TransportThread;
+use super::client_report::ClientReports;
+use super::ratelimit::RateLimiter;
+use super::thread::{Interrupt, TransportThread};
+use super::{
+    add_trace_header, before_send_envelope, bypasses_proxy, endpoint_scheme,
+    warn_accept_invalid_certs,
+};
 
-use crate::{sentry_debug, types::Scheme, ClientOptions, Envelope, Transport};
+use crate::protocol::{DataCategory, DiscardReason};
+use crate::{
+    sentry_debug, types::Scheme, AddressFamily, BeforeCallback, ClientOptions, Envelope, Transport,
+    TransportError, TransportErrorKind,
+};
+
+/// Configures `handle` to connect through the proxy at `url`.
+///
+/// The `proxy_username` and `proxy_password` options are used unless the URL
+/// already contains credentials.
+fn configure_proxy(
+    handle: &mut CurlClient,
+    url: &str,
+    username: Option<&str>,
+    password: Option<&str>,
+) {
+    if let Err(err) = handle.proxy(url) {
+        sentry_debug!("invalid proxy: {:?}", err);
+        return;
+    }
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let has_credentials = rest.split('/').next().unwrap_or_default().contains('@');
+    if let (Some(username), false) = (username, has_credentials) {
+        let result = handle
+            .proxy_username(username)
+            .and_then(|_| handle.proxy_password(password.unwrap_or_default()));
+        if let Err(err) = result {
+            sentry_debug!("invalid proxy credentials: {:?}", err);
+        }
+    }
+}
 
 /// A [`Transport`] that sends events via the [`curl`] library.
 ///
 /// This is enabled by the `curl` feature flag.
 #[cfg_attr(doc_cfg, doc(cfg(feature = "curl")))]
 pub struct CurlHttpTransport {
-    thread: TransportThread,
+    /// The worker sending envelopes, or `None` if there is no DSN to send them to.
+    thread: Option<TransportThread>,
+    before_send_envelope: Option<BeforeCallback<Envelope>>,
+    last_error: Arc<Mutex<Option<TransportError>>>,
 }
 
 impl CurlHttpTransport {
@@ -26,44 +66,119 @@ impl CurlHttpTransport {
         Self::new_internal(options, Some(client))
     }
 
+    /// Sends the envelope and blocks until that is done, returning why it was
+    /// not accepted, if it wasn't.
+    ///
+    /// The envelope goes through the same rate limits and retries as the ones
+    /// given to [`Transport::send_envelope`], after the ones queued before it.
+    /// Envelopes dropped by `before_send_envelope` or attachment sampling are
+    /// not considered a failure.
+    pub fn send_envelope_sync(&self, envelope: Envelope) -> Result<(), TransportError> {
+        let Some(ref thread) = self.thread else {
+            return Err(TransportError::new(
+                TransportErrorKind::Discarded,
+                "no DSN is configured",
+            ));
+        };
+        match before_send_envelope(self.before_send_envelope.as_ref(), envelope) {
+            Some(envelope) => thread.send_sync(envelope),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the number of envelope sends which panicked so far.
+    ///
+    /// Each of those envelopes was dropped, while the transport kept sending
+    /// the ones after it. The most recent panic is also reported by
+    /// [`Transport::last_error`].
+    pub fn panics(&self) -> usize {
+        self.thread.as_ref().map_or(0, TransportThread::panics)
+    }
+
     fn new_internal(options: &ClientOptions, client: Option<CurlClient>) -> Self {
         let client = client.unwrap_or_else(CurlClient::new);
         let http_proxy = options.http_proxy.as_ref().map(ToString::to_string);
         let https_proxy = options.https_proxy.as_ref().map(ToString::to_string);
-        let dsn = options.dsn.as_ref().unwrap();
-        let user_agent = options.user_agent.clone();
-        let auth = dsn.to_auth(Some(&user_agent)).to_string();
-        let url = dsn.envelope_api_url().to_string();
-        let scheme = dsn.scheme();
+        let proxy_username = options.proxy_username.as_ref().map(ToString::to_string);
+        let proxy_password = options.proxy_password.as_ref().map(ToString::to_string);
+        let proxy_tunnel = options.proxy_tunnel;
+        let last_error = Arc::new(Mutex::new(None));
+        let Some((url, auth)) = options.envelope_endpoint() else {
+            sentry_debug!("No DSN configured, discarding all envelopes");
+            return Self {
+                thread: None,
+                before_send_envelope: None,
+                last_error,
+            };
+        };
+        let scheme = endpoint_scheme(&url);
         let accept_invalid_certs = options.accept_invalid_certs;
+        warn_accept_invalid_certs(options);
+        let no_proxy = bypasses_proxy(options);
+        let ip_resolve = match options.address_family {
+            AddressFamily::Auto => IpResolve::Any,
+            AddressFamily::Ipv4 => IpResolve::V4,
+            AddressFamily::Ipv6 => IpResolve::V6,
+        };
+        // an empty port matches requests to any port of the host
+        let connect_to: Vec<_> = options
+            .resolve_override
+            .iter()
+            .map(|(host, addr)| format!("{host}::{addr}"))
+            .collect();
+        let dynamic_sampling_context_fn = options.dynamic_sampling_context_fn.clone();
 
         let mut handle = client;
-        let thread = TransportThread::new(move |envelope, rl| {
+        let mut send = move |mut envelope: Envelope,
+                             rl: &mut RateLimiter,
+                             interrupt: &Interrupt,
+                             reports: &ClientReports| {
             handle.reset();
             handle.url(&url).unwrap();
             handle.custom_request("POST").unwrap();
+            handle.ip_resolve(ip_resolve).unwrap();
+            if !connect_to.is_empty() {
+                let mut list = curl::easy::List::new();
+                for entry in &connect_to {
+                    list.append(entry).unwrap();
+                }
+                handle.connect_to(list).unwrap();
+            }
 
             if accept_invalid_certs {
                 handle.ssl_verify_host(false).unwrap();
                 handle.ssl_verify_peer(false).unwrap();
             }
 
-            match (scheme, &http_proxy, &https_proxy) {
-                (Scheme::Https, _, Some(proxy)) => {
-                    if let Err(err) = handle.proxy(proxy) {
-                        sentry_debug!("invalid proxy: {:?}", err);
-                    }
-                }
-                (_, Some(proxy), _) => {
-                    if let Err(err) = handle.proxy(proxy) {
-                        sentry_debug!("invalid proxy: {:?}", err);
-                    }
+            let proxy = match (scheme, &http_proxy, &https_proxy) {
+                _ if no_proxy => None,
+                (Scheme::Https, _, Some(proxy)) => Some(proxy),
+                (_, Some(proxy), _) => Some(proxy),
+                _ => None,
+            };
+            if let Some(proxy) = proxy {
+                configure_proxy(
+                    &mut handle,
+                    proxy,
+                    proxy_username.as_deref(),
+                    proxy_password.as_deref(),
+                );
+                if proxy_tunnel {
+                    handle.http_proxy_tunnel(true).unwrap();
                 }
-                _ => {}
             }
 
             let mut body = Vec::new();
-            envelope.to_writer(&mut body).unwrap();
+            envelope.set_sent_at(SystemTime::now());
+            add_trace_header(&mut envelope, dynamic_sampling_context_fn.as_deref());
+            if let Err(err) = envelope.to_writer(&mut body) {
+                sentry_debug!("Failed to serialize envelope: {}", err);
+                reports.record_envelope(DiscardReason::InternalSdkError, &envelope);
+                return Err(TransportError::new(
+                    TransportErrorKind::Serialization,
+                    format!("failed to serialize envelope: {err}"),
+                ));
+            }
             let mut body = Cursor::new(body);
 
             let mut retry_after = None;
@@ -77,6 +192,12 @@ impl CurlHttpTransport {
             handle
                 .read_function(move |buf| Ok(body.read(buf).unwrap_or(0)))
                 .unwrap();
+            // cancel the transfer once shutting down timed out
+            let interrupt = interrupt.clone();
+            handle.progress(true).unwrap();
+            handle
+                .progress_function(move |_, _, _, _| !interrupt.is_aborted())
+                .unwrap();
             handle.verbose(true).unwrap();
             handle
                 .debug_function(move |info, data| {
@@ -90,7 +211,7 @@ impl CurlHttpTransport {
                 })
                 .unwrap();
 
-            {
+            let performed = {
                 let mut handle = handle.transfer();
                 let retry_after_setter = &mut retry_after;
                 let sentry_header_setter = &mut sentry_header;
@@ -110,7 +231,14 @@ impl CurlHttpTransport {
                         true
                     })
                     .unwrap();
-                handle.perform().ok();
+                handle.perform()
+            };
+            if let Err(err) = performed {
+                sentry_debug!("Failed to send envelope: {}", err);
+                return Err(TransportError::new(
+                    TransportErrorKind::Network,
+                    err.to_string(),
+                ));
             }
 
             match handle.response_code() {
@@ -120,27 +248,206 @@ impl CurlHttpTransport {
                     } else if let Some(retry_after) = retry_after {
                         rl.update_from_retry_after(&retry_after);
                     } else if response_code == 429 {
-                        rl.update_from_429();
+                        rl.update_from_429(Some(&envelope));
+                    }
+                    if (200..300).contains(&response_code) {
+                        Ok(())
+                    } else {
+                        Err(TransportError::new(
+                            TransportErrorKind::Http(response_code as u16),
+                            format!("server responded with status {response_code}"),
+                        ))
                     }
                 }
                 Err(err) => {
                     sentry_debug!("Failed to send envelope: {}", err);
+                    Err(TransportError::new(
+                        TransportErrorKind::Network,
+                        err.to_string(),
+                    ))
                 }
             }
+        };
+        let worker_last_error = last_error.clone();
+        let thread = TransportThread::new(options, move |envelope, rl, interrupt, reports| {
+            let result = send(envelope, rl, interrupt, reports);
+            if let Err(ref err) = result {
+                *worker_last_error.lock().unwrap() = Some(err.clone());
+            }
+            result
         });
-        Self { thread }
+        Self {
+            thread: Some(thread),
+            before_send_envelope: options.before_send_envelope.clone(),
+            last_error,
+        }
     }
 }
 
 impl Transport for CurlHttpTransport {
     fn send_envelope(&self, envelope: Envelope) {
-        self.thread.send(envelope)
+        let Some(ref thread) = self.thread else {
+            return;
+        };
+        if let Some(envelope) = before_send_envelope(self.before_send_envelope.as_ref(), envelope) {
+            thread.send(envelope);
+        }
     }
     fn flush(&self, timeout: Duration) -> bool {
-        self.thread.flush(timeout)
+        match self.thread {
+            Some(ref thread) => thread.flush(timeout),
+            None => true,
+        }
+    }
+
+    fn flush_remaining(&self, timeout: Duration) -> usize {
+        match self.thread {
+            Some(ref thread) => thread.flush_remaining(timeout),
+            None => 0,
+        }
+    }
+
+    fn flush_category(&self, category: DataCategory, timeout: Duration) -> bool {
+        match self.thread {
+            Some(ref thread) => thread.flush_category(category, timeout),
+            None => true,
+        }
     }
 
     fn shutdown(&self, timeout: Duration) -> bool {
-        self.flush(timeout)
+        match self.thread {
+            Some(ref thread) => thread.shutdown(timeout),
+            None => true,
+        }
+    }
+
+    fn clear_rate_limits(&self) {
+        if let Some(ref thread) = self.thread {
+            thread.clear_rate_limits();
+        }
+    }
+
+    fn time_until_allowed(&self, category: DataCategory) -> Option<Duration> {
+        self.thread.as_ref()?.time_until_allowed(category)
+    }
+
+    fn last_error(&self) -> Option<TransportError> {
+        let last_error = self.last_error.lock().unwrap().clone();
+        let last_panic = self.thread.as_ref().and_then(TransportThread::last_panic);
+        last_error
+            .into_iter()
+            .chain(last_panic)
+            .max_by_key(|error| error.timestamp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    use super::*;
+    use crate::protocol::Event;
+
+    /// Sends an event through a proxy which records the request headers.
+    fn proxied_headers(proxy_url: impl FnOnce(&str) -> String, options: ClientOptions) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut headers = String::new();
+            for line in BufReader::new(stream).lines() {
+                let line = line.unwrap();
+                if line.is_empty() {
+                    break;
+                }
+                headers.push_str(&line);
+                headers.push('\n');
+            }
+            sender.send(headers).unwrap();
+        });
+
+        let options = ClientOptions {
+            dsn: Some("http://public@sentry.invalid/1".parse().unwrap()),
+            http_proxy: Some(proxy_url(&address).into()),
+            ..options
+        };
+        let transport = CurlHttpTransport::new(&options);
+        transport.send_envelope(Event::default().into());
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap()
+    }
+
+    #[test]
+    fn test_proxy_credentials() {
+        let options = || ClientOptions {
+            proxy_username: Some("user".into()),
+            proxy_password: Some("secret".into()),
+            ..Default::default()
+        };
+
+        let headers = proxied_headers(|address| format!("http://{address}"), options());
+        assert!(headers.starts_with("POST http://sentry.invalid/api/1/envelope/ "));
+        // base64 of `user:secret`
+        assert!(headers.contains("Proxy-Authorization: Basic dXNlcjpzZWNyZXQ=\n"));
+
+        let headers = proxied_headers(|address| format!("http://other:pass@{address}"), options());
+        // base64 of `other:pass`
+        assert!(headers.contains("Proxy-Authorization: Basic b3RoZXI6cGFzcw==\n"));
+    }
+
+    #[test]
+    fn test_proxy_tunnel() {
+        let options = ClientOptions {
+            proxy_tunnel: true,
+            ..Default::default()
+        };
+        let headers = proxied_headers(|address| format!("http://{address}"), options);
+        assert!(
+            headers.starts_with("CONNECT sentry.invalid:80 "),
+            "{headers}"
+        );
+    }
+
+    #[test]
+    fn test_last_error() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let dsn = format!("http://public@{}/1", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut length = 0;
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                if let Some((key, value)) = line.split_once(':') {
+                    if key.eq_ignore_ascii_case("content-length") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+                line.clear();
+            }
+            reader.read_exact(&mut vec![0; length]).unwrap();
+            write!(
+                reader.get_mut(),
+                "HTTP/1.1 400 Bad Request\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+            )
+            .unwrap();
+        });
+
+        let options = ClientOptions {
+            dsn: Some(dsn.parse().unwrap()),
+            send_client_reports: false,
+            ..Default::default()
+        };
+        let transport = CurlHttpTransport::new(&options);
+        assert_eq!(transport.last_error(), None);
+
+        let error = transport
+            .send_envelope_sync(Event::default().into())
+            .unwrap_err();
+        assert_eq!(error.kind, TransportErrorKind::Http(400));
+        assert_eq!(transport.last_error(), Some(error));
     }
 }
@@ -0,0 +1,124 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{sentry_debug, ClientOptions, Envelope, Transport};
+
+/// A [`Transport`] that writes envelopes to files in a directory instead of
+/// sending them.
+///
+/// Every envelope is serialized into its own `.envelope` file, named after the
+/// time it was written so that sorting the names yields the order they were
+/// sent in. As nothing is sent, there are never any rate limits to apply.
+///
+/// This is used in place of the default transport when
+/// [`ClientOptions::dry_run_dir`] is set.
+pub struct DryRunTransport {
+    dir: PathBuf,
+    counter: AtomicUsize,
+    /// Files written since the last flush.
+    unsynced: Mutex<Vec<File>>,
+}
+
+impl DryRunTransport {
+    /// Creates a new Transport writing to the `dry_run_dir` of `options`,
+    /// or the current directory if there is none.
+    pub fn new(options: &ClientOptions) -> Self {
+        Self::with_dir(options.dry_run_dir.clone().unwrap_or_default())
+    }
+
+    /// Creates a new Transport writing to `dir`, creating it if needed.
+    pub fn with_dir(dir: impl Into<PathBuf>) -> Self {
+        let dir = dir.into();
+        if let Err(err) = fs::create_dir_all(&dir) {
+            sentry_debug!("Failed to create dry run directory: {}", err);
+        }
+        Self {
+            dir,
+            counter: AtomicUsize::new(0),
+            unsynced: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn write(&self, envelope: &Envelope) -> std::io::Result<File> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let counter = self.counter.fetch_add(1, Ordering::Relaxed);
+        let name = format!("{nanos:020}-{counter:010}-{}.envelope", process::id());
+
+        let mut writer = BufWriter::new(File::create(self.dir.join(name))?);
+        envelope.to_writer(&mut writer)?;
+        writer.flush()?;
+        writer.into_inner().map_err(|err| err.into_error())
+    }
+}
+
+impl Transport for DryRunTransport {
+    fn send_envelope(&self, envelope: Envelope) {
+        match self.write(&envelope) {
+            Ok(file) => self.unsynced.lock().unwrap().push(file),
+            Err(err) => sentry_debug!("Failed to write envelope: {}", err),
+        }
+    }
+
+    fn flush(&self, timeout: Duration) -> bool {
+        let _timeout = timeout;
+        let files = std::mem::take(&mut *self.unsynced.lock().unwrap());
+        files.iter().fold(true, |synced, file| {
+            if let Err(err) = file.sync_all() {
+                sentry_debug!("Failed to sync envelope: {}", err);
+                return false;
+            }
+            synced
+        })
+    }
+
+    fn shutdown(&self, timeout: Duration) -> bool {
+        // the files are closed once synced
+        self.flush(timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::Event;
+
+    #[test]
+    fn test_dry_run() {
+        let dir = std::env::temp_dir().join(format!("sentry-dry-run-test-{}", process::id()));
+        fs::remove_dir_all(&dir).ok();
+
+        let transport = DryRunTransport::new(&ClientOptions {
+            dry_run_dir: Some(dir.clone()),
+            ..Default::default()
+        });
+        let first: Envelope = Event {
+            message: Some("first".into()),
+            ..Default::default()
+        }
+        .into();
+        transport.send_envelope(first.clone());
+        transport.send_envelope(Event::default().into());
+        assert!(transport.flush(Duration::from_secs(1)));
+
+        let mut paths: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        paths.sort();
+        assert_eq!(paths.len(), 2);
+        let mut expected = Vec::new();
+        first.to_writer(&mut expected).unwrap();
+        assert_eq!(fs::read(&paths[0]).unwrap(), expected);
+        assert!(Envelope::from_path(&paths[1]).is_ok());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -19,19 +19,16 @@ impl EmbeddedSVCHttpTransport {
 impl EmbeddedSVCHttpTransport {
     fn send_envelope(
         &self,
-        envelope: sentry_core::Envelope,
+        mut envelope: sentry_core::Envelope,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let dsn = self
+        let (url, auth) = self
             .options
-            .dsn
-            .as_ref()
+            .envelope_endpoint()
             .ok_or_else(|| "No DSN specified")?;
-        let user_agent = &self.options.user_agent;
-        let auth = dsn.to_auth(Some(user_agent)).to_string();
         let headers = [("X-Sentry-Auth", auth.as_str())];
-        let url = dsn.envelope_api_url();
 
         let mut body = Vec::new();
+        envelope.set_sent_at(std::time::SystemTime::now());
         envelope.to_writer(&mut body)?;
 
         let config = esp_idf_svc::http::client::Configuration {
@@ -3,15 +3,45 @@
 //! This module exposes all transports that are compiled into the sentry
 //! library.  The `reqwest`, `curl`, and `ureq` features turn on these transports.
 
+/// Records an event of the transport together with some fields.
+///
+/// With the `tracing` feature this is a `tracing` debug event targeting
+/// `sentry::transport`, which `sentry-tracing` never sends to Sentry. Otherwise
+/// it is logged with `sentry_debug!`.
+#[allow(unused_macros)]
+macro_rules! transport_event {
+    ($message:literal $(, $field:ident = $value:expr)* $(,)?) => {{
+        #[cfg(feature = "tracing")]
+        ::tracing::debug!(target: "sentry::transport", $($field = $value,)* $message);
+        #[cfg(not(feature = "tracing"))]
+        $crate::sentry_debug!(
+            concat!($message $(, " ", stringify!($field), "={}")*)
+            $(, $value)*
+        );
+    }};
+}
+
+#[cfg(any(feature = "reqwest", feature = "curl", feature = "ureq"))]
+use crate::{sentry_debug, BeforeCallback, DynamicSamplingContextFn, Envelope};
 use crate::{ClientOptions, Transport, TransportFactory};
 use std::sync::Arc;
 
+#[cfg(feature = "ureq")]
+mod abort;
+#[cfg(any(feature = "curl", feature = "ureq", feature = "reqwest"))]
+mod client_report;
+#[cfg(feature = "ureq")]
+mod compression;
 #[cfg(feature = "httpdate")]
 mod ratelimit;
+#[cfg(feature = "ureq")]
+mod spool;
 #[cfg(any(feature = "curl", feature = "ureq"))]
 mod thread;
 #[cfg(feature = "reqwest")]
 mod tokio_thread;
+#[cfg(all(unix, feature = "ureq"))]
+mod uds;
 
 #[cfg(feature = "reqwest")]
 mod reqwest;
@@ -28,29 +58,136 @@ mod curl;
 #[cfg(feature = "curl")]
 pub use self::curl::CurlHttpTransport;
 
+mod dry_run;
+pub use self::dry_run::DryRunTransport;
+mod multiplex;
+pub use self::multiplex::MultiplexTransport;
+mod tee;
+pub use self::tee::TeeTransport;
+
 #[cfg(feature = "ureq")]
 mod ureq;
 #[cfg(feature = "ureq")]
 pub use self::ureq::UreqHttpTransport;
 
-#[cfg(feature = "reqwest")]
-type DefaultTransport = ReqwestHttpTransport;
+/// Passes `envelope` through the `before_send_envelope` callback, if there is one.
+#[cfg(any(feature = "reqwest", feature = "curl", feature = "ureq"))]
+fn before_send_envelope(
+    callback: Option<&BeforeCallback<Envelope>>,
+    envelope: Envelope,
+) -> Option<Envelope> {
+    let Some(callback) = callback else {
+        return Some(envelope);
+    };
+    let envelope = callback(envelope);
+    if envelope.is_none() {
+        sentry_debug!("Envelope was discarded by before_send_envelope");
+    }
+    envelope
+}
+
+/// Sets the `trace` header of the `envelope` from the `dynamic_sampling_context_fn`,
+/// unless it has one already.
+#[cfg(any(feature = "reqwest", feature = "curl", feature = "ureq"))]
+fn add_trace_header(
+    envelope: &mut Envelope,
+    dynamic_sampling_context_fn: Option<&DynamicSamplingContextFn>,
+) {
+    if envelope.trace().is_some() {
+        return;
+    }
+    if let Some(trace) = dynamic_sampling_context_fn.and_then(|f| f(envelope)) {
+        envelope.set_trace(trace);
+    }
+}
+
+/// Returns the scheme of the `url` envelopes are sent to.
+///
+/// This decides whether the `https_proxy` or the `http_proxy` is used, and
+/// differs from the scheme of the DSN if the `endpoint_override` does.
+#[cfg(any(feature = "curl", feature = "ureq"))]
+fn endpoint_scheme(url: &str) -> crate::types::Scheme {
+    match crate::protocol::Url::parse(url) {
+        Ok(url) if url.scheme() == "https" => crate::types::Scheme::Https,
+        _ => crate::types::Scheme::Http,
+    }
+}
+
+/// Warns that TLS certificates are not verified if `accept_invalid_certs` is set.
+#[cfg(any(feature = "reqwest", feature = "curl", feature = "ureq"))]
+fn warn_accept_invalid_certs(options: &ClientOptions) {
+    if options.accept_invalid_certs {
+        sentry_debug!(
+            "WARNING: TLS certificate verification is disabled by `accept_invalid_certs`, \
+             this must not be used in production"
+        );
+    }
+}
+
+/// Logs the body of a response, which was `truncated` to the
+/// `transport_log_body_limit`.
+#[cfg(any(feature = "reqwest", feature = "ureq"))]
+fn log_response_body(body: &[u8], truncated: bool) {
+    let marker = if truncated { " (truncated)" } else { "" };
+    sentry_debug!(
+        "Get response: `{}`{}",
+        String::from_utf8_lossy(body),
+        marker
+    );
+}
+
+/// Returns whether the host envelopes are sent to is exempt from the proxy by `no_proxy`.
+#[cfg(any(feature = "reqwest", feature = "curl", feature = "ureq"))]
+fn bypasses_proxy(options: &ClientOptions) -> bool {
+    let Some(url) = options.envelope_url() else {
+        return false;
+    };
+    let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+    options.no_proxy.iter().any(|entry| {
+        let entry = entry.trim().to_ascii_lowercase();
+        match entry.strip_prefix('.') {
+            _ if entry == "*" => true,
+            Some(domain) => host == domain || host.ends_with(&entry),
+            None => host == entry,
+        }
+    })
+}
+
+/// Creates a check-in for the monitor `slug`, for the tests of the transports.
+#[cfg(all(test, feature = "httpdate"))]
+fn check_in(slug: &str) -> crate::protocol::MonitorCheckIn {
+    crate::protocol::MonitorCheckIn {
+        check_in_id: Default::default(),
+        monitor_slug: slug.into(),
+        status: crate::protocol::MonitorCheckInStatus::Ok,
+        environment: None,
+        duration: None,
+        monitor_config: None,
+    }
+}
 
 #[cfg(all(
-    feature = "curl",
+    feature = "ureq",
     not(all(target_os = "espidf", feature = "embedded-svc-http")),
-    not(feature = "reqwest"),
-    not(feature = "ureq")
 ))]
-type DefaultTransport = CurlHttpTransport;
+type DefaultTransport = UreqHttpTransport;
 
 #[cfg(all(
-    feature = "ureq",
+    feature = "reqwest",
+    any(
+        not(feature = "ureq"),
+        all(target_os = "espidf", feature = "embedded-svc-http")
+    ),
+))]
+type DefaultTransport = ReqwestHttpTransport;
+
+#[cfg(all(
+    feature = "curl",
     not(all(target_os = "espidf", feature = "embedded-svc-http")),
     not(feature = "reqwest"),
-    not(feature = "curl"),
+    not(feature = "ureq")
 ))]
-type DefaultTransport = UreqHttpTransport;
+type DefaultTransport = CurlHttpTransport;
 
 #[cfg(all(
     target_os = "espidf",
@@ -73,13 +210,19 @@ pub type HttpTransport = DefaultTransport;
 /// Creates the default HTTP transport.
 ///
 /// This is the default value for `transport` on the client options.  It
-/// creates a `HttpTransport`.  If no http transport was compiled into the
-/// library it will panic on transport creation.
+/// creates a `HttpTransport`, or a [`DryRunTransport`] if `dry_run_dir` is set.
+/// The `HttpTransport` is the `ureq` transport when its feature is enabled,
+/// followed by the `reqwest`, `curl` and `embedded-svc-http` transports.
+/// If no http transport was compiled into the library it will panic on
+/// transport creation.
 #[derive(Clone)]
 pub struct DefaultTransportFactory;
 
 impl TransportFactory for DefaultTransportFactory {
     fn create_transport(&self, options: &ClientOptions) -> Arc<dyn Transport> {
+        if options.dry_run_dir.is_some() {
+            return Arc::new(DryRunTransport::new(options));
+        }
         #[cfg(any(
             all(target_os = "espidf", feature = "embedded-svc-http"),
             feature = "reqwest",
@@ -101,3 +244,49 @@ impl TransportFactory for DefaultTransportFactory {
         }
     }
 }
+
+/// Creates an [`UreqHttpTransport`].
+#[cfg(feature = "ureq")]
+#[derive(Clone)]
+pub struct UreqTransportFactory;
+
+#[cfg(feature = "ureq")]
+impl TransportFactory for UreqTransportFactory {
+    fn create_transport(&self, options: &ClientOptions) -> Arc<dyn Transport> {
+        Arc::new(UreqHttpTransport::new(options))
+    }
+}
+
+/// Creates a [`ReqwestHttpTransport`].
+#[cfg(feature = "reqwest")]
+#[derive(Clone)]
+pub struct ReqwestTransportFactory;
+
+#[cfg(feature = "reqwest")]
+impl TransportFactory for ReqwestTransportFactory {
+    fn create_transport(&self, options: &ClientOptions) -> Arc<dyn Transport> {
+        Arc::new(ReqwestHttpTransport::new(options))
+    }
+}
+
+/// Creates a [`CurlHttpTransport`].
+#[cfg(feature = "curl")]
+#[derive(Clone)]
+pub struct CurlTransportFactory;
+
+#[cfg(feature = "curl")]
+impl TransportFactory for CurlTransportFactory {
+    fn create_transport(&self, options: &ClientOptions) -> Arc<dyn Transport> {
+        Arc::new(CurlHttpTransport::new(options))
+    }
+}
+
+/// Creates a [`DryRunTransport`] writing to the `dry_run_dir` of the options.
+#[derive(Clone)]
+pub struct DryRunTransportFactory;
+
+impl TransportFactory for DryRunTransportFactory {
+    fn create_transport(&self, options: &ClientOptions) -> Arc<dyn Transport> {
+        Arc::new(DryRunTransport::new(options))
+    }
+}
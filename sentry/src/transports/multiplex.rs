@@ -0,0 +1,288 @@
+use std::path::PathBuf;
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::DefaultTransportFactory;
+use crate::protocol::DataCategory;
+use crate::{ClientOptions, Envelope, Transport, TransportError, TransportFactory};
+
+/// A [`Transport`] that forwards every envelope to several other transports.
+///
+/// This can be used to send the same events to multiple Sentry projects, with
+/// each transport targeting its own DSN. Flushing and shutting down happen on
+/// all transports at once, so they share the same timeout, and return `false`
+/// unless every transport finished in time.
+///
+/// Each transport applies the rate limits of its own DSN, so that one project
+/// being rate limited does not hold back the events sent to the others.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use std::sync::Arc;
+/// let transport = sentry::transports::MultiplexTransport::from_options(&[
+///     sentry::ClientOptions {
+///         dsn: "https://team@example.com/1".parse().ok(),
+///         ..Default::default()
+///     },
+///     sentry::ClientOptions {
+///         dsn: "https://org@example.com/2".parse().ok(),
+///         ..Default::default()
+///     },
+/// ]);
+/// let _sentry = sentry::init(sentry::ClientOptions {
+///     dsn: "https://team@example.com/1".parse().ok(),
+///     transport: Some(Arc::new(Arc::new(transport))),
+///     ..Default::default()
+/// });
+/// ```
+pub struct MultiplexTransport {
+    transports: Vec<Arc<dyn Transport>>,
+}
+
+impl MultiplexTransport {
+    /// Creates a new Transport forwarding to all of the given `transports`.
+    pub fn new(transports: Vec<Box<dyn Transport>>) -> Self {
+        Self {
+            transports: transports.into_iter().map(Arc::from).collect(),
+        }
+    }
+
+    /// Creates a new Transport forwarding to one transport per entry of `options`.
+    ///
+    /// Each transport is created by the `transport` factory of its options,
+    /// falling back to the default HTTP transport. When several of them share
+    /// a `rate_limit_store_path`, each persists its rate limits to a file of
+    /// its own next to it, named after the public key and project of its DSN.
+    pub fn from_options(options: &[ClientOptions]) -> Self {
+        let transports = options
+            .iter()
+            .map(|child| {
+                let keyed;
+                let child = match keyed_store_path(child, options) {
+                    Some(path) => {
+                        keyed = ClientOptions {
+                            rate_limit_store_path: Some(path),
+                            ..child.clone()
+                        };
+                        &keyed
+                    }
+                    None => child,
+                };
+                match child.transport {
+                    Some(ref factory) => factory.create_transport(child),
+                    None => DefaultTransportFactory.create_transport(child),
+                }
+            })
+            .collect();
+        Self { transports }
+    }
+
+    /// Runs `f` on all transports at the same time, each on its own thread,
+    /// giving every one of them the whole `timeout`.
+    ///
+    /// Returns what `f` returned for each transport once all of them finished,
+    /// or once the `timeout` has passed, with [`None`] for those that did not
+    /// finish in time.
+    fn run_within<T, F>(&self, timeout: Duration, f: F) -> Vec<Option<T>>
+    where
+        T: Send + 'static,
+        F: Fn(&dyn Transport, Duration) -> T + Copy + Send + 'static,
+    {
+        if let [transport] = &self.transports[..] {
+            return vec![Some(f(&**transport, timeout))];
+        }
+        let deadline = Instant::now() + timeout;
+        let (sender, receiver) = mpsc::channel();
+        for (index, transport) in self.transports.iter().enumerate() {
+            let (thread_transport, thread_sender) = (transport.clone(), sender.clone());
+            let spawned = thread::Builder::new()
+                .name("sentry-multiplex".into())
+                .spawn(move || {
+                    thread_sender
+                        .send((index, f(&*thread_transport, timeout)))
+                        .ok();
+                });
+            if spawned.is_err() {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                sender.send((index, f(&**transport, remaining))).ok();
+            }
+        }
+        drop(sender);
+
+        let mut results: Vec<_> = self.transports.iter().map(|_| None).collect();
+        // this stops early once every thread sent its result
+        while let Ok((index, result)) =
+            receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        {
+            results[index] = Some(result);
+        }
+        results
+    }
+
+    /// Runs `f` like [`MultiplexTransport::run_within`], returning `true` if
+    /// it returned `true` for all transports in time.
+    fn all_within<F>(&self, timeout: Duration, f: F) -> bool
+    where
+        F: Fn(&dyn Transport, Duration) -> bool + Copy + Send + 'static,
+    {
+        self.run_within(timeout, f)
+            .into_iter()
+            .all(|result| result == Some(true))
+    }
+}
+
+/// Returns the file to persist the rate limits of `child` to, keyed by its DSN,
+/// if other `options` persist theirs to the same file.
+fn keyed_store_path(child: &ClientOptions, options: &[ClientOptions]) -> Option<PathBuf> {
+    let path = child.rate_limit_store_path.as_ref()?;
+    let dsn = child.dsn.as_ref()?;
+    let sharing = options
+        .iter()
+        .filter(|other| other.rate_limit_store_path.as_ref() == Some(path))
+        .count();
+    if sharing < 2 {
+        return None;
+    }
+    let mut name = path.file_name()?.to_owned();
+    name.push(format!(".{}-{}", dsn.public_key(), dsn.project_id()));
+    Some(path.with_file_name(name))
+}
+
+impl Transport for MultiplexTransport {
+    fn send_envelope(&self, envelope: Envelope) {
+        let Some((last, rest)) = self.transports.split_last() else {
+            return;
+        };
+        for transport in rest {
+            transport.send_envelope(envelope.clone());
+        }
+        last.send_envelope(envelope);
+    }
+
+    fn flush(&self, timeout: Duration) -> bool {
+        self.all_within(timeout, |transport, timeout| transport.flush(timeout))
+    }
+
+    fn flush_remaining(&self, timeout: Duration) -> usize {
+        self.run_within(timeout, |transport, timeout| {
+            transport.flush_remaining(timeout)
+        })
+        .into_iter()
+        // a transport that did not finish still has at least one envelope
+        .map(|remaining| remaining.unwrap_or(1))
+        .sum()
+    }
+
+    fn flush_category(&self, category: DataCategory, timeout: Duration) -> bool {
+        self.all_within(timeout, move |transport, timeout| {
+            transport.flush_category(category, timeout)
+        })
+    }
+
+    fn shutdown(&self, timeout: Duration) -> bool {
+        self.all_within(timeout, |transport, timeout| transport.shutdown(timeout))
+    }
+
+    fn clear_rate_limits(&self) {
+        for transport in &self.transports {
+            transport.clear_rate_limits();
+        }
+    }
+
+    /// Returns the longest time until any of the transports sends items of
+    /// `category` again.
+    fn time_until_allowed(&self, category: DataCategory) -> Option<Duration> {
+        self.transports
+            .iter()
+            .filter_map(|transport| transport.time_until_allowed(category))
+            .max()
+    }
+
+    fn last_error(&self) -> Option<TransportError> {
+        self.transports
+            .iter()
+            .filter_map(|transport| transport.last_error())
+            .max_by_key(|error| error.timestamp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::protocol::Event;
+
+    #[derive(Default)]
+    struct Recording {
+        sent: Mutex<usize>,
+        flushes: bool,
+    }
+
+    impl Transport for Recording {
+        fn send_envelope(&self, _envelope: Envelope) {
+            *self.sent.lock().unwrap() += 1;
+        }
+
+        fn flush(&self, _timeout: Duration) -> bool {
+            self.flushes
+        }
+    }
+
+    #[test]
+    fn test_multiplex() {
+        let first = Arc::new(Recording {
+            flushes: true,
+            ..Default::default()
+        });
+        let second = Arc::new(Recording::default());
+        let transport =
+            MultiplexTransport::new(vec![Box::new(first.clone()), Box::new(second.clone())]);
+
+        transport.send_envelope(Event::default().into());
+        transport.send_envelope(Event::default().into());
+        assert_eq!(*first.sent.lock().unwrap(), 2);
+        assert_eq!(*second.sent.lock().unwrap(), 2);
+
+        assert!(!transport.flush(Duration::from_secs(1)));
+        assert!(MultiplexTransport::new(vec![Box::new(first)]).flush(Duration::from_secs(1)));
+    }
+
+    /// A transport taking `delay` to flush, whatever the timeout.
+    struct Slow {
+        delay: Duration,
+    }
+
+    impl Transport for Slow {
+        fn send_envelope(&self, _envelope: Envelope) {}
+
+        fn flush(&self, _timeout: Duration) -> bool {
+            thread::sleep(self.delay);
+            true
+        }
+    }
+
+    #[test]
+    fn test_shared_deadline() {
+        let slow = |millis| -> Box<dyn Transport> {
+            Box::new(Slow {
+                delay: Duration::from_millis(millis),
+            })
+        };
+
+        // the transports flush at the same time
+        let transport = MultiplexTransport::new(vec![slow(300), slow(300)]);
+        let start = Instant::now();
+        assert!(transport.flush(Duration::from_secs(5)));
+        assert!(start.elapsed() < Duration::from_millis(550));
+
+        // and flushing returns at the deadline, even if they are still busy
+        let transport = MultiplexTransport::new(vec![slow(50), slow(2000)]);
+        let start = Instant::now();
+        assert!(!transport.flush(Duration::from_millis(200)));
+        assert!(start.elapsed() < Duration::from_millis(1000));
+        assert_eq!(transport.flush_remaining(Duration::from_millis(200)), 1);
+    }
+}
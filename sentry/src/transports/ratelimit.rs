@@ -1,17 +1,59 @@
 use httpdate::parse_http_date;
-use std::time::{Duration, SystemTime};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use crate::protocol::EnvelopeItem;
-use crate::Envelope;
+use crate::protocol::{DataCategory, EnvelopeItem, ItemContainer};
+use crate::{Envelope, RateLimit, RateLimitCallback, RateLimits};
+
+/// A source of the current monotonic and wall-clock time, used by the [`RateLimiter`].
+pub type Clock = Arc<dyn Fn() -> (Instant, SystemTime) + Send + Sync>;
+/// A source of random numbers between `0.0` and `1.0`, used by the [`RateLimiter`].
+pub type Rng = Arc<dyn Fn() -> f32 + Send + Sync>;
 
 /// A Utility that helps with rate limiting sentry requests.
-#[derive(Debug, Default)]
+///
+/// The deadlines of the limits are kept as [`Instant`]s, so that the system
+/// clock stepping backwards can't keep a category limited for longer than the
+/// server asked for. The wall-clock time is only used to interpret HTTP dates
+/// and to persist the limits.
+#[derive(Clone, Default)]
 pub struct RateLimiter {
-    global: Option<SystemTime>,
-    error: Option<SystemTime>,
-    session: Option<SystemTime>,
-    transaction: Option<SystemTime>,
-    attachment: Option<SystemTime>,
+    clock: Option<Clock>,
+    rng: Option<Rng>,
+    on_change: Option<Arc<RateLimitCallback>>,
+    jitter: f32,
+    ramp_up: Duration,
+    global: Option<Instant>,
+    error: Option<Instant>,
+    session: Option<Instant>,
+    transaction: Option<Instant>,
+    attachment: Option<Instant>,
+    monitor: Option<Instant>,
+    log_item: Option<Instant>,
+    profile: Option<Instant>,
+    feedback: Option<Instant>,
+}
+
+impl fmt::Debug for RateLimiter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimiter")
+            .field("jitter", &self.jitter)
+            .field("ramp_up", &self.ramp_up)
+            .field("global", &self.global)
+            .field("error", &self.error)
+            .field("session", &self.session)
+            .field("transaction", &self.transaction)
+            .field("attachment", &self.attachment)
+            .field("monitor", &self.monitor)
+            .field("log_item", &self.log_item)
+            .field("profile", &self.profile)
+            .field("feedback", &self.feedback)
+            .finish_non_exhaustive()
+    }
 }
 
 impl RateLimiter {
@@ -20,56 +62,281 @@ impl RateLimiter {
         Self::default()
     }
 
-    /// Updates the RateLimiter with information from a `Retry-After` header.
-    pub fn update_from_retry_after(&mut self, header: &str) {
-        let new_time = if let Ok(value) = header.parse::<f64>() {
-            SystemTime::now() + Duration::from_secs(value.ceil() as u64)
-        } else if let Ok(value) = parse_http_date(header) {
-            value
+    /// Makes the RateLimiter read the current time from `clock` instead of
+    /// the system clock.
+    #[cfg(test)]
+    pub fn with_clock(mut self, clock: Clock) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    /// Makes the RateLimiter read random numbers from `rng` instead of
+    /// generating them.
+    #[cfg(test)]
+    pub fn with_rng(mut self, rng: Rng) -> Self {
+        self.rng = Some(rng);
+        self
+    }
+
+    /// Makes the RateLimiter add a random delay of up to `jitter` times the
+    /// duration of every rate limit.
+    ///
+    /// The `jitter` is clamped to between `0.0` and `1.0`.
+    pub fn with_jitter(mut self, jitter: f32) -> Self {
+        self.jitter = if jitter.is_nan() {
+            0.0
         } else {
-            SystemTime::now() + Duration::from_secs(60)
+            jitter.clamp(0.0, 1.0)
         };
+        self
+    }
 
-        self.global = Some(new_time);
+    /// Makes the RateLimiter only let through part of the envelopes for
+    /// `ramp_up` after a rate limit expired.
+    ///
+    /// The fraction of envelopes let through grows linearly from none at the
+    /// end of the limit to all of them once `ramp_up` passed, see
+    /// [`RateLimiter::filter_envelope`]. Limits lifted by the server, by
+    /// sending a limit that already expired, are not ramped up.
+    pub fn with_ramp_up(mut self, ramp_up: Duration) -> Self {
+        self.ramp_up = ramp_up;
+        self
     }
 
-    /// Updates the RateLimiter with information from a `X-Sentry-Rate-Limits` header.
-    pub fn update_from_sentry_header(&mut self, header: &str) {
-        // <rate-limit> = (<group>,)+
-        // <group> = <time>:(<category>;)+:<scope>(:<reason>)?
+    /// Makes the RateLimiter call `on_change` whenever it changes a rate limit.
+    pub fn with_callback(mut self, on_change: Option<Arc<RateLimitCallback>>) -> Self {
+        self.on_change = on_change;
+        self
+    }
 
-        let mut parse_group = |group: &str| {
-            let mut splits = group.split(':');
-            let seconds = splits.next()?.parse::<f64>().ok()?;
-            let categories = splits.next()?;
-            let _scope = splits.next()?;
+    fn now(&self) -> Instant {
+        match &self.clock {
+            Some(clock) => clock().0,
+            None => Instant::now(),
+        }
+    }
 
-            let new_time = Some(SystemTime::now() + Duration::from_secs(seconds.ceil() as u64));
+    fn system_now(&self) -> SystemTime {
+        match &self.clock {
+            Some(clock) => clock().1,
+            None => SystemTime::now(),
+        }
+    }
 
-            if categories.is_empty() {
-                self.global = new_time;
-            }
+    /// Converts a `deadline` into the wall-clock time it is reached at.
+    fn system_time_of(&self, deadline: Instant) -> SystemTime {
+        let (now, system_now) = (self.now(), self.system_now());
+        match deadline.checked_duration_since(now) {
+            Some(time_left) => system_now + time_left,
+            None => system_now - now.duration_since(deadline),
+        }
+    }
+
+    /// Converts a wall-clock time into a deadline, relative to the current time.
+    ///
+    /// Deadlines further away than [`RateLimit::MAX_DURATION`] are shortened to that.
+    fn deadline_at(&self, time: SystemTime) -> Instant {
+        let (now, system_now) = (self.now(), self.system_now());
+        match time.duration_since(system_now) {
+            Ok(time_left) => now + time_left.min(RateLimit::MAX_DURATION),
+            Err(err) => now.checked_sub(err.duration()).unwrap_or(now),
+        }
+    }
+
+    fn random(&self) -> f32 {
+        match &self.rng {
+            Some(rng) => rng(),
+            None => rand::random(),
+        }
+    }
 
-            for category in categories.split(';') {
-                match category {
-                    "error" => self.error = new_time,
-                    "session" => self.session = new_time,
-                    "transaction" => self.transaction = new_time,
-                    "attachment" => self.attachment = new_time,
-                    _ => {}
-                }
+    /// Adds the random delay configured via [`RateLimiter::with_jitter`] to a
+    /// rate limit `duration`.
+    fn jittered(&self, duration: Duration) -> Duration {
+        if self.jitter == 0.0 {
+            return duration;
+        }
+        let random = self.random();
+        duration
+            .mul_f32(1.0 + self.jitter * random.clamp(0.0, 1.0))
+            .min(RateLimit::MAX_DURATION)
+    }
+
+    /// Loads the rate limits previously persisted via [`RateLimiter::save`].
+    ///
+    /// Returns an empty RateLimiter if the file is missing or corrupt.
+    pub fn load(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| Self::parse_persisted(&contents))
+            .unwrap_or_default()
+    }
+
+    fn parse_persisted(contents: &str) -> Option<Self> {
+        let mut rl = Self::new();
+        for line in contents.lines() {
+            let (category, secs) = line.split_once(' ')?;
+            let time = UNIX_EPOCH + Duration::from_secs(secs.parse().ok()?);
+            let deadline = Some(rl.deadline_at(time));
+            match category {
+                "global" => rl.global = deadline,
+                category => *rl.category_mut(RateLimit::parse_category(category)?)? = deadline,
             }
-            Some(())
+        }
+        Some(rl)
+    }
+
+    /// Returns the rate limit of a category.
+    ///
+    /// Returns [`None`] for categories the SDK doesn't send.
+    fn category_mut(&mut self, category: DataCategory) -> Option<&mut Option<Instant>> {
+        Some(match category {
+            DataCategory::Error => &mut self.error,
+            DataCategory::Session => &mut self.session,
+            DataCategory::Transaction => &mut self.transaction,
+            DataCategory::Attachment => &mut self.attachment,
+            DataCategory::Monitor => &mut self.monitor,
+            DataCategory::LogItem => &mut self.log_item,
+            DataCategory::Profile => &mut self.profile,
+            DataCategory::Feedback => &mut self.feedback,
+            _ => return None,
+        })
+    }
+
+    /// Sets the rate limit of a `category`, or the global one if it is [`None`],
+    /// calling the callback if that changed it.
+    ///
+    /// A `deadline` that was already reached lifts the limit right away,
+    /// without ramping up.
+    fn set_limit(&mut self, category: Option<DataCategory>, deadline: Instant) {
+        let lifted = deadline <= self.now();
+        let limit = match category {
+            Some(category) => self.category_mut(category),
+            None => Some(&mut self.global),
+        };
+        let Some(limit) = limit else {
+            return;
         };
+        let new = (!lifted).then_some(deadline);
+        if std::mem::replace(limit, new) == new {
+            return;
+        }
+        if let Some(ref on_change) = self.on_change {
+            on_change(category, self.system_time_of(deadline));
+        }
+    }
 
-        for group in header.split(',') {
-            parse_group(group.trim());
+    /// Lifts all rate limits right away, without ramping up.
+    pub fn clear(&mut self) {
+        let now = self.now();
+        for category in [
+            None,
+            Some(DataCategory::Error),
+            Some(DataCategory::Session),
+            Some(DataCategory::Transaction),
+            Some(DataCategory::Attachment),
+            Some(DataCategory::Monitor),
+            Some(DataCategory::LogItem),
+            Some(DataCategory::Profile),
+            Some(DataCategory::Feedback),
+        ] {
+            self.set_limit(category, now);
+        }
+    }
+
+    /// Persists the currently active rate limits to the given file.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let now = self.now();
+        let mut contents = String::new();
+        for (category, deadline) in [
+            ("global", self.global),
+            ("error", self.error),
+            ("session", self.session),
+            ("transaction", self.transaction),
+            ("attachment", self.attachment),
+            ("monitor", self.monitor),
+            ("log_item", self.log_item),
+            ("profile", self.profile),
+            ("feedback", self.feedback),
+        ] {
+            let Some(deadline) = deadline.filter(|deadline| *deadline > now) else {
+                continue;
+            };
+            let secs = self
+                .system_time_of(deadline)
+                .duration_since(UNIX_EPOCH)
+                .map_err(io::Error::other)?;
+            // round up, so we never resume sending too early
+            let secs = secs.as_secs() + u64::from(secs.subsec_nanos() > 0);
+            contents.push_str(&format!("{category} {secs}\n"));
+        }
+        fs::write(path, contents)
+    }
+
+    /// Updates the RateLimiter with information from a `Retry-After` header.
+    ///
+    /// The header can either be a number of seconds or an HTTP date. Invalid
+    /// values result in a default rate limit of 60 seconds. The jitter is
+    /// added on top.
+    pub fn update_from_retry_after(&mut self, header: &str) {
+        let header = header.trim();
+        let now = self.now();
+        let duration = if let Ok(seconds) = header.parse::<f64>() {
+            RateLimit::duration_from_secs(seconds)
+        } else if let Ok(date) = parse_http_date(header) {
+            // a date in the past means we can send right away
+            date.duration_since(self.system_now())
+                .unwrap_or_default()
+                .min(RateLimit::MAX_DURATION)
+        } else {
+            RateLimit::DEFAULT_DURATION
+        };
+
+        let deadline = now + self.jittered(duration);
+        self.set_limit(None, deadline);
+    }
+
+    /// Updates the RateLimiter with information from a `X-Sentry-Rate-Limits` header.
+    ///
+    /// Each limit only applies to the categories it lists, or to all of them if
+    /// the list is empty, see [`RateLimits::parse`]. The jitter is added on top
+    /// of every limit.
+    pub fn update_from_sentry_header(&mut self, header: &str) {
+        let now = self.now();
+        for limit in RateLimits::parse(header).limits() {
+            let deadline = now + self.jittered(limit.duration);
+            if limit.categories.is_empty() {
+                self.set_limit(None, deadline);
+            }
+            for &category in &limit.categories {
+                self.set_limit(Some(category), deadline);
+            }
         }
     }
 
     /// Updates the RateLimiter in response to a `429` status code.
-    pub fn update_from_429(&mut self) {
-        self.global = Some(SystemTime::now() + Duration::from_secs(60));
+    ///
+    /// Given the `envelope` that was rejected, only the categories of its items
+    /// are limited. Without one, or if any of its items belongs to no category,
+    /// all categories are.
+    pub fn update_from_429(&mut self, envelope: Option<&Envelope>) {
+        let deadline = self.now() + self.jittered(RateLimit::DEFAULT_DURATION);
+        let categories: Option<Vec<_>> = envelope.and_then(|envelope| {
+            let mut items = envelope.items().peekable();
+            items.peek()?;
+            items
+                .map(|item| item_category(item).data_category())
+                .collect()
+        });
+        let Some(mut categories) = categories else {
+            self.set_limit(None, deadline);
+            return;
+        };
+        categories.sort_unstable();
+        categories.dedup();
+        for category in categories {
+            self.set_limit(Some(category), deadline);
+        }
     }
 
     /// Query the RateLimiter if a certain category of event is currently rate limited.
@@ -77,20 +344,36 @@ impl RateLimiter {
     /// If the given category is rate limited, it will return the remaining
     /// [`Duration`] for which it is.
     pub fn is_disabled(&self, category: RateLimitingCategory) -> Option<Duration> {
-        if let Some(ts) = self.global {
-            let time_left = ts.duration_since(SystemTime::now()).ok();
-            if time_left.is_some() {
-                return time_left;
-            }
+        let now = self.now();
+        // a limit expires once its deadline is reached
+        let time_left = |deadline: Instant| {
+            deadline
+                .checked_duration_since(now)
+                .filter(|time_left| !time_left.is_zero())
+        };
+        if let Some(global) = self.global.and_then(time_left) {
+            return Some(global);
         }
-        let time_left = match category {
+        let deadline = match category {
             RateLimitingCategory::Any => self.global,
             RateLimitingCategory::Error => self.error,
             RateLimitingCategory::Session => self.session,
             RateLimitingCategory::Transaction => self.transaction,
             RateLimitingCategory::Attachment => self.attachment,
+            RateLimitingCategory::Monitor => self.monitor,
+            RateLimitingCategory::LogItem => self.log_item,
+            RateLimitingCategory::Profile => self.profile,
+            RateLimitingCategory::Feedback => self.feedback,
         }?;
-        time_left.duration_since(SystemTime::now()).ok()
+        time_left(deadline)
+    }
+
+    /// Returns how long until items of `category` may be sent again, or
+    /// [`None`] if they may be sent now.
+    ///
+    /// Limits applying to all categories are taken into account as well.
+    pub fn time_until_allowed(&self, category: DataCategory) -> Option<Duration> {
+        self.is_disabled(RateLimitingCategory::of_data(category))
     }
 
     /// Query the RateLimiter for a certain category of event.
@@ -100,26 +383,113 @@ impl RateLimiter {
         self.is_disabled(category).is_none()
     }
 
+    /// Returns the categories of data which are currently rate limited.
+    #[cfg_attr(not(any(feature = "curl", feature = "ureq")), allow(dead_code))]
+    pub fn disabled_categories(&self) -> impl Iterator<Item = DataCategory> + '_ {
+        [
+            RateLimitingCategory::Error,
+            RateLimitingCategory::Session,
+            RateLimitingCategory::Transaction,
+            RateLimitingCategory::Attachment,
+            RateLimitingCategory::Monitor,
+            RateLimitingCategory::LogItem,
+            RateLimitingCategory::Profile,
+            RateLimitingCategory::Feedback,
+        ]
+        .into_iter()
+        .filter(|&category| !self.is_enabled(category))
+        .filter_map(RateLimitingCategory::data_category)
+    }
+
+    /// Query the RateLimiter if an envelope `item` should be sent.
+    pub fn is_item_enabled(&self, item: &EnvelopeItem) -> bool {
+        self.is_enabled(item_category(item))
+    }
+
+    /// Returns the fraction of the envelopes of a `category` let through
+    /// while ramping up after a limit expired, see [`RateLimiter::with_ramp_up`].
+    fn ramp_fraction(&self, category: RateLimitingCategory) -> f32 {
+        if self.ramp_up.is_zero() {
+            return 1.0;
+        }
+        let now = self.now();
+        let fraction = |deadline: Option<Instant>| {
+            let elapsed = now.checked_duration_since(deadline?)?;
+            (elapsed < self.ramp_up).then(|| elapsed.div_duration_f32(self.ramp_up))
+        };
+        let deadline = match category {
+            RateLimitingCategory::Any => None,
+            RateLimitingCategory::Error => self.error,
+            RateLimitingCategory::Session => self.session,
+            RateLimitingCategory::Transaction => self.transaction,
+            RateLimitingCategory::Attachment => self.attachment,
+            RateLimitingCategory::Monitor => self.monitor,
+            RateLimitingCategory::LogItem => self.log_item,
+            RateLimitingCategory::Profile => self.profile,
+            RateLimitingCategory::Feedback => self.feedback,
+        };
+        [self.global, deadline]
+            .into_iter()
+            .filter_map(fraction)
+            .fold(1.0, f32::min)
+    }
+
     /// Filters the [`Envelope`] according to the current rate limits.
     ///
-    /// Returns [`None`] if all the envelope items were filtered out.
-    pub fn filter_envelope(&self, envelope: Envelope) -> Option<Envelope> {
+    /// While ramping up after a limit expired, items are let through with a
+    /// growing probability. The same random number is used for all items, so
+    /// that an event is not sent without the attachments of the same category.
+    ///
+    /// Calls `on_discard` for every item that is filtered out, and returns
+    /// [`None`] if all the envelope items were filtered out.
+    pub fn filter_envelope<F>(&self, envelope: Envelope, mut on_discard: F) -> Option<Envelope>
+    where
+        F: FnMut(&EnvelopeItem),
+    {
+        let random = if self.ramp_up.is_zero() {
+            0.0
+        } else {
+            self.random()
+        };
         envelope.filter(|item| {
-            self.is_enabled(match item {
-                EnvelopeItem::Event(_) => RateLimitingCategory::Error,
-                EnvelopeItem::SessionUpdate(_) | EnvelopeItem::SessionAggregates(_) => {
-                    RateLimitingCategory::Session
-                }
-                EnvelopeItem::Transaction(_) => RateLimitingCategory::Transaction,
-                EnvelopeItem::Attachment(_) => RateLimitingCategory::Attachment,
-                _ => RateLimitingCategory::Any,
-            })
+            let enabled =
+                self.is_item_enabled(item) && random < self.ramp_fraction(item_category(item));
+            if !enabled {
+                on_discard(item);
+            }
+            enabled
         })
     }
 }
 
+/// Returns whether `envelope` carries items of `category`.
+#[cfg_attr(not(any(feature = "curl", feature = "ureq")), allow(dead_code))]
+pub fn has_category(envelope: &Envelope, category: DataCategory) -> bool {
+    envelope
+        .items()
+        .any(|item| item_category(item).data_category() == Some(category))
+}
+
+/// Returns the category of the rate limits applying to an envelope `item`.
+fn item_category(item: &EnvelopeItem) -> RateLimitingCategory {
+    match item {
+        EnvelopeItem::Event(_) => RateLimitingCategory::Error,
+        EnvelopeItem::SessionUpdate(_) | EnvelopeItem::SessionAggregates(_) => {
+            RateLimitingCategory::Session
+        }
+        EnvelopeItem::Transaction(_) => RateLimitingCategory::Transaction,
+        EnvelopeItem::Attachment(_) => RateLimitingCategory::Attachment,
+        EnvelopeItem::MonitorCheckIn(_) => RateLimitingCategory::Monitor,
+        EnvelopeItem::ItemContainer(ItemContainer::Logs(_)) => RateLimitingCategory::LogItem,
+        EnvelopeItem::Profile(_) => RateLimitingCategory::Profile,
+        EnvelopeItem::Feedback(_) => RateLimitingCategory::Feedback,
+        _ => RateLimitingCategory::Any,
+    }
+}
+
 /// The Category of payload that a Rate Limit refers to.
 #[non_exhaustive]
+#[derive(Clone, Copy)]
 pub enum RateLimitingCategory {
     /// Rate Limit for any kind of payload.
     Any,
@@ -131,10 +501,53 @@ pub enum RateLimitingCategory {
     Transaction,
     /// Rate Limit pertaining to Attachments.
     Attachment,
+    /// Rate Limit pertaining to Monitor Check-Ins.
+    Monitor,
+    /// Rate Limit pertaining to Logs.
+    LogItem,
+    /// Rate Limit pertaining to Profiles.
+    Profile,
+    /// Rate Limit pertaining to User Feedback.
+    Feedback,
+}
+
+impl RateLimitingCategory {
+    /// Returns the category limiting data of `category`, which is
+    /// [`RateLimitingCategory::Any`] for data not limited separately.
+    fn of_data(category: DataCategory) -> Self {
+        match category {
+            DataCategory::Default | DataCategory::Error => Self::Error,
+            DataCategory::Session => Self::Session,
+            DataCategory::Transaction => Self::Transaction,
+            DataCategory::Attachment => Self::Attachment,
+            DataCategory::Monitor => Self::Monitor,
+            DataCategory::LogItem => Self::LogItem,
+            DataCategory::Profile => Self::Profile,
+            DataCategory::Feedback => Self::Feedback,
+            _ => Self::Any,
+        }
+    }
+
+    /// Returns the category of the data limited, or [`None`] for [`RateLimitingCategory::Any`].
+    fn data_category(self) -> Option<DataCategory> {
+        Some(match self {
+            Self::Any => return None,
+            Self::Error => DataCategory::Error,
+            Self::Session => DataCategory::Session,
+            Self::Transaction => DataCategory::Transaction,
+            Self::Attachment => DataCategory::Attachment,
+            Self::Monitor => DataCategory::Monitor,
+            Self::LogItem => DataCategory::LogItem,
+            Self::Profile => DataCategory::Profile,
+            Self::Feedback => DataCategory::Feedback,
+        })
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Mutex;
+
     use super::*;
 
     #[test]
@@ -161,6 +574,413 @@ mod tests {
         assert!(rl.is_disabled(RateLimitingCategory::Any).unwrap() <= Duration::from_secs(30));
     }
 
+    #[test]
+    fn test_sentry_header_fields() {
+        let mut rl = RateLimiter::new();
+        rl.update_from_sentry_header(
+            "60:transaction:key:quota_exceeded, 120:attachment;monitor:organization:quota_exceeded:custom;spans, 30:log_item",
+        );
+
+        assert!(
+            rl.is_disabled(RateLimitingCategory::Transaction).unwrap() <= Duration::from_secs(60)
+        );
+        assert!(
+            rl.is_disabled(RateLimitingCategory::Attachment).unwrap() <= Duration::from_secs(120)
+        );
+        assert!(rl.is_disabled(RateLimitingCategory::Monitor).unwrap() <= Duration::from_secs(120));
+        assert!(rl.is_disabled(RateLimitingCategory::LogItem).unwrap() <= Duration::from_secs(30));
+        assert!(rl.is_enabled(RateLimitingCategory::Error));
+        assert!(rl.is_enabled(RateLimitingCategory::Session));
+        assert!(rl.is_enabled(RateLimitingCategory::Any));
+
+        rl.update_from_sentry_header("10::organization:quota_exceeded");
+        assert!(rl.is_disabled(RateLimitingCategory::Error).unwrap() <= Duration::from_secs(10));
+        assert!(rl.is_disabled(RateLimitingCategory::Any).unwrap() <= Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_filter_envelope() {
+        use crate::protocol::{Attachment, Event, Feedback, Profile};
+
+        let mut rl = RateLimiter::new();
+        rl.update_from_sentry_header("60:attachment;profile;feedback:key, 60:unknown:key");
+
+        let mut envelope: Envelope = Event::default().into();
+        envelope.add_item(Attachment::default());
+        envelope.add_item(Profile::default());
+        envelope.add_item(Feedback::default());
+        let mut discarded = 0;
+        let envelope = rl.filter_envelope(envelope, |_| discarded += 1).unwrap();
+        assert_eq!(discarded, 3);
+        assert!(matches!(
+            envelope.items().collect::<Vec<_>>()[..],
+            [EnvelopeItem::Event(_)]
+        ));
+    }
+
+    /// A handle to the time of a clock that stands still.
+    struct ManualTime(Arc<Mutex<(Instant, SystemTime)>>);
+
+    impl ManualTime {
+        /// Advances both the monotonic and the wall-clock time.
+        fn advance(&self, duration: Duration) {
+            let mut time = self.0.lock().unwrap();
+            time.0 += duration;
+            time.1 += duration;
+        }
+
+        /// Steps the wall-clock time back, like a clock synced via NTP might.
+        fn step_back(&self, duration: Duration) {
+            self.0.lock().unwrap().1 -= duration;
+        }
+
+        fn system(&self) -> SystemTime {
+            self.0.lock().unwrap().1
+        }
+    }
+
+    /// Returns a clock that stands still, and a handle to advance it.
+    fn manual_clock() -> (Clock, ManualTime) {
+        let time = Arc::new(Mutex::new((
+            Instant::now(),
+            UNIX_EPOCH + Duration::from_secs(1_000_000),
+        )));
+        let handle = ManualTime(time.clone());
+        (Arc::new(move || *time.lock().unwrap()), handle)
+    }
+
+    #[test]
+    fn test_clock() {
+        let (clock, time) = manual_clock();
+        let mut rl = RateLimiter::new().with_clock(clock);
+
+        rl.update_from_429(None);
+        assert_eq!(
+            rl.is_disabled(RateLimitingCategory::Any),
+            Some(Duration::from_secs(60))
+        );
+        time.advance(Duration::from_secs(45));
+        assert_eq!(
+            rl.is_disabled(RateLimitingCategory::Error),
+            Some(Duration::from_secs(15))
+        );
+        time.advance(Duration::from_secs(15));
+        assert!(rl.is_enabled(RateLimitingCategory::Any));
+
+        rl.update_from_retry_after("1.5");
+        assert_eq!(
+            rl.is_disabled(RateLimitingCategory::Any),
+            Some(Duration::from_secs(2))
+        );
+        let date = httpdate::fmt_http_date(time.system() + Duration::from_secs(120));
+        rl.update_from_retry_after(&date);
+        assert_eq!(
+            rl.is_disabled(RateLimitingCategory::Any),
+            Some(Duration::from_secs(120))
+        );
+        time.advance(Duration::from_secs(120));
+
+        rl.update_from_sentry_header("30:error:key, 90:transaction:key");
+        time.advance(Duration::from_secs(30));
+        assert!(rl.is_enabled(RateLimitingCategory::Error));
+        assert_eq!(
+            rl.is_disabled(RateLimitingCategory::Transaction),
+            Some(Duration::from_secs(60))
+        );
+    }
+
+    #[test]
+    fn test_time_until_allowed() {
+        let (clock, time) = manual_clock();
+        let mut rl = RateLimiter::new().with_clock(clock);
+        assert_eq!(rl.time_until_allowed(DataCategory::Error), None);
+
+        rl.update_from_sentry_header("60:error:key, 30:transaction:key");
+        assert_eq!(
+            rl.time_until_allowed(DataCategory::Error),
+            Some(Duration::from_secs(60))
+        );
+        // events without a type share the limits of errors
+        assert_eq!(
+            rl.time_until_allowed(DataCategory::Default),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(rl.time_until_allowed(DataCategory::Session), None);
+
+        // the limit of transactions expires first
+        time.advance(Duration::from_secs(30));
+        assert_eq!(rl.time_until_allowed(DataCategory::Transaction), None);
+        assert_eq!(
+            rl.time_until_allowed(DataCategory::Error),
+            Some(Duration::from_secs(30))
+        );
+
+        // a global limit applies to all categories
+        rl.update_from_retry_after("90");
+        assert_eq!(
+            rl.time_until_allowed(DataCategory::Session),
+            Some(Duration::from_secs(90))
+        );
+    }
+
+    #[test]
+    fn test_clock_stepping_back() {
+        let (clock, time) = manual_clock();
+        let mut rl = RateLimiter::new().with_clock(clock);
+
+        rl.update_from_sentry_header("60:error:key");
+        let date = httpdate::fmt_http_date(time.system() + Duration::from_secs(30));
+        rl.update_from_retry_after(&date);
+
+        // the limits still expire after the durations the server asked for
+        time.step_back(Duration::from_secs(3600));
+        assert_eq!(
+            rl.is_disabled(RateLimitingCategory::Any),
+            Some(Duration::from_secs(30))
+        );
+        time.advance(Duration::from_secs(30));
+        assert!(rl.is_enabled(RateLimitingCategory::Any));
+        assert_eq!(
+            rl.is_disabled(RateLimitingCategory::Error),
+            Some(Duration::from_secs(30))
+        );
+        time.advance(Duration::from_secs(30));
+        assert!(rl.is_enabled(RateLimitingCategory::Error));
+
+        // the callback is passed deadlines of the current wall-clock time
+        let deadlines = Arc::new(Mutex::new(Vec::new()));
+        let recorded = deadlines.clone();
+        rl = rl.with_callback(Some(Arc::new(move |_, deadline| {
+            recorded.lock().unwrap().push(deadline);
+        })));
+        rl.update_from_sentry_header("60:error:key");
+        assert_eq!(
+            *deadlines.lock().unwrap(),
+            [time.system() + Duration::from_secs(60)]
+        );
+    }
+
+    #[test]
+    fn test_callback() {
+        let (clock, time) = manual_clock();
+        let now = time.system();
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let recorded = changes.clone();
+        let mut rl = RateLimiter::new()
+            .with_clock(clock)
+            .with_callback(Some(Arc::new(move |category, deadline| {
+                recorded.lock().unwrap().push((category, deadline));
+            })));
+
+        rl.update_from_sentry_header("60:error;unknown:key, 30::organization");
+        rl.update_from_429(None);
+        // neither limits unknown categories nor changes a deadline
+        rl.update_from_sentry_header("60:unknown:key");
+        rl.update_from_sentry_header("60:error:key");
+        time.advance(Duration::from_secs(10));
+        rl.update_from_retry_after("5");
+
+        assert_eq!(
+            *changes.lock().unwrap(),
+            [
+                (Some(DataCategory::Error), now + Duration::from_secs(60)),
+                (None, now + Duration::from_secs(30)),
+                (None, now + Duration::from_secs(60)),
+                (None, now + Duration::from_secs(15)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_clear() {
+        let (clock, time) = manual_clock();
+        let now = time.system();
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let recorded = changes.clone();
+        let mut rl = RateLimiter::new()
+            .with_clock(clock)
+            .with_ramp_up(Duration::from_secs(60))
+            .with_callback(Some(Arc::new(move |category, deadline| {
+                recorded.lock().unwrap().push((category, deadline));
+            })));
+
+        rl.update_from_sentry_header("60:error;session:key, 30::organization");
+        changes.lock().unwrap().clear();
+        rl.clear();
+        // lifted right away, without ramping up
+        assert!(rl.is_enabled(RateLimitingCategory::Any));
+        assert!(rl.is_enabled(RateLimitingCategory::Error));
+        assert!(rl.is_enabled(RateLimitingCategory::Session));
+        assert_eq!(
+            *changes.lock().unwrap(),
+            [
+                (None, now),
+                (Some(DataCategory::Error), now),
+                (Some(DataCategory::Session), now),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_jitter() {
+        let (clock, _) = manual_clock();
+        let random = Arc::new(Mutex::new(0.5));
+        let rng = random.clone();
+        let mut rl = RateLimiter::new()
+            .with_clock(clock)
+            .with_rng(Arc::new(move || *rng.lock().unwrap()))
+            .with_jitter(0.5);
+
+        rl.update_from_sentry_header("60:error:key");
+        assert_eq!(
+            rl.is_disabled(RateLimitingCategory::Error),
+            Some(Duration::from_secs(75))
+        );
+        assert!(rl.is_enabled(RateLimitingCategory::Any));
+        *random.lock().unwrap() = 1.0;
+        rl.update_from_retry_after("100");
+        assert_eq!(
+            rl.is_disabled(RateLimitingCategory::Any),
+            Some(Duration::from_secs(150))
+        );
+
+        // the delay is never shortened, nor extended beyond the maximum
+        *random.lock().unwrap() = 0.0;
+        rl.update_from_429(None);
+        assert_eq!(
+            rl.is_disabled(RateLimitingCategory::Any),
+            Some(RateLimit::DEFAULT_DURATION)
+        );
+        *random.lock().unwrap() = 1.0;
+        rl = rl.with_jitter(f32::INFINITY);
+        rl.update_from_retry_after("86400");
+        assert_eq!(
+            rl.is_disabled(RateLimitingCategory::Any),
+            Some(RateLimit::MAX_DURATION)
+        );
+        rl = rl.with_jitter(f32::NAN);
+        rl.update_from_retry_after("10");
+        assert_eq!(
+            rl.is_disabled(RateLimitingCategory::Any),
+            Some(Duration::from_secs(10))
+        );
+    }
+
+    #[test]
+    fn test_429() {
+        use crate::protocol::{Attachment, Event};
+
+        let mut rl = RateLimiter::new();
+        rl.update_from_429(None);
+        assert!(rl.is_disabled(RateLimitingCategory::Any).unwrap() <= RateLimit::DEFAULT_DURATION);
+
+        // only the categories of the rejected envelope are limited
+        let mut rl = RateLimiter::new();
+        let mut envelope: Envelope = Event::default().into();
+        envelope.add_item(Attachment::default());
+        envelope.add_item(Attachment::default());
+        rl.update_from_429(Some(&envelope));
+        assert!(
+            rl.is_disabled(RateLimitingCategory::Error).unwrap() <= RateLimit::DEFAULT_DURATION
+        );
+        assert!(rl.is_disabled(RateLimitingCategory::Attachment).is_some());
+        assert!(rl.is_enabled(RateLimitingCategory::Transaction));
+        assert!(rl.is_enabled(RateLimitingCategory::Any));
+
+        // items without a category, or no items at all, limit everything
+        let mut rl = RateLimiter::new();
+        let mut envelope: Envelope = Event::default().into();
+        envelope.add_item(EnvelopeItem::Raw);
+        rl.update_from_429(Some(&envelope));
+        assert!(rl.is_disabled(RateLimitingCategory::Any).is_some());
+        let mut rl = RateLimiter::new();
+        rl.update_from_429(Some(&Envelope::new()));
+        assert!(rl.is_disabled(RateLimitingCategory::Any).is_some());
+    }
+
+    #[test]
+    fn test_ramp_up() {
+        use super::super::check_in;
+        use crate::protocol::Event;
+
+        let (clock, time) = manual_clock();
+        let random = Arc::new(Mutex::new(0.5));
+        let rng = random.clone();
+        let mut rl = RateLimiter::new()
+            .with_clock(clock)
+            .with_rng(Arc::new(move || *rng.lock().unwrap()))
+            .with_ramp_up(Duration::from_secs(10));
+        let mut envelope: Envelope = Event::default().into();
+        envelope.add_item(check_in("slug"));
+        let sent_items = |rl: &RateLimiter, value: f32| {
+            *random.lock().unwrap() = value;
+            rl.filter_envelope(envelope.clone(), |_| {})
+                .map_or(0, |envelope| envelope.items().count())
+        };
+        let advance = |secs| time.advance(Duration::from_secs(secs));
+
+        // only the limited category ramps up, linearly
+        rl.update_from_sentry_header("60:error:key");
+        advance(60);
+        assert!(rl.is_enabled(RateLimitingCategory::Error));
+        assert_eq!(sent_items(&rl, 0.0), 1);
+        advance(5);
+        assert_eq!(sent_items(&rl, 0.5), 1);
+        assert_eq!(sent_items(&rl, 0.4), 2);
+        advance(5);
+        assert_eq!(sent_items(&rl, 0.99), 2);
+
+        // a global limit ramps up all categories
+        rl.update_from_429(None);
+        advance(62);
+        assert_eq!(sent_items(&rl, 0.1), 2);
+        assert_eq!(sent_items(&rl, 0.3), 0);
+        advance(8);
+        assert_eq!(sent_items(&rl, 0.99), 2);
+
+        // limits lifted by the server are not ramped up
+        rl.update_from_sentry_header("60:error:key");
+        advance(30);
+        rl.update_from_sentry_header("0:error:key");
+        assert!(rl.is_enabled(RateLimitingCategory::Error));
+        assert_eq!(sent_items(&rl, 0.99), 2);
+    }
+
+    #[test]
+    fn test_persistence() {
+        let path =
+            std::env::temp_dir().join(format!("sentry-rate-limits-{}.txt", std::process::id()));
+
+        let mut rl = RateLimiter::new();
+        rl.update_from_sentry_header("120:error:project, 1:attachment:project");
+        rl.attachment = Some(Instant::now() - Duration::from_secs(1));
+        rl.save(&path).unwrap();
+
+        let loaded = RateLimiter::load(&path);
+        assert!(
+            loaded.is_disabled(RateLimitingCategory::Error).unwrap() <= Duration::from_secs(121)
+        );
+        assert!(loaded
+            .is_disabled(RateLimitingCategory::Attachment)
+            .is_none());
+        assert!(loaded.is_disabled(RateLimitingCategory::Any).is_none());
+
+        // limits persisted before the clock stepped back are shortened to the maximum
+        std::fs::write(&path, "error 99999999999\n").unwrap();
+        assert!(
+            RateLimiter::load(&path)
+                .is_disabled(RateLimitingCategory::Error)
+                .unwrap()
+                <= RateLimit::MAX_DURATION
+        );
+
+        std::fs::write(&path, "error not-a-number\n").unwrap();
+        assert!(RateLimiter::load(&path).is_enabled(RateLimitingCategory::Error));
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(RateLimiter::load(&path).is_enabled(RateLimitingCategory::Error));
+    }
+
     #[test]
     fn test_retry_after() {
         let mut rl = RateLimiter::new();
@@ -172,5 +992,45 @@ mod tests {
             rl.is_disabled(RateLimitingCategory::Transaction).unwrap() <= Duration::from_secs(60)
         );
         assert!(rl.is_disabled(RateLimitingCategory::Any).unwrap() <= Duration::from_secs(60));
+
+        rl.update_from_retry_after(" 1.5 ");
+        let time_left = rl.is_disabled(RateLimitingCategory::Any).unwrap();
+        assert!(time_left > Duration::from_secs(1) && time_left <= Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_retry_after_http_date() {
+        let mut rl = RateLimiter::new();
+        let date = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(120));
+        rl.update_from_retry_after(&date);
+        let time_left = rl.is_disabled(RateLimitingCategory::Any).unwrap();
+        assert!(time_left > Duration::from_secs(100) && time_left <= Duration::from_secs(120));
+
+        let date = httpdate::fmt_http_date(SystemTime::now() - Duration::from_secs(120));
+        rl.update_from_retry_after(&date);
+        assert!(rl.is_enabled(RateLimitingCategory::Any));
+
+        rl.update_from_retry_after("Fri, 31 Dec 9999 23:59:59 GMT");
+        assert!(rl.is_disabled(RateLimitingCategory::Any).unwrap() <= RateLimit::MAX_DURATION);
+    }
+
+    #[test]
+    fn test_retry_after_invalid() {
+        for header in ["", "garbage", "-10", "NaN", "inf"] {
+            let mut rl = RateLimiter::new();
+            rl.update_from_retry_after(header);
+            let time_left = rl.is_disabled(RateLimitingCategory::Any).unwrap();
+            assert!(
+                time_left > Duration::from_secs(50) && time_left <= RateLimit::DEFAULT_DURATION,
+                "{header:?}"
+            );
+        }
+
+        let mut rl = RateLimiter::new();
+        rl.update_from_retry_after("999999999999");
+        assert!(rl.is_disabled(RateLimitingCategory::Any).unwrap() <= RateLimit::MAX_DURATION);
+
+        rl.update_from_sentry_header("999999999999:error:organization");
+        assert!(rl.is_disabled(RateLimitingCategory::Error).unwrap() <= RateLimit::MAX_DURATION);
     }
 }
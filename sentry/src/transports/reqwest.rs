@@ -1,10 +1,20 @@
-use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
 
-use reqwest::{header as ReqwestHeaders, Client as ReqwestClient, Proxy, StatusCode};
+use reqwest::{header as ReqwestHeaders, Client as ReqwestClient, Proxy, Response, StatusCode};
+#[cfg(any(feature = "rustls", feature = "native-tls"))]
+use reqwest::{Certificate, Identity};
+use tokio::runtime::Handle;
 
+use super::ratelimit::RateLimiter;
 use super::tokio_thread::TransportThread;
+use super::{
+    add_trace_header, before_send_envelope, bypasses_proxy, log_response_body,
+    warn_accept_invalid_certs,
+};
 
-use crate::{sentry_debug, ClientOptions, Envelope, Transport};
+use crate::protocol::DataCategory;
+use crate::{sentry_debug, BeforeCallback, ClientOptions, Envelope, Transport};
 
 /// A [`Transport`] that sends events via the [`reqwest`] library.
 ///
@@ -13,110 +23,394 @@ use crate::{sentry_debug, ClientOptions, Envelope, Transport};
 /// `reqwest` feature flag.
 #[cfg_attr(doc_cfg, doc(cfg(feature = "reqwest")))]
 pub struct ReqwestHttpTransport {
-    thread: TransportThread,
+    /// The worker sending envelopes, or `None` if there is no DSN to send them to.
+    thread: Option<TransportThread>,
+    before_send_envelope: Option<BeforeCallback<Envelope>>,
+}
+
+/// Reads at most `limit` bytes of the body of `response`, returning them and
+/// whether the body was longer.
+async fn read_body(mut response: Response, limit: usize) -> reqwest::Result<(Vec<u8>, bool)> {
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        let rest = limit - body.len();
+        if chunk.len() > rest {
+            body.extend_from_slice(&chunk[..rest]);
+            return Ok((body, true));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok((body, false))
+}
+
+/// Updates the rate limits from the headers and status of the `response` to
+/// `envelope`.
+///
+/// A `429` without any headers only limits the categories of the `envelope`.
+fn update_rate_limits(rl: &mut RateLimiter, response: &Response, envelope: &Envelope) {
+    let headers = response.headers();
+    if let Some(sentry_header) = headers
+        .get("x-sentry-rate-limits")
+        .and_then(|x| x.to_str().ok())
+    {
+        rl.update_from_sentry_header(sentry_header);
+    } else if let Some(retry_after) = headers
+        .get(ReqwestHeaders::RETRY_AFTER)
+        .and_then(|x| x.to_str().ok())
+    {
+        rl.update_from_retry_after(retry_after);
+    } else if response.status() == StatusCode::TOO_MANY_REQUESTS {
+        rl.update_from_429(Some(envelope));
+    }
+}
+
+/// Adds the configured proxy credentials to the `proxy` created for `url`.
+fn make_proxy(proxy: reqwest::Result<Proxy>, url: &str, options: &ClientOptions) -> Option<Proxy> {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let has_credentials = rest.split('/').next().unwrap_or_default().contains('@');
+    match proxy {
+        Ok(proxy) => Some(match (&options.proxy_username, has_credentials) {
+            (Some(username), false) => proxy.basic_auth(
+                username,
+                options.proxy_password.as_deref().unwrap_or_default(),
+            ),
+            _ => proxy,
+        }),
+        Err(err) => {
+            sentry_debug!("invalid proxy: {:?}", err);
+            None
+        }
+    }
+}
+
+/// Configures the `extra_ca_certs` and the client certificate on `builder`.
+#[cfg(any(feature = "rustls", feature = "native-tls"))]
+fn configure_tls(
+    mut builder: reqwest::ClientBuilder,
+    options: &ClientOptions,
+) -> reqwest::ClientBuilder {
+    if let Some(ref extra_ca_certs) = options.extra_ca_certs {
+        let certs = extra_ca_certs
+            .read()
+            .map_err(|err| err.to_string())
+            .and_then(|pem| Certificate::from_pem_bundle(&pem).map_err(|err| err.to_string()));
+        match certs {
+            Ok(certs) => {
+                for cert in certs {
+                    builder = builder.add_root_certificate(cert);
+                }
+            }
+            Err(err) => sentry_debug!("Failed to load extra CA certificates: {}", err),
+        }
+    }
+
+    match (&options.client_cert_pem, &options.client_key_pem) {
+        (Some(cert), Some(key)) => {
+            let identity = cert
+                .read()
+                .and_then(|cert| Ok((cert, key.read()?)))
+                .map_err(|err| err.to_string())
+                .and_then(|(cert, key)| {
+                    #[cfg(feature = "native-tls")]
+                    let identity = Identity::from_pkcs8_pem(&cert, &key);
+                    #[cfg(not(feature = "native-tls"))]
+                    let identity = Identity::from_pem(&[&*cert, b"\n", &*key].concat());
+                    identity.map_err(|err| err.to_string())
+                });
+            match identity {
+                Ok(identity) => builder = builder.identity(identity),
+                Err(err) => sentry_debug!("Failed to load client certificate: {}", err),
+            }
+        }
+        (None, None) => {}
+        _ => sentry_debug!("client_cert_pem and client_key_pem need to be set together"),
+    }
+    builder
 }
 
 impl ReqwestHttpTransport {
     /// Creates a new Transport.
     pub fn new(options: &ClientOptions) -> Self {
-        Self::new_internal(options, None)
+        Self::new_internal(options, None, None)
     }
 
     /// Creates a new Transport that uses the specified [`ReqwestClient`].
     pub fn with_client(options: &ClientOptions, client: ReqwestClient) -> Self {
-        Self::new_internal(options, Some(client))
+        Self::new_internal(options, Some(client), None)
+    }
+
+    /// Creates a new Transport that sends envelopes on an existing Tokio runtime.
+    ///
+    /// Instead of spawning a dedicated thread running its own runtime, the
+    /// envelopes are sent by a task spawned on `runtime`, optionally using the
+    /// specified [`ReqwestClient`].
+    ///
+    /// Note that [`flush`](Transport::flush) blocks the calling thread while
+    /// waiting for that task, so with a current-thread runtime it should not
+    /// be called from within the runtime itself.
+    pub fn with_runtime(
+        options: &ClientOptions,
+        runtime: Handle,
+        client: Option<ReqwestClient>,
+    ) -> Self {
+        Self::new_internal(options, client, Some(runtime))
     }
 
-    fn new_internal(options: &ClientOptions, client: Option<ReqwestClient>) -> Self {
+    fn new_internal(
+        options: &ClientOptions,
+        client: Option<ReqwestClient>,
+        runtime: Option<Handle>,
+    ) -> Self {
         let client = client.unwrap_or_else(|| {
             let mut builder = reqwest::Client::builder();
+            warn_accept_invalid_certs(options);
             if options.accept_invalid_certs {
                 builder = builder.danger_accept_invalid_certs(true);
             }
-            if let Some(url) = options.http_proxy.as_ref() {
-                match Proxy::http(url.as_ref()) {
-                    Ok(proxy) => {
+            if !bypasses_proxy(options) {
+                if let Some(url) = options.http_proxy.as_ref() {
+                    if let Some(proxy) = make_proxy(Proxy::http(url.as_ref()), url, options) {
                         builder = builder.proxy(proxy);
                     }
-                    Err(err) => {
-                        sentry_debug!("invalid proxy: {:?}", err);
-                    }
-                }
-            };
-            if let Some(url) = options.https_proxy.as_ref() {
-                match Proxy::https(url.as_ref()) {
-                    Ok(proxy) => {
+                };
+                if let Some(url) = options.https_proxy.as_ref() {
+                    if let Some(proxy) = make_proxy(Proxy::https(url.as_ref()), url, options) {
                         builder = builder.proxy(proxy);
                     }
-                    Err(err) => {
-                        sentry_debug!("invalid proxy: {:?}", err);
-                    }
-                }
-            };
+                };
+            }
+            #[cfg(any(feature = "rustls", feature = "native-tls"))]
+            {
+                builder = configure_tls(builder, options);
+            }
+            if let Some(timeout) = options.transport_connect_timeout {
+                builder = builder.connect_timeout(timeout);
+            }
+            if let Some(timeout) = options.transport_read_timeout {
+                builder = builder.read_timeout(timeout);
+            }
+            builder = builder
+                .pool_max_idle_per_host(options.transport_max_idle_connections)
+                .pool_idle_timeout(options.transport_idle_timeout);
             builder
                 .build()
                 .expect("Failed to build `reqwest` client as a TLS backend is not available. Enable either the `native-tls` or the `rustls` feature of the `sentry` crate.")
         });
-        let dsn = options.dsn.as_ref().unwrap();
-        let user_agent = options.user_agent.clone();
-        let auth = dsn.to_auth(Some(&user_agent)).to_string();
-        let url = dsn.envelope_api_url().to_string();
+        let Some((url, auth)) = options.envelope_endpoint() else {
+            sentry_debug!("No DSN configured, discarding all envelopes");
+            return Self {
+                thread: None,
+                before_send_envelope: None,
+            };
+        };
 
-        let thread = TransportThread::new(move |envelope, mut rl| {
+        let log_body_limit = options.transport_log_body_limit;
+        let read_response_body = options.read_response_body;
+        let dynamic_sampling_context_fn = options.dynamic_sampling_context_fn.clone();
+        let send = move |mut envelope: Envelope, rl: Arc<Mutex<RateLimiter>>| {
+            envelope.set_sent_at(SystemTime::now());
+            add_trace_header(&mut envelope, dynamic_sampling_context_fn.as_deref());
             let mut body = Vec::new();
-            envelope.to_writer(&mut body).unwrap();
-            let request = client.post(&url).header("X-Sentry-Auth", &auth).body(body);
+            let request = match envelope.to_writer(&mut body) {
+                Ok(()) => Some(client.post(&url).header("X-Sentry-Auth", &auth).body(body)),
+                Err(err) => {
+                    sentry_debug!("Failed to serialize envelope: {}", err);
+                    None
+                }
+            };
 
             // NOTE: because of lifetime issues, building the request using the
             // `client` has to happen outside of this async block.
             async move {
+                let Some(request) = request else {
+                    return;
+                };
                 match request.send().await {
                     Ok(response) => {
-                        let headers = response.headers();
-
-                        if let Some(sentry_header) = headers
-                            .get("x-sentry-rate-limits")
-                            .and_then(|x| x.to_str().ok())
-                        {
-                            rl.update_from_sentry_header(sentry_header);
-                        } else if let Some(retry_after) = headers
-                            .get(ReqwestHeaders::RETRY_AFTER)
-                            .and_then(|x| x.to_str().ok())
-                        {
-                            rl.update_from_retry_after(retry_after);
-                        } else if response.status() == StatusCode::TOO_MANY_REQUESTS {
-                            rl.update_from_429();
+                        update_rate_limits(&mut rl.lock().unwrap(), &response, &envelope);
+                        if !read_response_body {
+                            return;
                         }
 
-                        match response.text().await {
+                        match read_body(response, log_body_limit).await {
                             Err(err) => {
                                 sentry_debug!("Failed to read sentry response: {}", err);
                             }
-                            Ok(text) => {
-                                sentry_debug!("Get response: `{}`", text);
-                            }
+                            Ok((body, truncated)) => log_response_body(&body, truncated),
                         }
                     }
                     Err(err) => {
                         sentry_debug!("Failed to send envelope: {}", err);
                     }
                 }
-                rl
             }
-        });
-        Self { thread }
+        };
+        let thread = match runtime {
+            Some(runtime) => TransportThread::spawn_on(options, &runtime, send),
+            None => TransportThread::new(options, send),
+        };
+        Self {
+            thread: Some(thread),
+            before_send_envelope: options.before_send_envelope.clone(),
+        }
     }
 }
 
 impl Transport for ReqwestHttpTransport {
     fn send_envelope(&self, envelope: Envelope) {
-        self.thread.send(envelope)
+        let Some(ref thread) = self.thread else {
+            return;
+        };
+        if let Some(envelope) = before_send_envelope(self.before_send_envelope.as_ref(), envelope) {
+            thread.send(envelope);
+        }
     }
     fn flush(&self, timeout: Duration) -> bool {
-        self.thread.flush(timeout)
+        match self.thread {
+            Some(ref thread) => thread.flush(timeout),
+            None => true,
+        }
+    }
+
+    fn flush_remaining(&self, timeout: Duration) -> usize {
+        match self.thread {
+            Some(ref thread) => thread.flush_remaining(timeout),
+            None => 0,
+        }
     }
 
     fn shutdown(&self, timeout: Duration) -> bool {
         self.flush(timeout)
     }
+
+    fn clear_rate_limits(&self) {
+        if let Some(ref thread) = self.thread {
+            thread.clear_rate_limits();
+        }
+    }
+
+    fn time_until_allowed(&self, category: DataCategory) -> Option<Duration> {
+        self.thread.as_ref()?.time_until_allowed(category)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    use super::*;
+    use crate::protocol::Event;
+
+    #[test]
+    fn test_with_runtime() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let dsn = format!("http://public@{}/1", listener.local_addr().unwrap());
+        let (sender, requests) = mpsc::channel();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            sender.send(request_line).unwrap();
+            write!(
+                reader.get_mut(),
+                "HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+            )
+            .unwrap();
+        });
+
+        // an application runtime, driven by a thread of its own
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let handle = runtime.handle().clone();
+        thread::spawn(move || runtime.block_on(std::future::pending::<()>()));
+
+        let options = ClientOptions {
+            dsn: Some(dsn.parse().unwrap()),
+            ..Default::default()
+        };
+        let transport = ReqwestHttpTransport::with_runtime(&options, handle, None);
+        transport.send_envelope(Event::default().into());
+        assert!(transport.flush(Duration::from_secs(5)));
+
+        let request_line = requests.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(request_line, "POST /api/1/envelope/ HTTP/1.1\r\n");
+    }
+
+    #[test]
+    fn test_bare_429() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let dsn = format!("http://public@{}/1", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            write!(
+                reader.get_mut(),
+                "HTTP/1.1 429 Too Many Requests\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+            )
+            .unwrap();
+        });
+
+        let options = ClientOptions {
+            dsn: Some(dsn.parse().unwrap()),
+            ..Default::default()
+        };
+        let transport = ReqwestHttpTransport::new(&options);
+        transport.send_envelope(Event::default().into());
+        assert!(transport.flush(Duration::from_secs(5)));
+
+        // only the categories of the rejected envelope are limited
+        assert!(transport.time_until_allowed(DataCategory::Error).is_some());
+        assert!(transport
+            .time_until_allowed(DataCategory::Transaction)
+            .is_none());
+    }
+
+    #[test]
+    fn test_read_body() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut reader = BufReader::new(stream.unwrap());
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                let body = "x".repeat(100_000);
+                write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 502 Bad Gateway\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+                .unwrap_or_default();
+            }
+        });
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let client = ReqwestClient::new();
+        runtime.block_on(async {
+            let response = client.get(&url).send().await.unwrap();
+            let (body, truncated) = read_body(response, 1000).await.unwrap();
+            assert_eq!(body.len(), 1000);
+            assert!(truncated);
+
+            let response = client.get(&url).send().await.unwrap();
+            let (body, truncated) = read_body(response, 100_000).await.unwrap();
+            assert_eq!(body.len(), 100_000);
+            assert!(!truncated);
+        });
+    }
 }
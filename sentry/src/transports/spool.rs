@@ -0,0 +1,406 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{sentry_debug, Envelope};
+
+const EXTENSION: &str = "envelope";
+/// The name of the file listing the spooled envelopes.
+const INDEX: &str = "spool.index";
+
+/// Distinguishes envelopes spooled within the same nanosecond.
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A spooled envelope, as listed in the index.
+#[derive(Debug, PartialEq, Eq)]
+struct Entry {
+    /// The name of the file, without the extension.
+    name: String,
+    /// The size of the file in bytes.
+    size: u64,
+    /// When the envelope was stored, in seconds since the epoch.
+    stored: u64,
+}
+
+impl Entry {
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split(' ');
+        let entry = Self {
+            name: fields.next().filter(|name| !name.is_empty())?.to_owned(),
+            size: fields.next()?.parse().ok()?,
+            stored: fields.next()?.parse().ok()?,
+        };
+        fields.next().is_none().then_some(entry)
+    }
+}
+
+/// A directory of serialized envelopes that could not be sent.
+///
+/// Envelopes are stored one per file, named so that sorting the file names
+/// yields them in the order they were stored. An index file lists them in
+/// that order along with their sizes, so that opening the spool and evicting
+/// the oldest envelopes does not need to scan the directory.
+///
+/// The index is replaced atomically, and lists an envelope before its file is
+/// written and until after it was removed, so that a crash can at worst
+/// leave entries for missing files behind. A corrupt or missing index is
+/// rebuilt by scanning the directory. The spool is meant to be used by one
+/// process at a time.
+///
+/// Replayed envelopes stay spooled until they were sent, so that they
+/// are not lost if the process exits first.
+#[derive(Debug)]
+pub struct Spool {
+    dir: PathBuf,
+    max_items: usize,
+    max_bytes: Option<u64>,
+    index: Mutex<Vec<Entry>>,
+    /// The envelopes being replayed, along with the files they were read from.
+    replaying: Mutex<Vec<(PathBuf, Envelope)>>,
+}
+
+impl Spool {
+    /// Opens the spool in `dir`, creating the directory if needed.
+    ///
+    /// Once the spool holds more than `max_items` envelopes or `max_bytes`
+    /// bytes, the oldest envelopes are removed.
+    pub fn new(dir: &Path, max_items: usize, max_bytes: Option<u64>) -> Option<Self> {
+        if let Err(err) = fs::create_dir_all(dir) {
+            sentry_debug!("Failed to create spool directory: {}", err);
+            return None;
+        }
+        let spool = Self {
+            dir: dir.to_owned(),
+            max_items,
+            max_bytes,
+            index: Mutex::new(Vec::new()),
+            replaying: Mutex::new(Vec::new()),
+        };
+        let index = match spool.read_index() {
+            Some(index) => index,
+            None => {
+                let index = spool.scan();
+                spool.write_index(&index);
+                index
+            }
+        };
+        *spool.index.lock().unwrap() = index;
+        Some(spool)
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{name}.{EXTENSION}"))
+    }
+
+    /// Reads the index, returning `None` if it is missing or corrupt.
+    fn read_index(&self) -> Option<Vec<Entry>> {
+        let contents = match fs::read_to_string(self.dir.join(INDEX)) {
+            Ok(contents) => contents,
+            Err(err) => {
+                if err.kind() != io::ErrorKind::NotFound {
+                    sentry_debug!("Failed to read spool index: {}", err);
+                }
+                return None;
+            }
+        };
+        let index: Option<Vec<_>> = contents.lines().map(Entry::parse).collect();
+        if index.is_none() {
+            sentry_debug!("Spool index is corrupt, scanning the spool directory");
+        }
+        index
+    }
+
+    /// Lists the envelopes in the directory, oldest first.
+    fn scan(&self) -> Vec<Entry> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                sentry_debug!("Failed to read spool directory: {}", err);
+                return Vec::new();
+            }
+        };
+        let mut index: Vec<_> = entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let path = entry.path();
+                if path.extension()? != EXTENSION {
+                    return None;
+                }
+                let name = path.file_stem()?.to_str()?.to_owned();
+                // the names start with the nanoseconds they were stored at
+                let nanos: u128 = name.split('-').next()?.parse().ok()?;
+                Some(Entry {
+                    size: entry.metadata().ok()?.len(),
+                    stored: (nanos / 1_000_000_000) as u64,
+                    name,
+                })
+            })
+            .collect();
+        index.sort_by(|a, b| a.name.cmp(&b.name));
+        index
+    }
+
+    /// Replaces the index file, so that a crash leaves either the old or the
+    /// new index behind.
+    fn write_index(&self, index: &[Entry]) -> bool {
+        let contents: String = index
+            .iter()
+            .map(|entry| format!("{} {} {}\n", entry.name, entry.size, entry.stored))
+            .collect();
+        let tmp_path = self.dir.join(format!("{INDEX}.tmp"));
+        let written = fs::write(&tmp_path, contents)
+            .and_then(|_| fs::rename(&tmp_path, self.dir.join(INDEX)));
+        if let Err(err) = written {
+            sentry_debug!("Failed to write spool index: {}", err);
+            fs::remove_file(&tmp_path).ok();
+            return false;
+        }
+        true
+    }
+
+    /// Stores a serialized envelope, removing the oldest ones if the spool is full.
+    pub fn store(&self, body: &[u8]) {
+        if self.max_items == 0 || self.max_bytes.is_some_and(|max| (body.len() as u64) > max) {
+            return;
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let nanos = now.as_nanos();
+        let counter = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let name = format!("{nanos:020}-{counter:010}-{}", process::id());
+
+        let mut index = self.index.lock().unwrap();
+        index.push(Entry {
+            name: name.clone(),
+            size: body.len() as u64,
+            stored: now.as_secs(),
+        });
+        if !self.write_index(&index) {
+            index.pop();
+            return;
+        }
+
+        // Write to a temporary file first, so a crash can't leave a truncated
+        // envelope behind that would be replayed later.
+        let tmp_path = self.dir.join(format!("{name}.tmp"));
+        let path = self.path(&name);
+        if let Err(err) = fs::write(&tmp_path, body).and_then(|_| fs::rename(&tmp_path, &path)) {
+            sentry_debug!("Failed to spool envelope: {}", err);
+            fs::remove_file(&tmp_path).ok();
+            index.pop();
+            self.write_index(&index);
+            return;
+        }
+
+        let mut excess = index.len().saturating_sub(self.max_items);
+        if let Some(max_bytes) = self.max_bytes {
+            let mut bytes: u64 = index[excess..].iter().map(|entry| entry.size).sum();
+            while bytes > max_bytes {
+                bytes -= index[excess].size;
+                excess += 1;
+            }
+        }
+        if excess == 0 {
+            return;
+        }
+        for entry in &index[..excess] {
+            let path = self.path(&entry.name);
+            sentry_debug!("Spool is full, dropping {}", path.display());
+            remove_file(&path);
+        }
+        index.drain(..excess);
+        self.write_index(&index);
+    }
+
+    /// Returns the paths of all spooled envelopes, oldest first.
+    pub fn pending(&self) -> Vec<PathBuf> {
+        let index = self.index.lock().unwrap();
+        index.iter().map(|entry| self.path(&entry.name)).collect()
+    }
+
+    /// Reads the spooled envelopes back and passes them to `send`, oldest first,
+    /// until it returns `false`.
+    ///
+    /// The envelopes stay spooled until their file is looked up with
+    /// [`Spool::replayed_from`] and removed. Files which can't be read are
+    /// removed right away.
+    pub fn replay<F>(&self, mut send: F)
+    where
+        F: FnMut(Envelope) -> bool,
+    {
+        let mut unreadable = Vec::new();
+        for path in self.pending() {
+            let envelope = match Envelope::from_path_raw(&path) {
+                Ok(envelope) => envelope,
+                Err(err) => {
+                    sentry_debug!("Failed to read spooled envelope: {}", err);
+                    unreadable.push(path);
+                    continue;
+                }
+            };
+            // remembered first, as the envelope may be sent right away
+            self.replaying
+                .lock()
+                .unwrap()
+                .push((path, envelope.clone()));
+            if !send(envelope) {
+                self.replaying.lock().unwrap().pop();
+                break;
+            }
+        }
+        self.remove(&unreadable);
+    }
+
+    /// Returns the file a replayed `envelope` was read from, forgetting about
+    /// it, or [`None`] if it was not replayed.
+    pub fn replayed_from(&self, envelope: &Envelope) -> Option<PathBuf> {
+        let mut replaying = self.replaying.lock().unwrap();
+        let index = replaying
+            .iter()
+            .position(|(_, replayed)| replayed == envelope)?;
+        Some(replaying.swap_remove(index).0)
+    }
+
+    /// Removes spooled envelopes.
+    pub fn remove(&self, paths: &[PathBuf]) {
+        for path in paths {
+            remove_file(path);
+        }
+        let mut index = self.index.lock().unwrap();
+        let len = index.len();
+        index.retain(|entry| !paths.contains(&self.path(&entry.name)));
+        if index.len() != len {
+            self.write_index(&index);
+        }
+    }
+}
+
+fn remove_file(path: &Path) {
+    if let Err(err) = fs::remove_file(path) {
+        if err.kind() != io::ErrorKind::NotFound {
+            sentry_debug!("Failed to remove spooled envelope: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sentry-{name}-test-{}", process::id()));
+        fs::remove_dir_all(&dir).ok();
+        dir
+    }
+
+    #[test]
+    fn test_spool() {
+        let dir = temp_dir("spool");
+
+        let spool = Spool::new(&dir, 2, None).unwrap();
+        assert!(spool.pending().is_empty());
+
+        spool.store(b"first");
+        spool.store(b"second");
+        spool.store(b"third");
+
+        let pending = spool.pending();
+        assert_eq!(pending.len(), 2);
+        assert_eq!(fs::read(&pending[0]).unwrap(), b"second");
+        assert_eq!(fs::read(&pending[1]).unwrap(), b"third");
+
+        spool.remove(&pending[..1]);
+        assert_eq!(spool.pending(), &pending[1..]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_replay() {
+        let dir = temp_dir("spool-replay");
+
+        let spool = Spool::new(&dir, 10, None).unwrap();
+        spool.store(b"first");
+        spool.store(b"second");
+        spool.store(b"third");
+        let pending = spool.pending();
+        fs::remove_file(&pending[1]).unwrap();
+
+        // the third envelope is not accepted, and the unreadable one is removed
+        let mut replayed = Vec::new();
+        spool.replay(|envelope| {
+            replayed.push(envelope);
+            replayed.len() < 2
+        });
+        assert_eq!(replayed.len(), 2);
+        assert_eq!(spool.pending(), [pending[0].clone(), pending[2].clone()]);
+        assert_eq!(spool.replayed_from(&replayed[0]), Some(pending[0].clone()));
+        assert_eq!(spool.replayed_from(&replayed[0]), None);
+        assert_eq!(spool.replayed_from(&replayed[1]), None);
+
+        // what was replayed stays spooled until it is removed
+        replayed.clear();
+        spool.replay(|envelope| {
+            replayed.push(envelope);
+            true
+        });
+        assert_eq!(replayed.len(), 2);
+        assert_eq!(spool.replayed_from(&replayed[1]), Some(pending[2].clone()));
+        assert_eq!(spool.pending(), [pending[0].clone(), pending[2].clone()]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_max_bytes() {
+        let dir = temp_dir("spool-bytes");
+
+        let spool = Spool::new(&dir, 10, Some(10)).unwrap();
+        spool.store(b"first");
+        spool.store(b"second");
+        // too large to be stored at all
+        spool.store(b"far too large");
+        let pending = spool.pending();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(fs::read(&pending[0]).unwrap(), b"second");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_index() {
+        let dir = temp_dir("spool-index");
+
+        let spool = Spool::new(&dir, 10, None).unwrap();
+        spool.store(b"first");
+        spool.store(b"second");
+        let pending = spool.pending();
+        drop(spool);
+
+        // the index is used rather than the directory
+        let index = fs::read_to_string(dir.join(INDEX)).unwrap();
+        assert_eq!(index.lines().count(), 2);
+        assert!(index.lines().next().unwrap().contains(" 5 "));
+        fs::write(dir.join("00000000000000000000-0000000000-0.envelope"), b"").unwrap();
+        assert_eq!(Spool::new(&dir, 10, None).unwrap().pending(), pending);
+
+        // a corrupt index is rebuilt from the directory
+        fs::remove_file(dir.join("00000000000000000000-0000000000-0.envelope")).unwrap();
+        fs::write(dir.join(INDEX), "garbage\n").unwrap();
+        let spool = Spool::new(&dir, 10, None).unwrap();
+        assert_eq!(spool.pending(), pending);
+        assert_eq!(fs::read_to_string(dir.join(INDEX)).unwrap(), index);
+
+        // entries left behind for missing files are removed like others
+        fs::remove_file(&pending[0]).unwrap();
+        spool.remove(&pending[..1]);
+        assert_eq!(spool.pending(), &pending[1..]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
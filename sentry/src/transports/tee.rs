@@ -0,0 +1,139 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::protocol::DataCategory;
+use crate::{Envelope, Transport, TransportError};
+
+/// The type of the callback seeing every envelope of a [`TeeTransport`].
+type Sink = dyn Fn(&Envelope) + Send + Sync;
+
+/// A [`Transport`] that shows every envelope to a callback before forwarding
+/// it to another transport.
+///
+/// This makes it easy to see what is sent during development, without
+/// affecting the delivery to Sentry. The callback runs on the thread
+/// submitting the envelope, so it should be quick.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use std::sync::Arc;
+/// use sentry::transports::{DefaultTransportFactory, TeeTransport};
+/// use sentry::TransportFactory;
+///
+/// let options = sentry::ClientOptions {
+///     dsn: "https://public@example.com/1".parse().ok(),
+///     ..Default::default()
+/// };
+/// let primary = DefaultTransportFactory.create_transport(&options);
+/// let transport = TeeTransport::new(primary, |envelope| {
+///     for item in envelope.items() {
+///         eprintln!("sending {item:#?}");
+///     }
+/// });
+/// let _sentry = sentry::init(sentry::ClientOptions {
+///     transport: Some(Arc::new(Arc::new(transport))),
+///     ..options
+/// });
+/// ```
+pub struct TeeTransport {
+    primary: Arc<dyn Transport>,
+    sink: Box<Sink>,
+}
+
+impl TeeTransport {
+    /// Creates a new Transport calling `sink` with every envelope before
+    /// handing it to `primary`.
+    pub fn new<F>(primary: Arc<dyn Transport>, sink: F) -> Self
+    where
+        F: Fn(&Envelope) + Send + Sync + 'static,
+    {
+        Self {
+            primary,
+            sink: Box::new(sink),
+        }
+    }
+}
+
+impl Transport for TeeTransport {
+    fn send_envelope(&self, envelope: Envelope) {
+        (self.sink)(&envelope);
+        self.primary.send_envelope(envelope);
+    }
+
+    fn flush(&self, timeout: Duration) -> bool {
+        self.primary.flush(timeout)
+    }
+
+    fn flush_remaining(&self, timeout: Duration) -> usize {
+        self.primary.flush_remaining(timeout)
+    }
+
+    fn flush_category(&self, category: DataCategory, timeout: Duration) -> bool {
+        self.primary.flush_category(category, timeout)
+    }
+
+    fn shutdown(&self, timeout: Duration) -> bool {
+        self.primary.shutdown(timeout)
+    }
+
+    fn clear_rate_limits(&self) {
+        self.primary.clear_rate_limits()
+    }
+
+    fn time_until_allowed(&self, category: DataCategory) -> Option<Duration> {
+        self.primary.time_until_allowed(category)
+    }
+
+    fn last_error(&self) -> Option<TransportError> {
+        self.primary.last_error()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::protocol::Event;
+
+    #[derive(Default)]
+    struct Recording {
+        sent: Mutex<Vec<Envelope>>,
+        shut_down: Mutex<bool>,
+    }
+
+    impl Transport for Recording {
+        fn send_envelope(&self, envelope: Envelope) {
+            self.sent.lock().unwrap().push(envelope);
+        }
+
+        fn shutdown(&self, _timeout: Duration) -> bool {
+            *self.shut_down.lock().unwrap() = true;
+            false
+        }
+    }
+
+    #[test]
+    fn test_tee() {
+        let primary = Arc::new(Recording::default());
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let transport = TeeTransport::new(primary.clone(), {
+            let seen = seen.clone();
+            move |envelope: &Envelope| seen.lock().unwrap().push(envelope.uuid().copied())
+        });
+
+        let event = Event::default();
+        let event_id = event.event_id;
+        transport.send_envelope(event.into());
+        assert_eq!(*seen.lock().unwrap(), [Some(event_id)]);
+        let sent = primary.sent.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].uuid(), Some(&event_id));
+        drop(sent);
+
+        // the primary decides whether shutting down succeeded
+        assert!(!transport.shutdown(Duration::from_secs(1)));
+        assert!(*primary.shut_down.lock().unwrap());
+    }
+}
@@ -1,100 +1,1854 @@
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::any::Any;
+use std::cmp::Reverse;
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::mpsc::{sync_channel, SyncSender};
-use std::sync::Arc;
+use std::sync::{Arc, Condvar, Mutex};
 use std::thread::{self, JoinHandle};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use super::ratelimit::{RateLimiter, RateLimitingCategory};
-use crate::{sentry_debug, Envelope};
+use super::client_report::{ClientReports, CLIENT_REPORT_INTERVAL};
+use super::ratelimit::{has_category, RateLimiter, RateLimitingCategory};
+use crate::protocol::{
+    DataCategory, DiscardReason, EnvelopeItem, MonitorCheckIn, MonitorCheckInStatus,
+};
+use crate::{
+    sentry_debug, ClientOptions, Envelope, QueueOverflowPolicy, TransportError, TransportErrorKind,
+};
+
+/// The result of sending an envelope, see [`TransportThread::send_sync`].
+pub type SendResult = Result<(), TransportError>;
 
 enum Task {
     SendEnvelope(Envelope),
+    /// Sends an envelope and reports the result back.
+    SendSync(Envelope, SyncSender<SendResult>),
     Flush(SyncSender<()>),
     Shutdown,
 }
 
+#[derive(Default)]
+struct QueueState {
+    tasks: VecDeque<Task>,
+    envelopes: usize,
+}
+
+/// The queue of tasks for the worker thread.
+///
+/// Only envelopes count towards its capacity, flushes and shutdowns are always queued.
+struct TaskQueue {
+    state: Mutex<QueueState>,
+    condvar: Condvar,
+    capacity: usize,
+    policy: QueueOverflowPolicy,
+}
+
+impl TaskQueue {
+    fn new(capacity: usize, policy: QueueOverflowPolicy) -> Self {
+        Self {
+            state: Default::default(),
+            condvar: Condvar::new(),
+            capacity,
+            policy,
+        }
+    }
+
+    /// Queues an envelope, returning the oldest envelope if it was dropped to make room.
+    ///
+    /// Returns the envelope itself as an error if it could not be queued.
+    fn push_envelope(&self, envelope: Envelope) -> Result<Option<Envelope>, Envelope> {
+        let mut state = self.state.lock().unwrap();
+        let mut dropped = None;
+        if state.envelopes >= self.capacity {
+            let oldest = state
+                .tasks
+                .iter()
+                .position(|task| matches!(task, Task::SendEnvelope(_)));
+            match (self.policy, oldest) {
+                (QueueOverflowPolicy::DropOldest, Some(index)) => {
+                    if let Some(Task::SendEnvelope(oldest)) = state.tasks.remove(index) {
+                        dropped = Some(oldest);
+                    }
+                    state.envelopes -= 1;
+                }
+                _ => return Err(envelope),
+            }
+        }
+        state.envelopes += 1;
+        state.tasks.push_back(Task::SendEnvelope(envelope));
+        self.condvar.notify_one();
+        Ok(dropped)
+    }
+
+    fn push(&self, task: Task) {
+        self.state.lock().unwrap().tasks.push_back(task);
+        self.condvar.notify_one();
+    }
+
+    /// Moves the envelopes carrying items of `category` to the front of the
+    /// queue, in the order they were queued, followed by `task`.
+    fn push_after_category(&self, category: DataCategory, task: Task) {
+        let mut state = self.state.lock().unwrap();
+        let (mut tasks, rest): (VecDeque<_>, VecDeque<_>) = state.tasks.drain(..).partition(
+            |task| matches!(task, Task::SendEnvelope(envelope) if has_category(envelope, category)),
+        );
+        tasks.push_back(task);
+        tasks.extend(rest);
+        state.tasks = tasks;
+        self.condvar.notify_one();
+    }
+
+    /// Takes the next task if it is an envelope, without waiting.
+    fn try_pop_envelope(&self) -> Option<Envelope> {
+        let mut state = self.state.lock().unwrap();
+        match state.tasks.pop_front() {
+            Some(Task::SendEnvelope(envelope)) => {
+                state.envelopes -= 1;
+                Some(envelope)
+            }
+            Some(task) => {
+                state.tasks.push_front(task);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Puts an envelope taken via [`TaskQueue::try_pop_envelope`] back in front.
+    fn unpop_envelope(&self, envelope: Envelope) {
+        let mut state = self.state.lock().unwrap();
+        state.envelopes += 1;
+        state.tasks.push_front(Task::SendEnvelope(envelope));
+    }
+
+    /// Takes all the queued envelopes, leaving the other tasks.
+    fn drain_envelopes(&self) -> Vec<Envelope> {
+        let mut state = self.state.lock().unwrap();
+        let (envelopes, tasks): (VecDeque<_>, VecDeque<_>) = state
+            .tasks
+            .drain(..)
+            .partition(|task| matches!(task, Task::SendEnvelope(_)));
+        state.tasks = tasks;
+        state.envelopes = 0;
+        envelopes
+            .into_iter()
+            .filter_map(|task| match task {
+                Task::SendEnvelope(envelope) => Some(envelope),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the number of envelopes waiting to be sent.
+    fn envelopes(&self) -> usize {
+        self.state.lock().unwrap().envelopes
+    }
+
+    /// Waits for the next task until `deadline`, returning `None` if there was none.
+    fn pop_until(&self, deadline: Instant) -> Option<Task> {
+        let guard = self.state.lock().unwrap();
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        let (mut state, _) = self
+            .condvar
+            .wait_timeout_while(guard, timeout, |state| state.tasks.is_empty())
+            .unwrap();
+        let task = state.tasks.pop_front()?;
+        if let Task::SendEnvelope(_) = task {
+            state.envelopes -= 1;
+        }
+        Some(task)
+    }
+
+    /// Waits for the next task.
+    fn pop(&self) -> Task {
+        let guard = self.state.lock().unwrap();
+        let mut state = self
+            .condvar
+            .wait_while(guard, |state| state.tasks.is_empty())
+            .unwrap();
+        let task = state.tasks.pop_front().unwrap();
+        if let Task::SendEnvelope(_) = task {
+            state.envelopes -= 1;
+        }
+        task
+    }
+}
+
+/// Allows the send function to wait, while waking it up early when the transport
+/// is being flushed or shut down.
+///
+/// Once shutting down timed out, the transport aborts, and the send function
+/// should cancel the request it is making.
+#[derive(Clone, Debug, Default)]
+pub struct Interrupt {
+    inner: Arc<(Mutex<bool>, Condvar)>,
+    aborted: Arc<AtomicBool>,
+}
+
+impl Interrupt {
+    /// Sleeps for the given `duration`.
+    ///
+    /// Returns `false` if the sleep was cut short because of a flush or shutdown.
+    #[cfg_attr(not(feature = "ureq"), allow(dead_code))]
+    pub fn sleep(&self, duration: Duration) -> bool {
+        let (lock, condvar) = &*self.inner;
+        let guard = lock.lock().unwrap();
+        let (guard, _) = condvar
+            .wait_timeout_while(guard, duration, |interrupted| !*interrupted)
+            .unwrap();
+        !*guard
+    }
+
+    /// Returns whether the transport gave up on sending the envelopes left,
+    /// because shutting down timed out.
+    #[cfg_attr(not(any(feature = "curl", feature = "ureq")), allow(dead_code))]
+    pub fn is_aborted(&self) -> bool {
+        self.aborted.load(Ordering::SeqCst)
+    }
+
+    fn abort(&self) {
+        self.aborted.store(true, Ordering::SeqCst);
+        self.trigger();
+    }
+
+    fn trigger(&self) {
+        let (lock, condvar) = &*self.inner;
+        *lock.lock().unwrap() = true;
+        condvar.notify_all();
+    }
+
+    fn reset(&self) {
+        *self.inner.0.lock().unwrap() = false;
+    }
+}
+
+/// Returns the size of the serialized `envelope`, or [`usize::MAX`] if it
+/// can't be serialized.
+fn serialized_len(envelope: &Envelope) -> usize {
+    envelope.serialized_len().unwrap_or(usize::MAX)
+}
+
+/// Merges the envelopes waiting at the front of the `queue` into `envelope`,
+/// as long as the result stays within `max_bytes`.
+///
+/// Every queued envelope is passed through `admit` before it is measured, so
+/// that items which will not be sent anyway are never serialized. Merging
+/// stops at the first envelope that can't be merged, to preserve the order in
+/// which envelopes are sent.
+fn coalesce<F>(
+    mut envelope: Envelope,
+    queue: &TaskQueue,
+    max_bytes: usize,
+    mut admit: F,
+) -> Envelope
+where
+    F: FnMut(Envelope) -> Option<Envelope>,
+{
+    let mut size = serialized_len(&envelope);
+    while let Some(next) = queue.try_pop_envelope() {
+        let Some(next) = admit(next) else {
+            continue;
+        };
+        let next_size = serialized_len(&next);
+        if size.saturating_add(next_size) > max_bytes {
+            queue.unpop_envelope(next);
+            break;
+        }
+        if let Err(next) = envelope.merge(next) {
+            queue.unpop_envelope(next);
+            break;
+        }
+        size += next_size;
+    }
+    envelope
+}
+
+/// The slug of the monitor receiving the check-ins configured by
+/// [`ClientOptions::heartbeat_interval`].
+const HEARTBEAT_MONITOR_SLUG: &str = "sentry-transport-heartbeat";
+
+/// How long [`TransportThread::shutdown`] waits for the worker thread to exit
+/// after aborting the send in flight.
+const ABORT_GRACE_PERIOD: Duration = Duration::from_millis(200);
+
+/// Creates the check-in a transport sends as a heartbeat.
+fn heartbeat(environment: Option<String>) -> Envelope {
+    MonitorCheckIn {
+        check_in_id: crate::types::random_uuid(),
+        monitor_slug: HEARTBEAT_MONITOR_SLUG.into(),
+        status: MonitorCheckInStatus::Ok,
+        environment,
+        duration: None,
+        monitor_config: None,
+    }
+    .into()
+}
+
+/// Removes the attachments of `envelope` which are not sampled at `rate`,
+/// recording them as discarded.
+fn sample_attachments(envelope: Envelope, rate: f32, reports: &ClientReports) -> Option<Envelope> {
+    if rate >= 1.0 {
+        return Some(envelope);
+    }
+    envelope.filter(|item| {
+        if !matches!(item, EnvelopeItem::Attachment(_)) {
+            return true;
+        }
+        let sampled = rate > 0.0 && rand::random::<f32>() < rate;
+        if !sampled {
+            reports.record_item(DiscardReason::SampleRate, item);
+        }
+        sampled
+    })
+}
+
+/// Removes the attachments of `envelope` past the first `max`, recording them
+/// as discarded.
+fn cap_attachments(envelope: Envelope, max: usize, reports: &ClientReports) -> Option<Envelope> {
+    let mut attachments = 0;
+    envelope.filter(|item| {
+        if !matches!(item, EnvelopeItem::Attachment(_)) {
+            return true;
+        }
+        attachments += 1;
+        let keep = attachments <= max;
+        if !keep {
+            reports.record_item(DiscardReason::TooManyItems, item);
+        }
+        keep
+    })
+}
+
+/// Returns how important an envelope `item` is, items of a lower priority
+/// being dropped first when an envelope has too many items.
+fn item_priority(item: &EnvelopeItem) -> u8 {
+    match item {
+        EnvelopeItem::Attachment(_) => 0,
+        EnvelopeItem::Profile(_) => 1,
+        EnvelopeItem::ItemContainer(_) => 2,
+        EnvelopeItem::SessionUpdate(_) | EnvelopeItem::SessionAggregates(_) => 3,
+        EnvelopeItem::Event(_) | EnvelopeItem::Transaction(_) => 5,
+        _ => 4,
+    }
+}
+
+/// Removes the items of `envelope` past the first `max`, lowest priority
+/// first, recording them as discarded.
+///
+/// Items of the same priority are kept in the order of the envelope.
+fn cap_items(envelope: Envelope, max: usize, reports: &ClientReports) -> Option<Envelope> {
+    let mut ranked: Vec<_> = envelope.items().map(item_priority).enumerate().collect();
+    if ranked.len() <= max {
+        return Some(envelope);
+    }
+    ranked.sort_by_key(|&(index, priority)| (Reverse(priority), index));
+    let mut keep = vec![false; ranked.len()];
+    for &(index, _) in &ranked[..max] {
+        keep[index] = true;
+    }
+    let mut index = 0;
+    envelope.filter(|item| {
+        let kept = keep[index];
+        index += 1;
+        if !kept {
+            reports.record_item(DiscardReason::TooManyItems, item);
+        }
+        kept
+    })
+}
+
+/// Removes attachments from `envelope`, largest first, until its serialized
+/// size is within `max_size`.
+///
+/// Drops the whole envelope if it is still too large without them. Everything
+/// dropped is recorded as discarded.
+fn limit_size(envelope: Envelope, max_size: usize, reports: &ClientReports) -> Option<Envelope> {
+    // the send function reports envelopes which can't be serialized
+    let Ok(mut size) = envelope.serialized_len() else {
+        return Some(envelope);
+    };
+    let mut envelope = envelope;
+    while size > max_size {
+        let largest = envelope
+            .items()
+            .enumerate()
+            .filter_map(|(index, item)| match item {
+                EnvelopeItem::Attachment(attachment) => Some((attachment.buffer.len(), index)),
+                _ => None,
+            })
+            .max();
+        let Some((_, largest)) = largest else {
+            break;
+        };
+        let mut index = 0;
+        envelope = envelope.filter(|item| {
+            let keep = index != largest;
+            index += 1;
+            if !keep {
+                sentry_debug!("attachment dropped: envelope exceeds {} bytes", max_size);
+                reports.record_item(DiscardReason::TooLarge, item);
+            }
+            keep
+        })?;
+        size = serialized_len(&envelope);
+    }
+    if size > max_size {
+        sentry_debug!("envelope dropped: {} bytes exceed {} bytes", size, max_size);
+        reports.record_envelope(DiscardReason::TooLarge, &envelope);
+        return None;
+    }
+    Some(envelope)
+}
+
+/// The function actually sending an envelope, see [`TransportThread::new`].
+type SendFn =
+    dyn FnMut(Envelope, &mut RateLimiter, &Interrupt, &ClientReports) -> SendResult + Send;
+
+/// The function taking the envelopes not sent while the circuit breaker is
+/// open, see [`TransportThread::with_fallback`].
+type FallbackFn = dyn FnMut(Envelope, &ClientReports) + Send;
+
+/// Stops sending envelopes for a while after too many consecutive network failures.
+///
+/// Once the cooldown is over, a single envelope is sent as a probe: the
+/// breaker closes if it reaches the server, and opens again otherwise.
+struct CircuitBreaker {
+    /// The number of consecutive failures opening the breaker, or 0 to never open it.
+    threshold: usize,
+    cooldown: Duration,
+    failures: usize,
+    /// Until when the breaker is open, kept past that while probing.
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    fn new(options: &ClientOptions) -> Self {
+        Self {
+            threshold: options.circuit_breaker_threshold,
+            cooldown: options.circuit_breaker_cooldown,
+            failures: 0,
+            open_until: None,
+        }
+    }
+
+    /// Returns whether envelopes should currently not be sent.
+    fn is_open(&self) -> bool {
+        self.open_until
+            .is_some_and(|open_until| Instant::now() < open_until)
+    }
+
+    /// Records whether sending an envelope `reached` the server.
+    fn record(&mut self, reached: bool) {
+        if self.threshold == 0 {
+            return;
+        }
+        if reached {
+            if self.open_until.take().is_some() {
+                sentry_debug!("Circuit breaker closed, the server is reachable again");
+            }
+            self.failures = 0;
+            return;
+        }
+        self.failures = self.failures.saturating_add(1);
+        // a failed probe opens the breaker right away
+        if self.open_until.is_some() || self.failures >= self.threshold {
+            sentry_debug!(
+                "Circuit breaker opened after {} consecutive network failures",
+                self.failures
+            );
+            self.open_until = Some(Instant::now() + self.cooldown);
+        }
+    }
+}
+
+/// Drops the envelope items of the least valuable categories while the
+/// transport is under pressure, see [`ClientOptions::load_shedding_order`].
+#[derive(Clone)]
+struct LoadShedder {
+    /// The categories to drop, the least valuable first.
+    order: Vec<DataCategory>,
+    /// The number of queued envelopes from which on items are dropped.
+    high_water_mark: usize,
+    capacity: usize,
+}
+
+impl LoadShedder {
+    fn new(options: &ClientOptions) -> Self {
+        let capacity = options.transport_queue_size;
+        let fraction = options.load_shedding_high_water_mark.clamp(0.0, 1.0);
+        Self {
+            order: options.load_shedding_order.clone(),
+            high_water_mark: (capacity as f32 * fraction).ceil() as usize,
+            capacity,
+        }
+    }
+
+    /// Returns how valuable the items of `category` are, categories that are
+    /// not listed being the most valuable.
+    fn rank(&self, category: DataCategory) -> usize {
+        self.order
+            .iter()
+            .position(|&shed| shed == category)
+            .unwrap_or(self.order.len())
+    }
+
+    /// Returns how many of the categories to drop with `queued` envelopes
+    /// waiting to be sent.
+    fn queue_level(&self, queued: usize) -> usize {
+        if self.order.is_empty() || queued < self.high_water_mark {
+            return 0;
+        }
+        // the levels are spread evenly up to a full queue
+        let span = self.capacity.saturating_sub(self.high_water_mark) + 1;
+        let above = queued - self.high_water_mark + 1;
+        (above * self.order.len())
+            .div_ceil(span)
+            .min(self.order.len())
+    }
+
+    /// Returns how many of the categories to drop so they don't take up the
+    /// quota of more valuable categories which are rate limited.
+    fn rate_limit_level(&self, rl: &RateLimiter) -> usize {
+        if self.order.is_empty() {
+            return 0;
+        }
+        rl.disabled_categories()
+            .map(|category| self.rank(category))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Removes the items of the first `level` categories from `envelope`,
+    /// recording them as discarded for `reason`.
+    fn shed(
+        &self,
+        envelope: Envelope,
+        level: usize,
+        reason: DiscardReason,
+        reports: &ClientReports,
+    ) -> Option<Envelope> {
+        if level == 0 {
+            return Some(envelope);
+        }
+        let shed = &self.order[..level];
+        let envelope = envelope.filter(|item| {
+            let keep = item
+                .data_category()
+                .map_or(true, |category| !shed.contains(&category));
+            if !keep {
+                reports.record_item(reason, item);
+            }
+            keep
+        });
+        if envelope.is_none() {
+            transport_event!("Envelope was discarded to shed load", level = level);
+        }
+        envelope
+    }
+}
+
+/// The panics caught while sending envelopes, shared with the [`TransportThread`].
+#[derive(Default)]
+struct Panics {
+    count: AtomicUsize,
+    last: Mutex<Option<TransportError>>,
+}
+
+impl Panics {
+    /// Records a panic with the given `payload`, returning the error the send
+    /// failed with.
+    fn record(&self, payload: Box<dyn Any + Send>) -> TransportError {
+        let message = match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => match payload.downcast::<&'static str>() {
+                Ok(message) => message.to_string(),
+                Err(_) => "Box<dyn Any>".to_owned(),
+            },
+        };
+        sentry_debug!("Sending an envelope panicked, dropping it: {}", message);
+        self.count.fetch_add(1, Ordering::SeqCst);
+        let error = TransportError::new(
+            TransportErrorKind::Panicked,
+            format!("sending the envelope panicked: {message}"),
+        );
+        *self.last.lock().unwrap() = Some(error.clone());
+        error
+    }
+}
+
+/// Sends envelopes, applying the rate limits and sending client reports.
+struct Worker {
+    send: Box<SendFn>,
+    fallback: Option<Box<FallbackFn>>,
+    breaker: CircuitBreaker,
+    shedder: LoadShedder,
+    rl: RateLimiter,
+    /// Set by [`TransportThread::clear_rate_limits`], for the next envelope to
+    /// lift the limits.
+    clear_rate_limits: Arc<AtomicBool>,
+    /// A copy of `rl` as of the last change, for
+    /// [`TransportThread::time_until_allowed`].
+    published_rl: Arc<Mutex<RateLimiter>>,
+    rate_limit_store: Option<PathBuf>,
+    max_envelope_size: usize,
+    interrupt: Interrupt,
+    reports: ClientReports,
+    last_report: Instant,
+    panics: Arc<Panics>,
+}
+
+impl Worker {
+    /// Applies the rate limits to `envelope`.
+    ///
+    /// This happens before anything else, so that envelopes which can't be sent
+    /// are never serialized.
+    fn admit(&mut self, envelope: Envelope) -> Option<Envelope> {
+        if self.clear_rate_limits.swap(false, Ordering::SeqCst) {
+            self.rl.clear();
+            self.publish_rl();
+        }
+        if let Some(time_left) = self.rl.is_disabled(RateLimitingCategory::Any) {
+            transport_event!(
+                "Skipping event send because we're disabled due to rate limits",
+                seconds_left = time_left.as_secs(),
+            );
+            self.reports
+                .record_envelope(DiscardReason::RateLimitBackoff, &envelope);
+            return None;
+        }
+        let envelope = self.rl.filter_envelope(envelope, |item| {
+            self.reports
+                .record_item(DiscardReason::RateLimitBackoff, item)
+        });
+        if envelope.is_none() {
+            transport_event!("Envelope was discarded due to per-item rate limits");
+        }
+        self.shedder.shed(
+            envelope?,
+            self.shedder.rate_limit_level(&self.rl),
+            DiscardReason::RateLimitBackoff,
+            &self.reports,
+        )
+    }
+
+    /// Sends an envelope which already passed [`Worker::admit`], unless it is
+    /// too large to be accepted.
+    fn send(&mut self, envelope: Envelope) -> SendResult {
+        let Some(envelope) = limit_size(envelope, self.max_envelope_size, &self.reports) else {
+            return Err(TransportError::new(
+                TransportErrorKind::Discarded,
+                "envelope is too large",
+            ));
+        };
+        if self.breaker.is_open() {
+            transport_event!("Skipping envelope send because the circuit breaker is open");
+            match self.fallback {
+                Some(ref mut fallback) => fallback(envelope, &self.reports),
+                None => self
+                    .reports
+                    .record_envelope(DiscardReason::NetworkError, &envelope),
+            }
+            return Err(TransportError::new(
+                TransportErrorKind::Network,
+                "circuit breaker is open",
+            ));
+        }
+        // A panic only loses the envelope being sent, rather than the worker
+        // and all envelopes after it.
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            (self.send)(envelope, &mut self.rl, &self.interrupt, &self.reports)
+        }))
+        .unwrap_or_else(|payload| Err(self.panics.record(payload)));
+        self.publish_rl();
+        let reached = !matches!(result, Err(ref err) if err.kind.is_network());
+        self.breaker.record(reached);
+        result
+    }
+
+    /// Makes the current rate limits visible to [`TransportThread::time_until_allowed`].
+    fn publish_rl(&self) {
+        *self.published_rl.lock().unwrap() = self.rl.clone();
+    }
+
+    /// Applies the rate limits to `envelope` and sends it, returning the result.
+    fn send_sync(&mut self, envelope: Envelope) -> SendResult {
+        let result = match self.admit(envelope) {
+            Some(envelope) => self.send(envelope),
+            None => Err(TransportError::new(
+                TransportErrorKind::RateLimited,
+                "envelope is rate limited",
+            )),
+        };
+        self.maybe_send_report();
+        result
+    }
+
+    /// Sends a heartbeat check-in, unless it is rate limited.
+    fn send_heartbeat(&mut self, environment: Option<String>) {
+        if let Some(envelope) = self.admit(heartbeat(environment)) {
+            self.send(envelope).ok();
+        }
+        self.maybe_send_report();
+    }
+
+    /// Sends the client reports accumulated so far.
+    fn send_report(&mut self) {
+        if let Some(report) = self.reports.take_envelope() {
+            if let Some(report) = self.admit(report) {
+                self.send(report).ok();
+            }
+        }
+        self.last_report = Instant::now();
+    }
+
+    /// Sends the client reports if they haven't been sent in a while.
+    fn maybe_send_report(&mut self) {
+        if self.last_report.elapsed() >= CLIENT_REPORT_INTERVAL {
+            self.send_report();
+        }
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        if let Some(ref path) = self.rate_limit_store {
+            // hold the lock, so this can't race with `TransportThread::shutdown`
+            let _published = self.published_rl.lock().unwrap();
+            save_rate_limits(&self.rl, path);
+        }
+    }
+}
+
+/// Persists the rate limits of `rl` to the file at `path`.
+fn save_rate_limits(rl: &RateLimiter, path: &Path) {
+    if let Err(err) = rl.save(path) {
+        sentry_debug!("Failed to persist rate limits: {}", err);
+    }
+}
+
+/// How a [`TransportThread`] sends its envelopes.
+enum Mode {
+    /// Envelopes are queued and sent by a worker thread.
+    Thread {
+        queue: Arc<TaskQueue>,
+        shutdown: Arc<AtomicBool>,
+        handle: Mutex<Option<JoinHandle<()>>>,
+    },
+    /// Envelopes are sent right away, on the thread handing them over.
+    Blocking(Box<Mutex<Worker>>),
+}
+
 pub struct TransportThread {
-    sender: SyncSender<Task>,
-    shutdown: Arc<AtomicBool>,
-    handle: Option<JoinHandle<()>>,
+    mode: Mode,
+    shedder: LoadShedder,
+    attachment_sample_rate: f32,
+    max_attachments: Option<usize>,
+    max_items: Option<usize>,
+    /// Set once [`TransportThread::shutdown`] was called, rejecting new envelopes.
+    closed: AtomicBool,
+    interrupt: Interrupt,
+    reports: ClientReports,
+    clear_rate_limits: Arc<AtomicBool>,
+    published_rl: Arc<Mutex<RateLimiter>>,
+    rate_limit_store: Option<PathBuf>,
+    panics: Arc<Panics>,
 }
 
 impl TransportThread {
-    pub fn new<SendFn>(mut send: SendFn) -> Self
+    /// Creates a new transport thread sending envelopes with `send`.
+    ///
+    /// With [`ClientOptions::blocking_transport`], no thread is spawned and
+    /// envelopes are sent as soon as they are handed to [`TransportThread::send`].
+    ///
+    /// `send` returns the error sending an envelope failed with. Network errors
+    /// are what the circuit breaker configured by
+    /// [`ClientOptions::circuit_breaker_threshold`] counts. While that is open,
+    /// envelopes are dropped.
+    ///
+    /// If `send` panics, the envelope it was sending is dropped and the panic
+    /// is recorded, see [`TransportThread::last_panic`], while the envelopes
+    /// after it are sent as usual.
+    #[cfg_attr(not(feature = "curl"), allow(dead_code))]
+    pub fn new<F>(options: &ClientOptions, send: F) -> Self
+    where
+        F: FnMut(Envelope, &mut RateLimiter, &Interrupt, &ClientReports) -> SendResult
+            + Send
+            + 'static,
+    {
+        Self::new_internal(options, Interrupt::default(), Box::new(send), None)
+    }
+
+    /// Creates a new transport thread like [`TransportThread::new`], which
+    /// hands the envelopes not sent while the circuit breaker is open to
+    /// `fallback` instead of dropping them. It is responsible for recording
+    /// the envelopes it drops in the client reports.
+    ///
+    /// `interrupt` is the one handed to `send`, so that the HTTP client can
+    /// watch it for aborts as well.
+    #[cfg_attr(not(feature = "ureq"), allow(dead_code))]
+    pub fn with_fallback<F, G>(
+        options: &ClientOptions,
+        interrupt: Interrupt,
+        send: F,
+        fallback: G,
+    ) -> Self
     where
-        SendFn: FnMut(Envelope, &mut RateLimiter) + Send + 'static,
+        F: FnMut(Envelope, &mut RateLimiter, &Interrupt, &ClientReports) -> SendResult
+            + Send
+            + 'static,
+        G: FnMut(Envelope, &ClientReports) + Send + 'static,
     {
-        let (sender, receiver) = sync_channel(30);
+        Self::new_internal(options, interrupt, Box::new(send), Some(Box::new(fallback)))
+    }
+
+    fn new_internal(
+        options: &ClientOptions,
+        interrupt: Interrupt,
+        send: Box<SendFn>,
+        fallback: Option<Box<FallbackFn>>,
+    ) -> Self {
+        let reports = ClientReports::new(options.send_client_reports);
+        let shedder = LoadShedder::new(options);
+        let rate_limit_store = options.rate_limit_store_path.clone();
+        let clear_rate_limits = Arc::new(AtomicBool::new(false));
+        let panics = Arc::new(Panics::default());
+        let rl = match rate_limit_store {
+            Some(ref path) => RateLimiter::load(path),
+            None => RateLimiter::new(),
+        }
+        .with_jitter(options.rate_limit_jitter)
+        .with_ramp_up(options.rate_limit_ramp_up)
+        .with_callback(options.on_rate_limit.clone());
+        let published_rl = Arc::new(Mutex::new(rl.clone()));
+        let worker = Worker {
+            send,
+            fallback,
+            breaker: CircuitBreaker::new(options),
+            shedder: shedder.clone(),
+            rl,
+            clear_rate_limits: clear_rate_limits.clone(),
+            published_rl: published_rl.clone(),
+            rate_limit_store: rate_limit_store.clone(),
+            max_envelope_size: options.max_envelope_size,
+            interrupt: interrupt.clone(),
+            reports: reports.clone(),
+            last_report: Instant::now(),
+            panics: panics.clone(),
+        };
+
+        let mode = if options.blocking_transport {
+            Mode::Blocking(Box::new(Mutex::new(worker)))
+        } else {
+            Self::spawn(options, worker)
+        };
+
+        Self {
+            mode,
+            shedder,
+            attachment_sample_rate: options.attachment_sample_rate,
+            max_attachments: options.max_attachments_per_envelope,
+            max_items: options.max_items_per_envelope,
+            closed: AtomicBool::new(false),
+            interrupt,
+            reports,
+            clear_rate_limits,
+            published_rl,
+            rate_limit_store,
+            panics,
+        }
+    }
+
+    fn spawn(options: &ClientOptions, mut worker: Worker) -> Mode {
+        let queue = Arc::new(TaskQueue::new(
+            options.transport_queue_size,
+            options.queue_overflow_policy,
+        ));
+        let queue_worker = queue.clone();
         let shutdown = Arc::new(AtomicBool::new(false));
         let shutdown_worker = shutdown.clone();
+        let batch_max_bytes = options.transport_batch_max_bytes;
+        let heartbeat_interval = options
+            .heartbeat_interval
+            .filter(|interval| !interval.is_zero());
+        let environment = options.environment.as_ref().map(|env| env.to_string());
         let handle = thread::Builder::new()
-            .name("sentry-transport".into())
+            .name(options.transport_thread_name.to_string())
             .spawn(move || {
-                let mut rl = RateLimiter::new();
-
-                for task in receiver.into_iter() {
+                let mut next_heartbeat =
+                    heartbeat_interval.map(|interval| Instant::now() + interval);
+                loop {
+                    // after an aborted send, leave the queued envelopes be
+                    if shutdown_worker.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    let task = match next_heartbeat {
+                        Some(deadline) => queue_worker.pop_until(deadline),
+                        None => Some(queue_worker.pop()),
+                    };
+                    let Some(task) = task else {
+                        next_heartbeat =
+                            heartbeat_interval.map(|interval| Instant::now() + interval);
+                        worker.send_heartbeat(environment.clone());
+                        continue;
+                    };
                     if shutdown_worker.load(Ordering::SeqCst) {
-                        return;
+                        if let Task::SendEnvelope(ref envelope) = task {
+                            worker
+                                .reports
+                                .record_envelope(DiscardReason::QueueOverflow, envelope);
+                        }
+                        break;
                     }
-                    let envelope = match task {
-                        Task::SendEnvelope(envelope) => envelope,
+                    match task {
+                        Task::SendEnvelope(envelope) => {
+                            if let Some(envelope) = worker.admit(envelope) {
+                                let envelope = match batch_max_bytes {
+                                    Some(max_bytes) => {
+                                        coalesce(envelope, &queue_worker, max_bytes, |next| {
+                                            worker.admit(next)
+                                        })
+                                    }
+                                    None => envelope,
+                                };
+                                worker.send(envelope).ok();
+                            }
+                        }
+                        Task::SendSync(envelope, sender) => {
+                            sender.send(worker.send_sync(envelope)).ok();
+                            continue;
+                        }
                         Task::Flush(sender) => {
+                            worker.interrupt.reset();
+                            worker.send_report();
                             sender.send(()).ok();
                             continue;
                         }
                         Task::Shutdown => {
-                            return;
-                        }
-                    };
-
-                    if let Some(time_left) = rl.is_disabled(RateLimitingCategory::Any) {
-                        sentry_debug!(
-                            "Skipping event send because we're disabled due to rate limits for {}s",
-                            time_left.as_secs()
-                        );
-                        continue;
-                    }
-                    match rl.filter_envelope(envelope) {
-                        Some(envelope) => {
-                            send(envelope, &mut rl);
-                        }
-                        None => {
-                            sentry_debug!("Envelope was discarded due to per-item rate limits");
+                            break;
                         }
                     };
+                    worker.maybe_send_report();
                 }
             })
             .ok();
 
-        Self {
-            sender,
+        Mode::Thread {
+            queue,
             shutdown,
-            handle,
+            handle: Mutex::new(handle),
+        }
+    }
+
+    /// Queues the envelope for sending, or sends it right away in blocking mode.
+    ///
+    /// Returns `false` if the envelope was dropped, either because the queue
+    /// was full and the policy is [`QueueOverflowPolicy::DropNewest`], or
+    /// because the thread was shut down.
+    pub fn send(&self, envelope: Envelope) -> bool {
+        if self.closed.load(Ordering::SeqCst) {
+            sentry_debug!("envelope dropped: transport is shut down");
+            return false;
+        }
+        let Some(envelope) = self.limit_items(envelope) else {
+            return true;
+        };
+        let queue = match self.mode {
+            Mode::Thread { ref queue, .. } => queue,
+            Mode::Blocking(ref worker) => {
+                worker.lock().unwrap().send_sync(envelope).ok();
+                return true;
+            }
+        };
+        let level = self.shedder.queue_level(queue.envelopes());
+        let Some(envelope) =
+            self.shedder
+                .shed(envelope, level, DiscardReason::QueueOverflow, &self.reports)
+        else {
+            return true;
+        };
+        // Blocking until there is room in the queue would mean that a slow
+        // Sentry could block everything. We'd rather drop an envelope in that case.
+        let (queued, dropped) = match queue.push_envelope(envelope) {
+            Ok(dropped) => (true, dropped),
+            Err(envelope) => (false, Some(envelope)),
+        };
+        if let Some(dropped) = dropped {
+            sentry_debug!("envelope dropped: transport queue is full");
+            self.reports
+                .record_envelope(DiscardReason::QueueOverflow, &dropped);
+        }
+        queued
+    }
+
+    /// Samples the attachments of `envelope` and drops those exceeding
+    /// [`ClientOptions::max_attachments_per_envelope`], then the items
+    /// exceeding [`ClientOptions::max_items_per_envelope`].
+    fn limit_items(&self, envelope: Envelope) -> Option<Envelope> {
+        let envelope = sample_attachments(envelope, self.attachment_sample_rate, &self.reports)?;
+        let envelope = match self.max_attachments {
+            Some(max) => cap_attachments(envelope, max, &self.reports)?,
+            None => envelope,
+        };
+        match self.max_items {
+            Some(max) => cap_items(envelope, max, &self.reports),
+            None => Some(envelope),
         }
     }
 
-    pub fn send(&self, envelope: Envelope) {
-        // Using send here would mean that when the channel fills up for whatever
-        // reason, trying to send an envelope would block everything. We'd rather
-        // drop the envelope in that case.
-        if let Err(e) = self.sender.try_send(Task::SendEnvelope(envelope)) {
-            sentry_debug!("envelope dropped: {e}");
+    /// Sends the envelope and waits until that is done, returning why it was
+    /// not accepted, if it wasn't.
+    ///
+    /// Unlike with [`TransportThread::send`], the envelope is never dropped
+    /// because the queue is full. It is sent once the envelopes queued before
+    /// it were, with the same rate limits and retries.
+    pub fn send_sync(&self, envelope: Envelope) -> SendResult {
+        let discarded = |message| Err(TransportError::new(TransportErrorKind::Discarded, message));
+        if self.closed.load(Ordering::SeqCst) {
+            return discarded("transport is shut down");
+        }
+        let Some(envelope) = self.limit_items(envelope) else {
+            return Ok(());
+        };
+        match self.mode {
+            Mode::Thread { ref queue, .. } => {
+                let (sender, receiver) = sync_channel(1);
+                queue.push(Task::SendSync(envelope, sender));
+                receiver
+                    .recv()
+                    .unwrap_or_else(|_| discarded("transport thread is gone"))
+            }
+            Mode::Blocking(ref worker) => worker.lock().unwrap().send_sync(envelope),
         }
     }
 
     pub fn flush(&self, timeout: Duration) -> bool {
-        let (sender, receiver) = sync_channel(1);
-        let _ = self.sender.send(Task::Flush(sender));
-        receiver.recv_timeout(timeout).is_ok()
+        match self.mode {
+            Mode::Thread { ref queue, .. } => {
+                let (sender, receiver) = sync_channel(1);
+                self.interrupt.trigger();
+                queue.push(Task::Flush(sender));
+                receiver.recv_timeout(timeout).is_ok()
+            }
+            Mode::Blocking(ref worker) => {
+                // everything else was sent already
+                worker.lock().unwrap().send_report();
+                true
+            }
+        }
+    }
+
+    /// Sends the queued envelopes carrying items of `category` ahead of the
+    /// other ones, returning `true` once they were sent before the `timeout`
+    /// elapsed.
+    ///
+    /// The other envelopes stay queued, to be sent as usual afterwards.
+    pub fn flush_category(&self, category: DataCategory, timeout: Duration) -> bool {
+        match self.mode {
+            Mode::Thread { ref queue, .. } => {
+                let (sender, receiver) = sync_channel(1);
+                self.interrupt.trigger();
+                queue.push_after_category(category, Task::Flush(sender));
+                receiver.recv_timeout(timeout).is_ok()
+            }
+            // everything was sent already
+            Mode::Blocking(_) => true,
+        }
+    }
+
+    /// Flushes the queue, returning the number of envelopes still queued if
+    /// the `timeout` elapsed first.
+    pub fn flush_remaining(&self, timeout: Duration) -> usize {
+        let drained = self.flush(timeout);
+        match self.mode {
+            Mode::Thread { ref queue, .. } if !drained => queue.envelopes(),
+            _ => 0,
+        }
+    }
+
+    /// Returns the number of sends which panicked so far.
+    pub fn panics(&self) -> usize {
+        self.panics.count.load(Ordering::SeqCst)
+    }
+
+    /// Returns the error of the most recent send which panicked, if any.
+    pub fn last_panic(&self) -> Option<TransportError> {
+        self.panics.last.lock().unwrap().clone()
+    }
+
+    /// Lifts all rate limits, starting with the next envelope sent.
+    pub fn clear_rate_limits(&self) {
+        self.clear_rate_limits.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns how long until items of `category` may be sent again, as of
+    /// the last envelope sent, or [`None`] if they may be sent now.
+    pub fn time_until_allowed(&self, category: DataCategory) -> Option<Duration> {
+        if self.clear_rate_limits.load(Ordering::SeqCst) {
+            return None;
+        }
+        self.published_rl
+            .lock()
+            .unwrap()
+            .time_until_allowed(category)
+    }
+
+    /// Stops accepting envelopes and sends the queued ones until the `timeout`
+    /// elapses, then stops the worker thread.
+    ///
+    /// Returns `false` if envelopes were left in the queue. In that case they
+    /// are dropped and recorded in the client reports, the [`Interrupt`]
+    /// aborts, so that the send function cancels an envelope still in flight,
+    /// and the thread is waited for a little longer. It exits once that send
+    /// is done, dropping the HTTP client and its connections. A send that ignores the
+    /// abort leaves the thread behind. The rate limits as of the last
+    /// envelope sent are persisted right away, as the process may well exit
+    /// before the thread does.
+    pub fn shutdown(&self, timeout: Duration) -> bool {
+        self.closed.store(true, Ordering::SeqCst);
+        let drained = self.flush(timeout);
+        let Mode::Thread {
+            ref handle,
+            ref queue,
+            ..
+        } = self.mode
+        else {
+            return drained;
+        };
+        self.stop();
+        if !drained {
+            if let Some(ref path) = self.rate_limit_store {
+                save_rate_limits(&self.published_rl.lock().unwrap(), path);
+            }
+            self.interrupt.abort();
+            let dropped = queue.drain_envelopes();
+            for envelope in &dropped {
+                self.reports
+                    .record_envelope(DiscardReason::QueueOverflow, envelope);
+            }
+            sentry_debug!(
+                "{} envelopes dropped: shutting down timed out",
+                dropped.len()
+            );
+        }
+        let handle = handle.lock().unwrap().take();
+        match handle {
+            Some(handle) if drained => handle.join().is_ok(),
+            Some(handle) => {
+                let deadline = Instant::now() + ABORT_GRACE_PERIOD;
+                while !handle.is_finished() && Instant::now() < deadline {
+                    thread::sleep(Duration::from_millis(1));
+                }
+                if handle.is_finished() {
+                    handle.join().ok();
+                }
+                false
+            }
+            None => drained,
+        }
+    }
+
+    /// Tells the worker thread to exit once it picks up its next task.
+    fn stop(&self) {
+        if let Mode::Thread {
+            ref queue,
+            ref shutdown,
+            ..
+        } = self.mode
+        {
+            shutdown.store(true, Ordering::SeqCst);
+            self.interrupt.trigger();
+            queue.push(Task::Shutdown);
+        }
     }
 }
 
 impl Drop for TransportThread {
     fn drop(&mut self) {
-        self.shutdown.store(true, Ordering::SeqCst);
-        let _ = self.sender.send(Task::Shutdown);
-        if let Some(handle) = self.handle.take() {
-            handle.join().unwrap();
+        self.stop();
+        if let Mode::Thread { ref mut handle, .. } = self.mode {
+            if let Some(handle) = handle.get_mut().unwrap().take() {
+                handle.join().unwrap();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::protocol::{Attachment, DiscardedEvent, Event, Transaction};
+    use crate::transports::check_in;
+
+    /// Returns the discarded events of the client report in `envelope`.
+    fn discarded(envelope: &Envelope) -> Vec<DiscardedEvent> {
+        let Some(EnvelopeItem::ClientReport(report)) = envelope.items().next() else {
+            panic!("expected a client report");
+        };
+        report.discarded_events.clone()
+    }
+
+    #[test]
+    fn test_sample_attachments() {
+        let reports = ClientReports::new(true);
+        let mut with_attachment = envelope("event");
+        with_attachment.add_item(Attachment {
+            buffer: vec![0; 10],
+            ..Default::default()
+        });
+
+        let sampled = sample_attachments(with_attachment.clone(), 1.0, &reports).unwrap();
+        assert_eq!(sampled.items().count(), 2);
+        assert!(reports.take_envelope().is_none());
+
+        let sampled = sample_attachments(with_attachment, 0.0, &reports).unwrap();
+        assert_eq!(sampled.event().unwrap().message.as_deref(), Some("event"));
+        assert_eq!(sampled.items().count(), 1);
+        assert_eq!(
+            discarded(&reports.take_envelope().unwrap()),
+            [DiscardedEvent {
+                reason: DiscardReason::SampleRate,
+                category: DataCategory::Attachment,
+                quantity: 10,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_cap_items() {
+        let reports = ClientReports::new(true);
+        let mut envelope = Envelope::new();
+        for index in 0..3 {
+            envelope.add_item(Attachment {
+                buffer: vec![0; 10],
+                filename: format!("{index}.txt"),
+                ..Default::default()
+            });
+        }
+        envelope.add_item(check_in("job"));
+        envelope.add_item(Event::default());
+
+        let capped = cap_items(envelope.clone(), 5, &reports).unwrap();
+        assert_eq!(capped.items().count(), 5);
+        assert!(reports.take_envelope().is_none());
+
+        // the attachments go first, the later ones before the earlier ones
+        let capped = cap_items(envelope, 3, &reports).unwrap();
+        let items: Vec<_> = capped
+            .items()
+            .map(|item| match item {
+                EnvelopeItem::Attachment(attachment) => attachment.filename.as_str(),
+                EnvelopeItem::MonitorCheckIn(_) => "check-in",
+                EnvelopeItem::Event(_) => "event",
+                _ => "other",
+            })
+            .collect();
+        assert_eq!(items, ["0.txt", "check-in", "event"]);
+        assert_eq!(
+            discarded(&reports.take_envelope().unwrap()),
+            [DiscardedEvent {
+                reason: DiscardReason::TooManyItems,
+                category: DataCategory::Attachment,
+                quantity: 20,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_limit_size() {
+        let reports = ClientReports::new(true);
+        let mut with_attachments = envelope("event");
+        for size in [10, 1000, 100] {
+            with_attachments.add_item(Attachment {
+                buffer: vec![0; size],
+                ..Default::default()
+            });
         }
+        let full_size = serialized_len(&with_attachments);
+
+        let limited = limit_size(with_attachments.clone(), full_size, &reports).unwrap();
+        assert_eq!(limited.items().count(), 4);
+        assert!(reports.take_envelope().is_none());
+
+        // dropping the largest attachment is enough
+        let limited = limit_size(with_attachments.clone(), full_size - 1, &reports).unwrap();
+        let sizes: Vec<_> = limited
+            .items()
+            .filter_map(|item| match item {
+                EnvelopeItem::Attachment(attachment) => Some(attachment.buffer.len()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(sizes, [10, 100]);
+        assert_eq!(limited.event().unwrap().message.as_deref(), Some("event"));
+        assert_eq!(
+            discarded(&reports.take_envelope().unwrap()),
+            [DiscardedEvent {
+                reason: DiscardReason::TooLarge,
+                category: DataCategory::Attachment,
+                quantity: 1000,
+            }]
+        );
+
+        // the event alone exceeds the limit
+        let event_only = with_attachments
+            .clone()
+            .filter(|item| !matches!(item, EnvelopeItem::Attachment(_)))
+            .unwrap();
+        let event_size = serialized_len(&event_only);
+        assert!(limit_size(with_attachments, event_size - 1, &reports).is_none());
+        assert_eq!(
+            discarded(&reports.take_envelope().unwrap()),
+            [
+                DiscardedEvent {
+                    reason: DiscardReason::TooLarge,
+                    category: DataCategory::Error,
+                    quantity: 1,
+                },
+                DiscardedEvent {
+                    reason: DiscardReason::TooLarge,
+                    category: DataCategory::Attachment,
+                    quantity: 1110,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_interrupt_sleep() {
+        let interrupt = Interrupt::default();
+        assert!(interrupt.sleep(Duration::from_millis(1)));
+
+        let trigger = interrupt.clone();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            trigger.trigger();
+        });
+        let start = Instant::now();
+        assert!(!interrupt.sleep(Duration::from_secs(10)));
+        assert!(start.elapsed() < Duration::from_secs(10));
+        handle.join().unwrap();
+
+        interrupt.reset();
+        assert!(interrupt.sleep(Duration::from_millis(1)));
+    }
+
+    fn message(task: Task) -> Option<String> {
+        match task {
+            Task::SendEnvelope(envelope) => envelope.event()?.message.clone(),
+            _ => None,
+        }
+    }
+
+    fn envelope(message: &str) -> Envelope {
+        Event {
+            message: Some(message.into()),
+            ..Default::default()
+        }
+        .into()
+    }
+
+    #[test]
+    fn test_coalesce() {
+        let check_in = |slug: &str| -> Envelope { check_in(slug).into() };
+
+        let queue = TaskQueue::new(10, QueueOverflowPolicy::DropNewest);
+        queue.push_envelope(check_in("second")).unwrap();
+        queue.push_envelope(envelope("third")).unwrap();
+        queue.push_envelope(envelope("fourth")).unwrap();
+
+        // the first event stops the merging, as the next one belongs to another event
+        let merged = coalesce(check_in("first"), &queue, usize::MAX, Some);
+        assert_eq!(merged.items().count(), 3);
+        assert_eq!(merged.event().unwrap().message.as_deref(), Some("third"));
+        assert_eq!(message(queue.pop()).as_deref(), Some("fourth"));
+
+        // attachments are not merged into the envelope of another event
+        let mut attachment = Envelope::new();
+        attachment.add_item(Attachment::default());
+        queue.push_envelope(check_in("second")).unwrap();
+        queue.push_envelope(attachment).unwrap();
+        let merged = coalesce(envelope("first"), &queue, usize::MAX, Some);
+        assert_eq!(merged.items().count(), 2);
+        assert!(matches!(
+            queue.try_pop_envelope().unwrap().items().next(),
+            Some(EnvelopeItem::Attachment(_))
+        ));
+
+        // the size limit is respected
+        let single = serialized_len(&check_in("first"));
+        queue.push_envelope(check_in("second")).unwrap();
+        queue.push_envelope(check_in("third")).unwrap();
+        let merged = coalesce(check_in("first"), &queue, single * 5 / 2, Some);
+        assert_eq!(merged.items().count(), 2);
+        assert_eq!(queue.try_pop_envelope().unwrap().items().count(), 1);
+        assert!(queue.try_pop_envelope().is_none());
+
+        // flushes are not skipped
+        queue.push(Task::Shutdown);
+        queue.push_envelope(check_in("second")).unwrap();
+        let merged = coalesce(check_in("first"), &queue, usize::MAX, Some);
+        assert_eq!(merged.items().count(), 1);
+        assert!(matches!(queue.pop(), Task::Shutdown));
+        assert!(queue.try_pop_envelope().is_some());
+
+        // envelopes that are not admitted are skipped before being measured
+        queue.push_envelope(check_in("second")).unwrap();
+        queue.push_envelope(envelope("third")).unwrap();
+        let mut rl = RateLimiter::new();
+        rl.update_from_sentry_header("60:error:key");
+        let mut discarded = 0;
+        let merged = coalesce(check_in("first"), &queue, single * 5 / 2, |next| {
+            rl.filter_envelope(next, |_| discarded += 1)
+        });
+        assert_eq!(merged.items().count(), 2);
+        assert_eq!(discarded, 1);
+        assert!(queue.try_pop_envelope().is_none());
+    }
+
+    #[test]
+    fn test_circuit_breaker() {
+        let reachable = Arc::new(AtomicBool::new(false));
+        let attempts = Arc::new(Mutex::new(0));
+        let held_back = Arc::new(Mutex::new(0));
+        let options = ClientOptions {
+            blocking_transport: true,
+            circuit_breaker_threshold: 2,
+            circuit_breaker_cooldown: Duration::from_millis(50),
+            ..Default::default()
+        };
+        let thread = TransportThread::with_fallback(
+            &options,
+            Interrupt::default(),
+            {
+                let (reachable, attempts) = (reachable.clone(), attempts.clone());
+                move |_envelope, _rl, _interrupt, _reports| {
+                    *attempts.lock().unwrap() += 1;
+                    if reachable.load(Ordering::SeqCst) {
+                        Ok(())
+                    } else {
+                        Err(TransportError::new(
+                            TransportErrorKind::Network,
+                            "unreachable",
+                        ))
+                    }
+                }
+            },
+            {
+                let held_back = held_back.clone();
+                move |_envelope, _reports| *held_back.lock().unwrap() += 1
+            },
+        );
+        let send = || thread.send(envelope("event"));
+        let counts = || (*attempts.lock().unwrap(), *held_back.lock().unwrap());
+
+        // two failures open the breaker
+        send();
+        send();
+        send();
+        assert_eq!(counts(), (2, 1));
+
+        // a failed probe opens it again right away
+        thread::sleep(Duration::from_millis(60));
+        send();
+        send();
+        assert_eq!(counts(), (3, 2));
+
+        // a successful probe closes it
+        reachable.store(true, Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(60));
+        send();
+        send();
+        assert_eq!(counts(), (5, 2));
+    }
+
+    #[test]
+    fn test_flush_category() {
+        let with_attachment = |message: &str| {
+            let mut envelope = envelope(message);
+            envelope.add_item(Attachment {
+                buffer: vec![0; 10],
+                filename: "crash.txt".into(),
+                ..Default::default()
+            });
+            envelope
+        };
+
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let (started, started_receiver) = sync_channel(0);
+        let (gate, gate_receiver) = sync_channel::<()>(0);
+        let options = ClientOptions {
+            send_client_reports: false,
+            ..Default::default()
+        };
+        let thread = TransportThread::new(&options, {
+            let sent = sent.clone();
+            move |envelope: Envelope,
+                  _rl: &mut RateLimiter,
+                  _interrupt: &Interrupt,
+                  _reports: &ClientReports| {
+                let message = message(Task::SendEnvelope(envelope)).unwrap();
+                if message == "blocking" {
+                    started.send(()).unwrap();
+                    gate_receiver.recv().unwrap();
+                }
+                sent.lock().unwrap().push(message);
+                Ok(())
+            }
+        });
+
+        // the other envelopes are queued while the first one is in flight
+        thread.send(envelope("blocking"));
+        started_receiver.recv().unwrap();
+        thread.send(envelope("first"));
+        thread.send(with_attachment("first crash"));
+        thread.send(envelope("second"));
+        thread.send(with_attachment("second crash"));
+        let opener = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            gate.send(()).unwrap();
+        });
+
+        assert!(thread.flush_category(DataCategory::Attachment, Duration::from_secs(5)));
+        let flushed = sent.lock().unwrap().clone();
+        assert!(flushed.starts_with(&["blocking", "first crash", "second crash"].map(String::from)));
+
+        // the events were left queued, and are sent afterwards
+        assert!(thread.flush(Duration::from_secs(5)));
+        assert_eq!(
+            *sent.lock().unwrap(),
+            ["blocking", "first crash", "second crash", "first", "second"]
+        );
+        opener.join().unwrap();
+    }
+
+    #[test]
+    fn test_panicking_send() {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let options = ClientOptions {
+            send_client_reports: false,
+            ..Default::default()
+        };
+        let thread = TransportThread::new(&options, {
+            let sent = sent.clone();
+            move |envelope: Envelope,
+                  _rl: &mut RateLimiter,
+                  _interrupt: &Interrupt,
+                  _reports: &ClientReports| {
+                let message = message(Task::SendEnvelope(envelope)).unwrap();
+                if message == "panic" {
+                    panic!("oh no");
+                }
+                sent.lock().unwrap().push(message);
+                Ok(())
+            }
+        });
+        assert_eq!(thread.last_panic(), None);
+
+        thread.send(envelope("panic"));
+        thread.send(envelope("event"));
+        assert!(thread.flush(Duration::from_secs(5)));
+
+        // the envelope after the panicking one is still sent
+        assert_eq!(*sent.lock().unwrap(), ["event"]);
+        assert_eq!(thread.panics(), 1);
+        let error = thread.last_panic().unwrap();
+        assert_eq!(error.kind, TransportErrorKind::Panicked);
+        assert!(error.message.contains("oh no"), "{}", error.message);
+
+        let error = thread.send_sync(envelope("panic")).unwrap_err();
+        assert_eq!(error.kind, TransportErrorKind::Panicked);
+        assert_eq!(thread.panics(), 2);
+    }
+
+    #[test]
+    fn test_shutdown_timeout_saves_rate_limits() {
+        let path = std::env::temp_dir().join(format!(
+            "sentry-shutdown-rate-limits-{}.txt",
+            std::process::id()
+        ));
+        std::fs::remove_file(&path).ok();
+        let options = ClientOptions {
+            rate_limit_store_path: Some(path.clone()),
+            ..Default::default()
+        };
+        // the second send is stuck until the end of the test
+        let (release, stuck) = std::sync::mpsc::channel::<()>();
+        let stuck = Mutex::new(stuck);
+        let thread = TransportThread::new(&options, {
+            move |envelope: Envelope,
+                  rl: &mut RateLimiter,
+                  _interrupt: &Interrupt,
+                  _reports: &ClientReports| {
+                if envelope.event().unwrap().message.as_deref() == Some("limited") {
+                    rl.update_from_sentry_header("120:transaction:key");
+                } else {
+                    stuck.lock().unwrap().recv().ok();
+                }
+                Ok(())
+            }
+        });
+        thread.send(envelope("limited"));
+        thread.send(envelope("stuck"));
+        assert!(!thread.shutdown(Duration::from_millis(100)));
+
+        // written before the worker thread got to exit
+        let loaded = RateLimiter::load(&path);
+        assert!(loaded
+            .is_disabled(RateLimitingCategory::Transaction)
+            .is_some());
+
+        release.send(()).unwrap();
+        drop(thread);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_shutdown_timeout_drops_queued() {
+        let exited = Arc::new(AtomicBool::new(false));
+        let thread = TransportThread::new(&ClientOptions::default(), {
+            let exited = exited.clone();
+            move |_envelope: Envelope,
+                  _rl: &mut RateLimiter,
+                  interrupt: &Interrupt,
+                  _reports: &ClientReports| {
+                while !interrupt.is_aborted() {
+                    interrupt.sleep(Duration::from_millis(10));
+                }
+                exited.store(true, Ordering::SeqCst);
+                Ok(())
+            }
+        });
+        thread.send(envelope("stuck"));
+        thread.send(envelope("queued"));
+        thread.send(envelope("queued"));
+        assert!(!thread.shutdown(Duration::from_millis(50)));
+        // the aborted send was waited for
+        assert!(exited.load(Ordering::SeqCst));
+
+        // the envelopes left in the queue are reported
+        assert_eq!(
+            discarded(&thread.reports.take_envelope().unwrap()),
+            [DiscardedEvent {
+                reason: DiscardReason::QueueOverflow,
+                category: DataCategory::Error,
+                quantity: 2,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_shutdown_sends_reports() {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let thread = TransportThread::new(&ClientOptions::default(), {
+            let sent = sent.clone();
+            move |envelope: Envelope,
+                  _rl: &mut RateLimiter,
+                  _interrupt: &Interrupt,
+                  reports: &ClientReports| {
+                reports.record_envelope(DiscardReason::NetworkError, &envelope);
+                sent.lock().unwrap().push(envelope);
+                Ok(())
+            }
+        });
+        thread.send(envelope("event"));
+        assert!(thread.shutdown(Duration::from_secs(5)));
+
+        // the discarded event is reported without waiting for the interval
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(
+            discarded(&sent[1]),
+            [DiscardedEvent {
+                reason: DiscardReason::NetworkError,
+                category: DataCategory::Error,
+                quantity: 1,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_max_attachments() {
+        let options = ClientOptions {
+            blocking_transport: true,
+            max_attachments_per_envelope: Some(3),
+            ..Default::default()
+        };
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let thread = TransportThread::new(&options, {
+            let sent = sent.clone();
+            move |envelope: Envelope,
+                  _rl: &mut RateLimiter,
+                  _interrupt: &Interrupt,
+                  _reports: &ClientReports| {
+                sent.lock().unwrap().push(envelope);
+                Ok(())
+            }
+        });
+
+        let mut with_attachments = envelope("event");
+        for index in 0..5 {
+            with_attachments.add_item(Attachment {
+                buffer: vec![0; 10],
+                filename: format!("{index}.txt"),
+                ..Default::default()
+            });
+        }
+        thread.send(with_attachments);
+
+        let sent = sent.lock().unwrap();
+        assert!(sent[0].event().is_some());
+        let filenames: Vec<_> = sent[0]
+            .items()
+            .filter_map(|item| match item {
+                EnvelopeItem::Attachment(attachment) => Some(attachment.filename.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(filenames, ["0.txt", "1.txt", "2.txt"]);
+        assert_eq!(
+            discarded(&thread.reports.take_envelope().unwrap()),
+            [DiscardedEvent {
+                reason: DiscardReason::TooManyItems,
+                category: DataCategory::Attachment,
+                quantity: 20,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_heartbeat() {
+        let options = ClientOptions {
+            heartbeat_interval: Some(Duration::from_millis(20)),
+            environment: Some("staging".into()),
+            send_client_reports: false,
+            ..Default::default()
+        };
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let thread = TransportThread::new(&options, {
+            let sent = sent.clone();
+            move |envelope: Envelope,
+                  rl: &mut RateLimiter,
+                  _interrupt: &Interrupt,
+                  _reports: &ClientReports| {
+                let mut sent = sent.lock().unwrap();
+                sent.push(envelope);
+                if sent.len() == 2 {
+                    rl.update_from_sentry_header("60:monitor:key");
+                }
+                Ok(())
+            }
+        });
+        thread::sleep(Duration::from_millis(150));
+
+        // the heartbeats stop once they are rate limited
+        let start = Instant::now();
+        assert!(thread.shutdown(Duration::from_secs(5)));
+        assert!(start.elapsed() < Duration::from_secs(1));
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent.len(), 2);
+        let Some(EnvelopeItem::MonitorCheckIn(check_in)) = sent[0].items().next() else {
+            panic!("expected a check-in");
+        };
+        assert_eq!(check_in.monitor_slug, HEARTBEAT_MONITOR_SLUG);
+        assert_eq!(check_in.environment.as_deref(), Some("staging"));
+    }
+
+    #[test]
+    fn test_load_shedding() {
+        let options = ClientOptions {
+            transport_queue_size: 10,
+            load_shedding_order: vec![DataCategory::Profile, DataCategory::Transaction],
+            ..Default::default()
+        };
+        let shedder = LoadShedder::new(&options);
+
+        // the high water mark is at 8 queued envelopes
+        assert_eq!(shedder.queue_level(7), 0);
+        assert_eq!(shedder.queue_level(8), 1);
+        assert_eq!(shedder.queue_level(9), 2);
+        assert_eq!(shedder.queue_level(10), 2);
+
+        // a rate limit drops the less valuable categories
+        let mut rl = RateLimiter::new();
+        assert_eq!(shedder.rate_limit_level(&rl), 0);
+        rl.update_from_sentry_header("60:transaction:key");
+        assert_eq!(shedder.rate_limit_level(&rl), 1);
+        rl.update_from_sentry_header("60:error:key");
+        assert_eq!(shedder.rate_limit_level(&rl), 2);
+
+        let mut mixed = envelope("event");
+        mixed.add_item(Transaction::default());
+        let reports = ClientReports::new(true);
+        let reason = DiscardReason::QueueOverflow;
+        let kept = shedder.shed(mixed.clone(), 1, reason, &reports).unwrap();
+        assert_eq!(kept.items().count(), 2);
+        let kept = shedder.shed(mixed, 2, reason, &reports).unwrap();
+        assert_eq!(kept.items().count(), 1);
+        assert!(kept.event().is_some());
+
+        assert_eq!(
+            discarded(&reports.take_envelope().unwrap()),
+            [DiscardedEvent {
+                reason,
+                category: DataCategory::Transaction,
+                quantity: 1,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_thread_name() {
+        let options = ClientOptions {
+            transport_thread_name: "custom-transport".into(),
+            ..Default::default()
+        };
+        let name = Arc::new(Mutex::new(None));
+        let thread = TransportThread::new(&options, {
+            let name = name.clone();
+            move |_envelope: Envelope,
+                  _rl: &mut RateLimiter,
+                  _interrupt: &Interrupt,
+                  _reports: &ClientReports| {
+                *name.lock().unwrap() = thread::current().name().map(str::to_owned);
+                Ok(())
+            }
+        });
+        thread.send(envelope("event"));
+        assert!(thread.flush(Duration::from_secs(5)));
+        assert_eq!(name.lock().unwrap().as_deref(), Some("custom-transport"));
+    }
+
+    #[test]
+    fn test_queue_drop_newest() {
+        let queue = TaskQueue::new(2, QueueOverflowPolicy::DropNewest);
+        assert!(queue.push_envelope(envelope("first")).unwrap().is_none());
+        assert!(queue.push_envelope(envelope("second")).unwrap().is_none());
+        let dropped = queue.push_envelope(envelope("third")).unwrap_err();
+        assert_eq!(dropped.event().unwrap().message.as_deref(), Some("third"));
+
+        // control tasks don't count towards the capacity
+        queue.push(Task::Shutdown);
+        assert_eq!(message(queue.pop()).as_deref(), Some("first"));
+        assert_eq!(message(queue.pop()).as_deref(), Some("second"));
+        assert!(matches!(queue.pop(), Task::Shutdown));
+    }
+
+    #[test]
+    fn test_queue_drop_oldest() {
+        let queue = TaskQueue::new(2, QueueOverflowPolicy::DropOldest);
+        queue.push_envelope(envelope("first")).unwrap();
+        queue.push_envelope(envelope("second")).unwrap();
+        let dropped = queue.push_envelope(envelope("third")).unwrap().unwrap();
+        assert_eq!(dropped.event().unwrap().message.as_deref(), Some("first"));
+
+        assert_eq!(message(queue.pop()).as_deref(), Some("second"));
+        assert_eq!(message(queue.pop()).as_deref(), Some("third"));
+
+        let queue = TaskQueue::new(0, QueueOverflowPolicy::DropOldest);
+        assert!(queue.push_envelope(envelope("first")).is_err());
     }
 }
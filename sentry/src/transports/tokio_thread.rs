@@ -1,11 +1,19 @@
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::mpsc::{sync_channel, SyncSender};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::thread::{self, JoinHandle};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use tokio::runtime::Handle;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::sync::Semaphore;
+
+use super::client_report::{ClientReports, CLIENT_REPORT_INTERVAL};
 use super::ratelimit::{RateLimiter, RateLimitingCategory};
-use crate::{sentry_debug, Envelope};
+use crate::protocol::{DataCategory, DiscardReason};
+use crate::{sentry_debug, ClientOptions, Envelope};
 
 enum Task {
     SendEnvelope(Envelope),
@@ -13,24 +21,39 @@ enum Task {
     Shutdown,
 }
 
+/// The state shared between the [`TransportThread`] and its worker.
+struct Shared {
+    shutdown: AtomicBool,
+    /// The number of envelopes waiting to be sent.
+    queued: AtomicUsize,
+    /// Set by [`TransportThread::clear_rate_limits`], for the next envelope to
+    /// lift the limits.
+    clear_rate_limits: AtomicBool,
+}
+
 pub struct TransportThread {
-    sender: SyncSender<Task>,
-    shutdown: Arc<AtomicBool>,
+    sender: UnboundedSender<Task>,
+    shared: Arc<Shared>,
+    rl: Arc<Mutex<RateLimiter>>,
+    capacity: usize,
     handle: Option<JoinHandle<()>>,
 }
 
 impl TransportThread {
-    pub fn new<SendFn, SendFuture>(mut send: SendFn) -> Self
+    /// Spawns the worker on a dedicated thread, running its own runtime.
+    ///
+    /// Up to [`ClientOptions::max_concurrent_sends`] futures returned by `send`
+    /// are run at the same time, sharing the rate limiter.
+    pub fn new<SendFn, SendFuture>(options: &ClientOptions, send: SendFn) -> Self
     where
-        SendFn: FnMut(Envelope, RateLimiter) -> SendFuture + Send + 'static,
-        // NOTE: returning RateLimiter here, otherwise we are in borrow hell
-        SendFuture: std::future::Future<Output = RateLimiter>,
+        SendFn: FnMut(Envelope, Arc<Mutex<RateLimiter>>) -> SendFuture + Send + 'static,
+        SendFuture: Future<Output = ()> + Send + 'static,
     {
-        let (sender, receiver) = sync_channel(30);
-        let shutdown = Arc::new(AtomicBool::new(false));
-        let shutdown_worker = shutdown.clone();
-        let handle = thread::Builder::new()
-            .name("sentry-transport".into())
+        let worker = Worker::new(options);
+        let (mut this, receiver) = Self::channel(options, worker.rl.clone());
+        let shared = this.shared.clone();
+        this.handle = thread::Builder::new()
+            .name(options.transport_thread_name.to_string())
             .spawn(move || {
                 // create a runtime on the transport thread
                 let rt = tokio::runtime::Builder::new_current_thread()
@@ -38,58 +61,63 @@ impl TransportThread {
                     .build()
                     .unwrap();
 
-                let mut rl = RateLimiter::new();
-
                 // and block on an async fn in this runtime/thread
-                rt.block_on(async move {
-                    for task in receiver.into_iter() {
-                        if shutdown_worker.load(Ordering::SeqCst) {
-                            return;
-                        }
-                        let envelope = match task {
-                            Task::SendEnvelope(envelope) => envelope,
-                            Task::Flush(sender) => {
-                                sender.send(()).ok();
-                                continue;
-                            }
-                            Task::Shutdown => {
-                                return;
-                            }
-                        };
-
-                        if let Some(time_left) =  rl.is_disabled(RateLimitingCategory::Any) {
-                            sentry_debug!(
-                                "Skipping event send because we're disabled due to rate limits for {}s",
-                                time_left.as_secs()
-                            );
-                            continue;
-                        }
-                        match rl.filter_envelope(envelope) {
-                            Some(envelope) => {
-                                rl = send(envelope, rl).await;
-                            },
-                            None => {
-                                sentry_debug!("Envelope was discarded due to per-item rate limits");
-                            },
-                        };
-                    }
-                })
+                rt.block_on(worker.work(receiver, shared, send))
             })
             .ok();
+        this
+    }
 
-        Self {
+    /// Spawns the worker as a task on an existing runtime.
+    pub fn spawn_on<SendFn, SendFuture>(
+        options: &ClientOptions,
+        runtime: &Handle,
+        send: SendFn,
+    ) -> Self
+    where
+        SendFn: FnMut(Envelope, Arc<Mutex<RateLimiter>>) -> SendFuture + Send + 'static,
+        SendFuture: Future<Output = ()> + Send + 'static,
+    {
+        let worker = Worker::new(options);
+        let (this, receiver) = Self::channel(options, worker.rl.clone());
+        let shared = this.shared.clone();
+        runtime.spawn(worker.work(receiver, shared, send));
+        this
+    }
+
+    fn channel(
+        options: &ClientOptions,
+        rl: Arc<Mutex<RateLimiter>>,
+    ) -> (Self, UnboundedReceiver<Task>) {
+        // The channel itself is unbounded, so that control tasks can always be
+        // queued without blocking. Envelopes are limited by `capacity` instead.
+        let (sender, receiver) = unbounded_channel();
+        let this = Self {
             sender,
-            shutdown,
-            handle,
-        }
+            shared: Arc::new(Shared {
+                shutdown: AtomicBool::new(false),
+                queued: AtomicUsize::new(0),
+                clear_rate_limits: AtomicBool::new(false),
+            }),
+            rl,
+            capacity: options.transport_queue_size,
+            handle: None,
+        };
+        (this, receiver)
     }
 
     pub fn send(&self, envelope: Envelope) {
-        // Using send here would mean that when the channel fills up for whatever
-        // reason, trying to send an envelope would block everything. We'd rather
-        // drop the envelope in that case.
-        if let Err(e) = self.sender.try_send(Task::SendEnvelope(envelope)) {
-            sentry_debug!("envelope dropped: {e}");
+        // Waiting for room in the queue would mean that when it fills up for
+        // whatever reason, trying to send an envelope would block everything.
+        // We'd rather drop the envelope in that case.
+        if self.shared.queued.fetch_add(1, Ordering::SeqCst) >= self.capacity {
+            self.shared.queued.fetch_sub(1, Ordering::SeqCst);
+            sentry_debug!("envelope dropped: queue is full");
+            return;
+        }
+        if self.sender.send(Task::SendEnvelope(envelope)).is_err() {
+            self.shared.queued.fetch_sub(1, Ordering::SeqCst);
+            sentry_debug!("envelope dropped: transport worker is gone");
         }
     }
 
@@ -98,14 +126,318 @@ impl TransportThread {
         let _ = self.sender.send(Task::Flush(sender));
         receiver.recv_timeout(timeout).is_ok()
     }
+
+    /// Flushes the queue, returning the number of envelopes still queued if
+    /// the `timeout` elapsed first.
+    pub fn flush_remaining(&self, timeout: Duration) -> usize {
+        if self.flush(timeout) {
+            0
+        } else {
+            self.shared.queued.load(Ordering::SeqCst)
+        }
+    }
+
+    /// Lifts all rate limits, starting with the next envelope sent.
+    pub fn clear_rate_limits(&self) {
+        self.shared.clear_rate_limits.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns how long until items of `category` may be sent again, or
+    /// [`None`] if they may be sent now.
+    pub fn time_until_allowed(&self, category: DataCategory) -> Option<Duration> {
+        if self.shared.clear_rate_limits.load(Ordering::SeqCst) {
+            return None;
+        }
+        self.rl.lock().unwrap().time_until_allowed(category)
+    }
+}
+
+/// Receives the tasks sent to the [`TransportThread`] and runs the sends.
+struct Worker {
+    rl: Arc<Mutex<RateLimiter>>,
+    rate_limit_store: Option<PathBuf>,
+    /// Limits the number of sends in flight.
+    permits: Arc<Semaphore>,
+    max_permits: u32,
+    reports: ClientReports,
+    last_report: Instant,
+}
+
+impl Worker {
+    fn new(options: &ClientOptions) -> Self {
+        let rate_limit_store = options.rate_limit_store_path.clone();
+        let rl = match rate_limit_store {
+            Some(ref path) => RateLimiter::load(path),
+            None => RateLimiter::new(),
+        }
+        .with_jitter(options.rate_limit_jitter)
+        .with_ramp_up(options.rate_limit_ramp_up)
+        .with_callback(options.on_rate_limit.clone());
+        let max_permits = options
+            .max_concurrent_sends
+            .clamp(1, Semaphore::MAX_PERMITS.min(u32::MAX as usize))
+            as u32;
+        Self {
+            rl: Arc::new(Mutex::new(rl)),
+            rate_limit_store,
+            permits: Arc::new(Semaphore::new(max_permits as usize)),
+            max_permits,
+            reports: ClientReports::new(options.send_client_reports),
+            last_report: Instant::now(),
+        }
+    }
+
+    /// Applies the rate limits to `envelope`, recording the items they drop in
+    /// the client reports.
+    fn admit(&self, envelope: Envelope, shared: &Shared) -> Option<Envelope> {
+        let mut rl = self.rl.lock().unwrap();
+        if shared.clear_rate_limits.swap(false, Ordering::SeqCst) {
+            rl.clear();
+        }
+        if let Some(time_left) = rl.is_disabled(RateLimitingCategory::Any) {
+            sentry_debug!(
+                "Skipping event send because we're disabled due to rate limits for {}s",
+                time_left.as_secs()
+            );
+            self.reports
+                .record_envelope(DiscardReason::RateLimitBackoff, &envelope);
+            return None;
+        }
+        let envelope = rl.filter_envelope(envelope, |item| {
+            self.reports
+                .record_item(DiscardReason::RateLimitBackoff, item)
+        });
+        if envelope.is_none() {
+            sentry_debug!("Envelope was discarded due to per-item rate limits");
+        }
+        envelope
+    }
+
+    /// Sends the client reports accumulated so far.
+    async fn send_report<SendFn, SendFuture>(&mut self, shared: &Shared, send: &mut SendFn)
+    where
+        SendFn: FnMut(Envelope, Arc<Mutex<RateLimiter>>) -> SendFuture,
+        SendFuture: Future<Output = ()> + Send + 'static,
+    {
+        self.last_report = Instant::now();
+        let Some(report) = self.reports.take_envelope() else {
+            return;
+        };
+        let Ok(permit) = self.permits.clone().acquire_owned().await else {
+            return;
+        };
+        if let Some(report) = self.admit(report, shared) {
+            let sending = send(report, self.rl.clone());
+            tokio::spawn(async move {
+                sending.await;
+                drop(permit);
+            });
+        }
+    }
+
+    /// Waits until all sends in flight are done.
+    async fn drain(&self) {
+        if let Ok(permits) = self.permits.acquire_many(self.max_permits).await {
+            drop(permits);
+        }
+    }
+
+    async fn work<SendFn, SendFuture>(
+        mut self,
+        mut receiver: UnboundedReceiver<Task>,
+        shared: Arc<Shared>,
+        mut send: SendFn,
+    ) where
+        SendFn: FnMut(Envelope, Arc<Mutex<RateLimiter>>) -> SendFuture,
+        SendFuture: Future<Output = ()> + Send + 'static,
+    {
+        while let Some(task) = receiver.recv().await {
+            if shared.shutdown.load(Ordering::SeqCst) {
+                if let Task::SendEnvelope(_) = task {
+                    shared.queued.fetch_sub(1, Ordering::SeqCst);
+                }
+                break;
+            }
+            let envelope = match task {
+                Task::SendEnvelope(envelope) => envelope,
+                Task::Flush(sender) => {
+                    self.send_report(&shared, &mut send).await;
+                    self.drain().await;
+                    sender.send(()).ok();
+                    continue;
+                }
+                Task::Shutdown => {
+                    break;
+                }
+            };
+
+            // wait for a free slot before applying the rate limits, so that the
+            // limits sent in by the responses to earlier envelopes are honored
+            let permit = self.permits.clone().acquire_owned().await;
+            // the envelope counts as queued until then
+            shared.queued.fetch_sub(1, Ordering::SeqCst);
+            let Ok(permit) = permit else {
+                break;
+            };
+            if let Some(envelope) = self.admit(envelope, &shared) {
+                let sending = send(envelope, self.rl.clone());
+                tokio::spawn(async move {
+                    sending.await;
+                    drop(permit);
+                });
+            } else {
+                drop(permit);
+            }
+            if self.last_report.elapsed() >= CLIENT_REPORT_INTERVAL {
+                self.send_report(&shared, &mut send).await;
+            }
+        }
+
+        self.send_report(&shared, &mut send).await;
+        self.drain().await;
+        if let Some(ref path) = self.rate_limit_store {
+            if let Err(err) = self.rl.lock().unwrap().save(path) {
+                sentry_debug!("Failed to persist rate limits: {}", err);
+            }
+        }
+    }
 }
 
 impl Drop for TransportThread {
     fn drop(&mut self) {
-        self.shutdown.store(true, Ordering::SeqCst);
+        self.shared.shutdown.store(true, Ordering::SeqCst);
         let _ = self.sender.send(Task::Shutdown);
+        // A worker running on an existing runtime is left to wind down on its own.
         if let Some(handle) = self.handle.take() {
             handle.join().unwrap();
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+    use std::time::Instant;
+
+    use super::*;
+    use crate::protocol::{DiscardedEvent, EnvelopeItem, Event};
+
+    #[test]
+    fn test_max_concurrent_sends() {
+        #[derive(Default)]
+        struct Counts {
+            in_flight: AtomicUsize,
+            max_in_flight: AtomicUsize,
+            sent: AtomicUsize,
+        }
+
+        let counts = Arc::new(Counts::default());
+        // holds back the sends until the test lets them finish
+        let gate = Arc::new(Semaphore::new(0));
+        let options = ClientOptions {
+            max_concurrent_sends: 3,
+            transport_queue_size: 50,
+            ..Default::default()
+        };
+        let thread = {
+            let counts = counts.clone();
+            let gate = gate.clone();
+            TransportThread::new(&options, move |_envelope, _rl| {
+                let counts = counts.clone();
+                let gate = gate.clone();
+                async move {
+                    let in_flight = counts.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    counts.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+                    gate.acquire().await.unwrap().forget();
+                    counts.in_flight.fetch_sub(1, Ordering::SeqCst);
+                    counts.sent.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+        };
+
+        for _ in 0..50 {
+            thread.send(Event::default().into());
+        }
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while counts.in_flight.load(Ordering::SeqCst) < 3 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(counts.in_flight.load(Ordering::SeqCst), 3);
+        assert!(!thread.flush(Duration::from_millis(10)));
+
+        gate.add_permits(50);
+        assert!(thread.flush(Duration::from_secs(5)));
+        assert_eq!(counts.sent.load(Ordering::SeqCst), 50);
+        assert_eq!(counts.max_in_flight.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_queued_until_sending() {
+        let sending = Arc::new(AtomicUsize::new(0));
+        let gate = Arc::new(Semaphore::new(0));
+        let options = ClientOptions {
+            max_concurrent_sends: 1,
+            ..Default::default()
+        };
+        let thread = {
+            let sending = sending.clone();
+            let gate = gate.clone();
+            TransportThread::new(&options, move |_envelope, _rl| {
+                let sending = sending.clone();
+                let gate = gate.clone();
+                async move {
+                    sending.fetch_add(1, Ordering::SeqCst);
+                    gate.acquire().await.unwrap().forget();
+                }
+            })
+        };
+
+        for _ in 0..3 {
+            thread.send(Event::default().into());
+        }
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while sending.load(Ordering::SeqCst) == 0 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        // the envelope waiting for the send in flight to finish is still queued
+        assert_eq!(thread.flush_remaining(Duration::from_millis(50)), 2);
+
+        gate.add_permits(3);
+        assert_eq!(thread.flush_remaining(Duration::from_secs(5)), 0);
+        assert_eq!(sending.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_rate_limits_reported() {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let thread = {
+            let sent = sent.clone();
+            TransportThread::new(&ClientOptions::default(), move |envelope, rl| {
+                rl.lock().unwrap().update_from_429(Some(&envelope));
+                sent.lock().unwrap().push(envelope);
+                async {}
+            })
+        };
+
+        thread.send(Event::default().into());
+        assert!(thread.flush(Duration::from_secs(5)));
+        thread.send(Event::default().into());
+        assert!(thread.flush(Duration::from_secs(5)));
+
+        // the rate limited event is reported on the flush
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent.len(), 2);
+        let Some(EnvelopeItem::ClientReport(report)) = sent[1].items().next() else {
+            panic!("expected a client report");
+        };
+        assert_eq!(
+            report.discarded_events,
+            [DiscardedEvent {
+                reason: DiscardReason::RateLimitBackoff,
+                category: DataCategory::Error,
+                quantity: 1,
+            }]
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use ureq::config::Config;
+use ureq::http::Uri;
+use ureq::unversioned::resolver::{ArrayVec, ResolvedSocketAddrs, Resolver};
+use ureq::unversioned::transport::{
+    Buffers, ConnectionDetails, Connector, LazyBuffers, NextTimeout, Transport,
+};
+use ureq::{Agent, Error};
+
+use super::abort::AbortConnector;
+use super::thread::Interrupt;
+
+/// Creates an [`Agent`] sending all its requests over the Unix domain socket
+/// at `path`, whatever their host, until the `interrupt` aborts.
+pub fn agent(config: Config, path: &Path, interrupt: &Interrupt) -> Agent {
+    let connector = UdsConnector {
+        path: path.to_owned(),
+    }
+    .chain(AbortConnector {
+        interrupt: interrupt.clone(),
+    });
+    Agent::with_parts(config, connector, UdsResolver)
+}
+
+/// Connects to the socket instead of the host of the request.
+#[derive(Debug)]
+struct UdsConnector {
+    path: PathBuf,
+}
+
+impl Connector for UdsConnector {
+    type Out = UdsTransport;
+
+    fn connect(
+        &self,
+        details: &ConnectionDetails,
+        _chained: Option<()>,
+    ) -> Result<Option<Self::Out>, Error> {
+        let stream = UnixStream::connect(&self.path)?;
+        let config = details.config;
+        Ok(Some(UdsTransport {
+            stream,
+            buffers: LazyBuffers::new(config.input_buffer_size(), config.output_buffer_size()),
+        }))
+    }
+}
+
+/// Skips resolving the host of the request, which is never connected to.
+#[derive(Debug)]
+struct UdsResolver;
+
+impl Resolver for UdsResolver {
+    fn resolve(
+        &self,
+        _uri: &Uri,
+        _config: &Config,
+        _timeout: NextTimeout,
+    ) -> Result<ResolvedSocketAddrs, Error> {
+        let unused = SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0));
+        let mut addrs = ArrayVec::from_fn(|_| unused);
+        addrs.push(unused);
+        Ok(addrs)
+    }
+}
+
+struct UdsTransport {
+    stream: UnixStream,
+    buffers: LazyBuffers,
+}
+
+impl fmt::Debug for UdsTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UdsTransport")
+            .field("addr", &self.stream.peer_addr().ok())
+            .finish()
+    }
+}
+
+/// Converts an I/O error into the error ureq expects, reporting timeouts as such.
+fn io_error(err: io::Error, timeout: NextTimeout) -> Error {
+    match err.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => Error::Timeout(timeout.reason),
+        _ => err.into(),
+    }
+}
+
+/// Returns the timeout to set on the socket, or [`None`] to block indefinitely.
+fn socket_timeout(timeout: NextTimeout) -> Option<Duration> {
+    timeout.not_zero().map(|timeout| *timeout)
+}
+
+impl Transport for UdsTransport {
+    fn buffers(&mut self) -> &mut dyn Buffers {
+        &mut self.buffers
+    }
+
+    fn transmit_output(&mut self, amount: usize, timeout: NextTimeout) -> Result<(), Error> {
+        self.stream.set_write_timeout(socket_timeout(timeout))?;
+        let output = &self.buffers.output()[..amount];
+        self.stream
+            .write_all(output)
+            .map_err(|err| io_error(err, timeout))
+    }
+
+    fn await_input(&mut self, timeout: NextTimeout) -> Result<bool, Error> {
+        self.stream.set_read_timeout(socket_timeout(timeout))?;
+        let input = self.buffers.input_append_buf();
+        let amount = self
+            .stream
+            .read(input)
+            .map_err(|err| io_error(err, timeout))?;
+        self.buffers.input_appended(amount);
+        Ok(amount > 0)
+    }
+
+    fn is_open(&mut self) -> bool {
+        // the connection is open as long as reading would block
+        if self.stream.set_nonblocking(true).is_err() {
+            return false;
+        }
+        let open = matches!(
+            self.stream.read(&mut [0]),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock
+        );
+        self.stream.set_nonblocking(false).is_ok() && open
+    }
+}
@@ -82,3 +82,149 @@ fn test_invalid_proxy() {
         ..Default::default()
     });
 }
+
+#[test]
+fn test_require_dsn() {
+    let options = sentry::ClientOptions {
+        require_dsn: true,
+        transport: Some(Arc::new(sentry::NoopTransport)),
+        ..Default::default()
+    };
+    assert_eq!(
+        sentry::Client::try_with_options(options.clone()).err(),
+        Some(sentry::DsnError::Missing)
+    );
+    let with_options = panic::AssertUnwindSafe(|| sentry::Client::with_options(options.clone()));
+    assert!(panic::catch_unwind(with_options).is_err());
+
+    let options = sentry::ClientOptions {
+        dsn: Some("https://public@example.com/1".parse().unwrap()),
+        endpoint_override: Some("not a url".into()),
+        ..options
+    };
+    assert!(matches!(
+        sentry::Client::try_with_options(options.clone()),
+        Err(sentry::DsnError::InvalidEndpointOverride(_))
+    ));
+
+    // without it, the client is merely disabled or ignores the override
+    let options = sentry::ClientOptions {
+        require_dsn: false,
+        ..options
+    };
+    assert!(sentry::Client::try_with_options(options)
+        .unwrap()
+        .is_enabled());
+    assert!(!sentry::Client::try_with_options(Default::default())
+        .unwrap()
+        .is_enabled());
+
+    let client = sentry::Client::try_with_options(sentry::ClientOptions {
+        dsn: Some("https://public@example.com/1".parse().unwrap()),
+        require_dsn: true,
+        transport: Some(Arc::new(sentry::NoopTransport)),
+        ..Default::default()
+    })
+    .unwrap();
+    assert!(client.is_enabled());
+}
+
+#[test]
+fn test_noop_transport() {
+    let options = sentry::ClientOptions {
+        dsn: Some("https://public@example.com/1".parse().unwrap()),
+        transport: Some(Arc::new(sentry::NoopTransport)),
+        ..Default::default()
+    };
+    let client: Arc<sentry::Client> = Arc::new(options.into());
+    assert!(client.is_enabled());
+
+    sentry::Hub::run(
+        Arc::new(sentry::Hub::new(Some(client.clone()), Default::default())),
+        || {
+            let event_id = sentry::capture_message("Hello World!", sentry::Level::Warning);
+            assert!(!event_id.is_nil());
+        },
+    );
+
+    assert!(client.flush(Some(std::time::Duration::from_secs(1))));
+    assert!(client.close(Some(std::time::Duration::from_secs(1))));
+}
+
+#[test]
+fn test_capture_check_in() {
+    use sentry::protocol::MonitorCheckInStatus;
+
+    let mut check_in_id = None;
+    let envelopes = sentry::test::with_captured_envelopes_options(
+        || {
+            check_in_id = Some(sentry::capture_check_in(
+                "nightly-backup",
+                MonitorCheckInStatus::InProgress,
+            ));
+        },
+        sentry::ClientOptions {
+            environment: Some("production".into()),
+            ..Default::default()
+        },
+    );
+    assert_eq!(envelopes.len(), 1);
+
+    let mut serialized = Vec::new();
+    envelopes[0].to_writer(&mut serialized).unwrap();
+    let serialized = String::from_utf8(serialized).unwrap();
+    let mut lines = serialized.lines();
+    assert_eq!(lines.next(), Some("{}"));
+    let payload = format!(
+        r#"{{"check_in_id":"{}","monitor_slug":"nightly-backup","status":"in_progress","environment":"production"}}"#,
+        check_in_id.unwrap().simple()
+    );
+    assert_eq!(
+        lines.next(),
+        Some(format!(r#"{{"type":"check_in","length":{}}}"#, payload.len()).as_str())
+    );
+    assert_eq!(lines.next(), Some(payload.as_str()));
+}
+
+#[test]
+fn test_close_uses_shutdown_timeout() {
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct TimeoutTransport(Mutex<Vec<Duration>>);
+
+    impl sentry::Transport for TimeoutTransport {
+        fn send_envelope(&self, _envelope: sentry::Envelope) {}
+
+        fn flush(&self, timeout: Duration) -> bool {
+            self.0.lock().unwrap().push(timeout);
+            true
+        }
+
+        fn shutdown(&self, timeout: Duration) -> bool {
+            self.0.lock().unwrap().push(timeout);
+            true
+        }
+    }
+
+    let transport = Arc::new(TimeoutTransport::default());
+    let client = sentry::Client::from(sentry::ClientOptions {
+        dsn: Some("https://public@example.com/1".parse().unwrap()),
+        transport: Some(Arc::new(transport.clone())),
+        shutdown_timeout: Duration::from_secs(7),
+        ..Default::default()
+    });
+
+    assert!(client.flush(None));
+    assert!(client.flush(Some(Duration::from_secs(1))));
+    assert!(client.close(None));
+    assert_eq!(
+        *transport.0.lock().unwrap(),
+        [
+            Duration::from_secs(7),
+            Duration::from_secs(1),
+            Duration::from_secs(7)
+        ]
+    );
+}